@@ -18,11 +18,11 @@ fn main() {
         println!("word 2: ");
         io::stdin().read_line(&mut word2).unwrap();
 
-        let embedding1 = embedding.get(&word1.trim());
-        let embedding2 = embedding.get(&word2.trim());
+        let embedding1 = embedding.get(word1.trim());
+        let embedding2 = embedding.get(word2.trim());
         match (embedding1, embedding2) {
             (Some(embedding1), Some(embedding2)) => {
-                println!("similarity: {}", embedding1.dot(&embedding2));
+                println!("similarity: {}", embedding1.dot(embedding2));
             }
             _ => println!("word not found"),
         }
@@ -2,6 +2,7 @@ use std::fs::read_to_string;
 
 use bamf::{
     activation::LELU,
+    group::ParameterGroup,
     language::{clean, Embedding},
     matrix::Matrix,
     vector::Vector,
@@ -19,7 +20,7 @@ fn main() {
     let dict_size = embedding.num_to_word.len();
     let nn_l1 = Layer::random((embedding.dim, embedding.dim), (0.0, 1.0)).with_activation(LELU);
 
-    // second (static) nn layer: embedding -> prediction
+    // second (pretrained) nn layer: embedding -> prediction
     let mut embeddings_arr: Vec<Vec<f64>> = vec![];
     for i in 0..dict_size {
         embeddings_arr.push(
@@ -32,11 +33,12 @@ fn main() {
         );
     }
     let embedding_to_prediction_matrix = Matrix::from(embeddings_arr).transpose();
-    let mut nn_l2 = Layer::new(embedding_to_prediction_matrix, Vector::zero(dict_size));
-    nn_l2.set_constant();
+    let nn_l2 = Layer::new(embedding_to_prediction_matrix, Vector::zero(dict_size));
 
-    // combine layers into nn
-    let mut nn = NeuralNetwork::new(vec![nn_l1, nn_l2]);
+    // combine layers into nn, fine-tuning the pretrained embeddings at a lower rate
+    let mut nn = NeuralNetwork::new(vec![nn_l1, nn_l2])
+        .with_group(ParameterGroup::new("embedding", &[1]).learning_rate_scale(0.1));
+    nn.freeze(1, Some("biases"));
 
     // process training data / input text
     let input_text = read_to_string("examples/your_name.txt").unwrap();
@@ -63,7 +65,7 @@ fn main() {
     }
 }
 
-fn max_index(vec: &Vec<f64>) -> usize {
+fn max_index(vec: &[f64]) -> usize {
     let mut max = 0.0;
     let mut max_index = 0;
     for (i, val) in vec.iter().enumerate() {
@@ -0,0 +1,84 @@
+use std::fs::read_to_string;
+
+use bamf::{
+    language::{clean, Embedding},
+    matrix::Matrix,
+    optimizer::Adam,
+    recurrent::Lstm,
+    vector::Vector,
+    Layer, NeuralNetwork,
+};
+
+/// Number of preceding words the LSTM reads to predict the next one.
+const CONTEXT: usize = 4;
+
+const BATCH_SIZE: usize = 32;
+
+fn main() {
+    let input_text = read_to_string("examples/your_name.txt").unwrap();
+    let embedding = Embedding::builder(input_text.clone())
+        .window(3)
+        .dim(32)
+        .train(500000);
+    let dict_size = embedding.num_to_word.len();
+
+    // lstm over the embeddings of the context -> prediction
+    let mut nn = NeuralNetwork::from_modules(vec![
+        Box::new(Lstm::new(embedding.dim, 64).last_only()),
+        Box::new(Layer::random((64, dict_size), (-0.1, 0.1))),
+    ])
+    .with_optimizer(Adam::new(0.005));
+
+    // process training data / input text
+    let words = clean(input_text)
+        .split_whitespace()
+        .map(|s| s.to_owned())
+        .collect::<Vec<String>>();
+    let context = |words: &[String]| {
+        let data = words
+            .iter()
+            .flat_map(|word| embedding.get(word).unwrap().data.clone())
+            .collect::<Vec<f64>>();
+        Vector::from(data)
+    };
+    let mut inputs = vec![];
+    let mut targets = vec![];
+    for i in CONTEXT..words.len() {
+        inputs.push(context(&words[i - CONTEXT..i]));
+        let mut target_one_hot = Vector::zero(dict_size);
+        target_one_hot.data[*embedding.word_to_num.get(&words[i]).unwrap()] = 1.0;
+        targets.push(target_one_hot);
+    }
+
+    // train nn (20 epochs)
+    for epoch in 0..20 {
+        let mut loss_sum = 0.0;
+        let batches = inputs.chunks(BATCH_SIZE).zip(targets.chunks(BATCH_SIZE));
+        for (input, target) in batches {
+            let (input, target) = (Matrix::from_rows(input), Matrix::from_rows(target));
+            loss_sum += nn.train_batch(&input, &target) * input.dims[0] as f64;
+        }
+        nn.end_epoch(None);
+        println!("epoch {}: loss {}", epoch, loss_sum / inputs.len() as f64);
+    }
+
+    // continue the beginning of the text word by word
+    let mut generated = words[..CONTEXT].to_vec();
+    for _ in 0..50 {
+        let output = nn.forward(context(&generated[generated.len() - CONTEXT..]));
+        generated.push(embedding.num_to_word[max_index(&output.data)].clone());
+    }
+    println!("{}", generated.join(" "));
+}
+
+fn max_index(vec: &[f64]) -> usize {
+    let mut max = 0.0;
+    let mut max_index = 0;
+    for (i, val) in vec.iter().enumerate() {
+        if *val > max {
+            max = *val;
+            max_index = i;
+        }
+    }
+    max_index
+}
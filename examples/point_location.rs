@@ -1,4 +1,8 @@
 use bamf::activation::RELU;
+use bamf::clipping::GradientClipping;
+use bamf::data::{Dataset, GeneratedDataset};
+use bamf::metrics::accuracy;
+use bamf::trainer::Trainer;
 use bamf::vector::Vector;
 use bamf::{Layer, NeuralNetwork};
 use rand::Rng;
@@ -15,35 +19,18 @@ fn run_axis_nn() -> f64 {
         Layer::random((2, 12), (0.0, 1.0)).with_activation(RELU),
         Layer::random((12, 12), (0.0, 1.0)).with_activation(RELU),
         Layer::random((12, 2), (0.0, 1.0)),
-    ]);
+    ])
+    .with_gradient_clipping(GradientClipping::GlobalNorm(1.0));
 
-    for _ in 0..100000 {
-        let point = random_point();
-        let near_axis = is_near_axis(point, RADIUS);
-        let input = Vector::from(vec![point.0, point.1]);
-        let target = if near_axis {
-            Vector::from(vec![1.0, 0.0])
-        } else {
-            Vector::from(vec![0.0, 1.0])
-        };
-        nn.train(input, &target);
-    }
+    let train = random_points(100000, |point| is_near_axis(point, RADIUS));
+    Trainer::new(1)
+        .batch_size(1)
+        .fit_dataset(&mut nn, &train, None);
 
-    let mut correct = 0;
-    let mut total = 0;
-    for _ in 0..1000 {
-        let point = random_point();
-        let near_axis = is_near_axis(point, RADIUS);
-        let input = Vector::from(vec![point.0, point.1]);
-        let result = nn.forward(input);
-        let predicted_in_circle = result.data[0] > result.data[1];
-        if predicted_in_circle == near_axis {
-            correct += 1;
-        }
-        total += 1;
-    }
-
-    correct as f64 / total as f64
+    let test = random_points(1000, |point| is_near_axis(point, RADIUS));
+    let (inputs, targets) = test.to_matrices();
+    let predictions = nn.forward_batch(&inputs).rows();
+    accuracy(&predictions, &targets.rows())
 }
 
 fn run_circle_nn() -> f64 {
@@ -53,35 +40,34 @@ fn run_circle_nn() -> f64 {
         Layer::random((2, 12), (0.0, 1.0)).with_activation(RELU),
         Layer::random((12, 12), (0.0, 1.0)).with_activation(RELU),
         Layer::random((12, 2), (0.0, 1.0)),
-    ]);
+    ])
+    .with_gradient_clipping(GradientClipping::GlobalNorm(1.0));
+
+    let train = random_points(100000, |point| is_in_circle(point, RADIUS));
+    Trainer::new(1)
+        .batch_size(1)
+        .fit_dataset(&mut nn, &train, None);
 
-    for _ in 0..100000 {
+    let test = random_points(1000, |point| is_in_circle(point, RADIUS));
+    let (inputs, targets) = test.to_matrices();
+    let predictions = nn.forward_batch(&inputs).rows();
+    accuracy(&predictions, &targets.rows())
+}
+
+/// Labels random points with one-hot targets, the first class if `label` holds.
+fn random_points(
+    len: usize,
+    label: fn((f64, f64)) -> bool,
+) -> GeneratedDataset<impl Fn(usize) -> (Vector, Vector)> {
+    GeneratedDataset::new(len, move |_| {
         let point = random_point();
-        let in_circle = is_in_circle(point, RADIUS);
-        let input = Vector::from(vec![point.0, point.1]);
-        let target = if in_circle {
-            Vector::from(vec![1.0, 0.0])
+        let target = if label(point) {
+            vec![1.0, 0.0]
         } else {
-            Vector::from(vec![0.0, 1.0])
+            vec![0.0, 1.0]
         };
-        nn.train(input, &target);
-    }
-
-    let mut correct = 0;
-    let mut total = 0;
-    for _ in 0..1000 {
-        let point = random_point();
-        let in_circle = is_in_circle(point, RADIUS);
-        let input = Vector::from(vec![point.0, point.1]);
-        let result = nn.forward(input);
-        let predicted_in_circle = result.data[0] > result.data[1];
-        if predicted_in_circle == in_circle {
-            correct += 1;
-        }
-        total += 1;
-    }
-
-    correct as f64 / total as f64
+        (Vector::from(vec![point.0, point.1]), Vector::from(target))
+    })
 }
 
 fn random_point() -> (f64, f64) {
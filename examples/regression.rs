@@ -0,0 +1,62 @@
+use bamf::activation::TANH;
+use bamf::loss::Huber;
+use bamf::matrix::Matrix;
+use bamf::optimizer::Adam;
+use bamf::trainer::Trainer;
+use bamf::vector::Vector;
+use bamf::{Layer, NeuralNetwork};
+use rand::Rng;
+use std::f64::consts::PI;
+
+fn main() {
+    println!("mse loss: {}", run_regression_nn(false));
+    println!("huber loss: {}", run_regression_nn(true));
+}
+
+/// Fits (sin(pi * x), cos(pi * x)) on [-1, 1] and returns the mean squared error on a test set.
+fn run_regression_nn(huber: bool) -> f64 {
+    let mut nn = NeuralNetwork::regression(vec![
+        Layer::random((1, 16), (-1.0, 1.0)).with_activation(TANH),
+        Layer::random((16, 16), (-0.5, 0.5)).with_activation(TANH),
+        Layer::random((16, 2), (-0.5, 0.5)),
+    ])
+    .with_optimizer(Adam::new(0.01));
+    if huber {
+        nn = nn.with_loss(Huber::new(0.1));
+    }
+
+    let (inputs, targets) = random_batch(2000);
+    let validation = random_batch(200);
+    let history = Trainer::new(60)
+        .batch_size(32)
+        .early_stopping(10)
+        .restore_best()
+        .fit(
+            &mut nn,
+            &inputs,
+            &targets,
+            Some((&validation.0, &validation.1)),
+        );
+    println!(
+        "best validation loss {} after {} epochs",
+        history.best().unwrap().monitored_loss(),
+        history.epochs.len()
+    );
+
+    let (inputs, targets) = random_batch(1000);
+    let output = nn.forward_batch(&inputs);
+    let diff = output.subtract(&targets);
+    diff.data.iter().map(|d| d * d).sum::<f64>() / diff.size as f64
+}
+
+fn random_batch(size: usize) -> (Matrix, Matrix) {
+    let mut rng = rand::thread_rng();
+    let mut inputs = vec![];
+    let mut targets = vec![];
+    for _ in 0..size {
+        let x = rng.gen::<f64>() * 2.0 - 1.0;
+        inputs.push(Vector::from(vec![x]));
+        targets.push(Vector::from(vec![(PI * x).sin(), (PI * x).cos()]));
+    }
+    (Matrix::from_rows(&inputs), Matrix::from_rows(&targets))
+}
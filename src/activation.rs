@@ -1,79 +1,709 @@
+use std::fmt::Debug;
+
+use crate::dual::{Dual, DualVector};
 use crate::vector::Vector;
 
-#[derive(Debug)]
-pub struct Activation {
-    pub function: fn(x: f64) -> f64,
-    pub derivative: fn(dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64,
-}
+pub trait Activation: Debug {
+    fn name(&self) -> &'static str;
+
+    fn forward(&self, x: f64) -> f64;
+
+    /// Computes the gradient of the loss wrt the input of the activation (y).
+    ///
+    /// # Arguments
+    ///
+    /// * `dl_dz` - gradient of loss wrt output of the activation (z)
+    /// * `y` - input to the activation
+    /// * `z` - output of the activation
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64;
+
+    /// Parameters of the activation, e.g. the slope of a leaky relu.
+    fn parameters(&self) -> &[f64] {
+        &[]
+    }
+
+    fn parameters_mut(&mut self) -> &mut [f64] {
+        &mut []
+    }
 
-impl Activation {
-    pub fn apply(&self, vec: Vector) -> Vector {
+    /// Whether the parameters should be updated during training.
+    fn learnable(&self) -> bool {
+        false
+    }
+
+    /// Computes the gradient of the loss wrt each of the parameters of the activation.
+    fn parameter_gradients(
+        &self,
+        _dl_dz: &Vector,
+        _y: Option<&Vector>,
+        _z: Option<&Vector>,
+    ) -> Vec<f64> {
+        vec![]
+    }
+
+    fn apply(&self, vec: Vector) -> Vector {
         vec.data
             .iter()
-            .map(|x| (self.function)(*x))
+            .map(|x| self.forward(*x))
             .collect::<Vec<f64>>()
             .into()
     }
 
-    pub fn backpropagate(&self, dl_dz: &Vector, y: Option<&Vector>, z: Option<&Vector>) -> Vector {
-        if let Some(y) = y {
-            dl_dz
-                .iter()
-                .zip(y.iter())
-                .map(|(i, j)| (self.derivative)(*i, Some(*j), None))
-                .collect::<Vec<f64>>()
-                .into()
-        } else if let Some(z) = z {
+    /// Applies the activation to a dual vector, propagating the tangent with the derivative.
+    fn apply_dual(&self, vec: DualVector) -> DualVector {
+        vec.map(|x| {
+            let z = self.forward(x.re);
+            Dual::new(z, self.backward(x.eps, Some(x.re), Some(z)))
+        })
+    }
+
+    fn backpropagate(&self, dl_dz: &Vector, y: Option<&Vector>, z: Option<&Vector>) -> Vector {
+        if y.is_none() && z.is_none() {
+            panic!("{}::backpropagate: y and z arguments are None", self.name())
+        }
+        (0..dl_dz.len())
+            .map(|i| self.backward(dl_dz[i], y.map(|y| y[i]), z.map(|z| z[i])))
+            .collect::<Vec<f64>>()
+            .into()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Relu;
+
+impl Activation for Relu {
+    fn name(&self) -> &'static str {
+        "relu"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        if x > 0.0 {
+            x
+        } else {
+            0.0
+        }
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        match z.or(y) {
+            Some(x) if x > 0.0 => dl_dz,
+            Some(_) => 0.0,
+            None => panic!("RELU::backwards: z argument is None"),
+        }
+    }
+}
+
+/// Relu with a fixed slope of `alpha` for negative inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct LeakyRelu {
+    pub alpha: f64,
+}
+
+impl LeakyRelu {
+    pub fn new(alpha: f64) -> LeakyRelu {
+        assert!(alpha.is_finite());
+        LeakyRelu { alpha }
+    }
+}
+
+impl Activation for LeakyRelu {
+    fn name(&self) -> &'static str {
+        "leaky_relu"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        if x > 0.0 {
+            x
+        } else {
+            x * self.alpha
+        }
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        // the sign of z is that of the input unless alpha is negative
+        let positive = match (y, z) {
+            (Some(y), _) => y > 0.0,
+            (None, Some(z)) if self.alpha >= 0.0 => z > 0.0,
+            (None, Some(_)) => panic!("LeakyRelu::backward: y argument is None with alpha < 0"),
+            (None, None) => panic!("LeakyRelu::backward: z argument is None"),
+        };
+        if positive {
             dl_dz
-                .iter()
-                .zip(z.iter())
-                .map(|(i, j)| (self.derivative)(*i, None, Some(*j)))
-                .collect::<Vec<f64>>()
-                .into()
         } else {
-            panic!()
+            dl_dz * self.alpha
         }
     }
+
+    fn parameters(&self) -> &[f64] {
+        std::slice::from_ref(&self.alpha)
+    }
+
+    fn parameters_mut(&mut self) -> &mut [f64] {
+        std::slice::from_mut(&mut self.alpha)
+    }
 }
 
-pub const RELU: Activation = Activation {
-    function: |x| {
+/// Leaky relu whose negative slope `alpha` is learned during training.
+#[derive(Debug, Clone, Copy)]
+pub struct PRelu {
+    pub alpha: f64,
+}
+
+impl PRelu {
+    /// Training can move `alpha` to any value, including zero or below, in which case backward
+    /// needs the input y of the activation.
+    pub fn new(alpha: f64) -> PRelu {
+        assert!(alpha.is_finite());
+        PRelu { alpha }
+    }
+
+    /// Recovers the input of the activation from either of y or z, which only determines it if
+    /// alpha is positive.
+    fn input(&self, y: Option<f64>, z: Option<f64>) -> f64 {
+        match (y, z) {
+            (Some(y), _) => y,
+            (None, Some(z)) if self.alpha > 0.0 && z > 0.0 => z,
+            (None, Some(z)) if self.alpha > 0.0 => z / self.alpha,
+            (None, Some(_)) => panic!("PRelu::backward: y argument is None with alpha <= 0"),
+            (None, None) => panic!("PRelu::backward: z argument is None"),
+        }
+    }
+}
+
+impl Activation for PRelu {
+    fn name(&self) -> &'static str {
+        "prelu"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
         if x > 0.0 {
             x
         } else {
-            0.0
+            x * self.alpha
         }
-    },
-    derivative: |dl_dz, _, z| {
-        if let Some(z) = z {
-            if z > 0.0 {
-                dl_dz
-            } else {
-                0.0
-            }
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        if self.input(y, z) > 0.0 {
+            dl_dz
         } else {
-            panic!("RELU::backwards: z argument is None")
+            dl_dz * self.alpha
+        }
+    }
+
+    fn parameters(&self) -> &[f64] {
+        std::slice::from_ref(&self.alpha)
+    }
+
+    fn parameters_mut(&mut self) -> &mut [f64] {
+        std::slice::from_mut(&mut self.alpha)
+    }
+
+    fn learnable(&self) -> bool {
+        true
+    }
+
+    fn parameter_gradients(
+        &self,
+        dl_dz: &Vector,
+        y: Option<&Vector>,
+        z: Option<&Vector>,
+    ) -> Vec<f64> {
+        let dl_dalpha = (0..dl_dz.len())
+            .map(|i| {
+                let x = self.input(y.map(|y| y[i]), z.map(|z| z[i]));
+                if x > 0.0 {
+                    0.0
+                } else {
+                    dl_dz[i] * x
+                }
+            })
+            .sum::<f64>();
+        vec![dl_dalpha]
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sigmoid;
+
+impl Activation for Sigmoid {
+    fn name(&self) -> &'static str {
+        "sigmoid"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        sigmoid(x)
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        let z = z.unwrap_or_else(|| sigmoid(expect(y, "SIGMOID", "y")));
+        dl_dz * z * (1.0 - z)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tanh;
+
+impl Activation for Tanh {
+    fn name(&self) -> &'static str {
+        "tanh"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        x.tanh()
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        let z = z.unwrap_or_else(|| expect(y, "TANH", "y").tanh());
+        dl_dz * (1.0 - z * z)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Softplus;
+
+impl Activation for Softplus {
+    fn name(&self) -> &'static str {
+        "softplus"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        softplus(x)
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        match (y, z) {
+            (Some(y), _) => dl_dz * sigmoid(y),
+            // sigmoid(y) = 1 - exp(-softplus(y))
+            (None, Some(z)) => -dl_dz * (-z).exp_m1(),
+            (None, None) => panic!("SOFTPLUS::backwards: y argument is None"),
         }
-    },
-};
+    }
+}
 
-pub const LELU: Activation = Activation {
-    function: |x| {
+/// Exponential linear unit, saturating to `-alpha` for negative inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct Elu {
+    pub alpha: f64,
+}
+
+impl Elu {
+    pub fn new(alpha: f64) -> Elu {
+        assert!(alpha > 0.0);
+        Elu { alpha }
+    }
+}
+
+impl Activation for Elu {
+    fn name(&self) -> &'static str {
+        "elu"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
         if x > 0.0 {
             x
         } else {
-            x / 10.0
-        }
-    },
-    derivative: |dl_dz, _, z| {
-        if let Some(z) = z {
-            if z > 0.0 {
-                dl_dz
-            } else {
-                dl_dz / 10.0
-            }
+            self.alpha * x.exp_m1()
+        }
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        match (z, y) {
+            (Some(z), _) if z > 0.0 => dl_dz,
+            (Some(z), _) => dl_dz * (z + self.alpha),
+            (None, Some(y)) if y > 0.0 => dl_dz,
+            (None, Some(y)) => dl_dz * self.alpha * y.exp(),
+            (None, None) => panic!("ELU::backwards: z argument is None"),
+        }
+    }
+
+    fn parameters(&self) -> &[f64] {
+        std::slice::from_ref(&self.alpha)
+    }
+
+    fn parameters_mut(&mut self) -> &mut [f64] {
+        std::slice::from_mut(&mut self.alpha)
+    }
+}
+
+/// Scaled exponential linear unit with the self-normalizing constants from Klambauer et al.
+#[derive(Debug, Clone, Copy)]
+pub struct Selu;
+
+impl Selu {
+    pub const ALPHA: f64 = 1.673_263_242_354_377_3;
+    pub const SCALE: f64 = 1.050_700_987_355_480_5;
+}
+
+impl Activation for Selu {
+    fn name(&self) -> &'static str {
+        "selu"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        if x > 0.0 {
+            Selu::SCALE * x
+        } else {
+            Selu::SCALE * Selu::ALPHA * x.exp_m1()
+        }
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        match (z, y) {
+            (Some(z), _) if z > 0.0 => dl_dz * Selu::SCALE,
+            (Some(z), _) => dl_dz * (z + Selu::SCALE * Selu::ALPHA),
+            (None, Some(y)) if y > 0.0 => dl_dz * Selu::SCALE,
+            (None, Some(y)) => dl_dz * Selu::SCALE * Selu::ALPHA * y.exp(),
+            (None, None) => panic!("SELU::backwards: z argument is None"),
+        }
+    }
+}
+
+/// Gaussian error linear unit `x * Φ(x)`, using the exact normal cdf.
+#[derive(Debug, Clone, Copy)]
+pub struct Gelu;
+
+impl Activation for Gelu {
+    fn name(&self) -> &'static str {
+        "gelu"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        x * normal_cdf(x)
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, _z: Option<f64>) -> f64 {
+        let y = expect(y, "GELU", "y");
+        let pdf = (-0.5 * y * y).exp() / (2.0 * std::f64::consts::PI).sqrt();
+        dl_dz * (normal_cdf(y) + y * pdf)
+    }
+}
+
+/// Gaussian error linear unit with the tanh approximation of the normal cdf.
+#[derive(Debug, Clone, Copy)]
+pub struct GeluTanh;
+
+impl GeluTanh {
+    const K: f64 = 0.797_884_560_802_865_4; // sqrt(2 / pi)
+    const C: f64 = 0.044_715;
+}
+
+impl Activation for GeluTanh {
+    fn name(&self) -> &'static str {
+        "gelu_tanh"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        0.5 * x * (1.0 + (GeluTanh::K * (x + GeluTanh::C * x * x * x)).tanh())
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, _z: Option<f64>) -> f64 {
+        let y = expect(y, "GELU_TANH", "y");
+        let t = (GeluTanh::K * (y + GeluTanh::C * y * y * y)).tanh();
+        let dt_dy = (1.0 - t * t) * GeluTanh::K * (1.0 + 3.0 * GeluTanh::C * y * y);
+        dl_dz * (0.5 * (1.0 + t) + 0.5 * y * dt_dy)
+    }
+}
+
+/// Swish `x * sigmoid(beta * x)`, which is SiLU for `beta = 1`.
+#[derive(Debug, Clone, Copy)]
+pub struct Swish {
+    pub beta: f64,
+}
+
+impl Swish {
+    pub fn new(beta: f64) -> Swish {
+        assert!(beta.is_finite());
+        Swish { beta }
+    }
+}
+
+impl Activation for Swish {
+    fn name(&self) -> &'static str {
+        "swish"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        x * sigmoid(self.beta * x)
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        let y = expect(y, "SWISH", "y");
+        let s = sigmoid(self.beta * y);
+        let z = z.unwrap_or(y * s);
+        dl_dz * (self.beta * z + s * (1.0 - self.beta * z))
+    }
+
+    fn parameters(&self) -> &[f64] {
+        std::slice::from_ref(&self.beta)
+    }
+
+    fn parameters_mut(&mut self) -> &mut [f64] {
+        std::slice::from_mut(&mut self.beta)
+    }
+}
+
+/// Mish `x * tanh(softplus(x))`.
+#[derive(Debug, Clone, Copy)]
+pub struct Mish;
+
+impl Activation for Mish {
+    fn name(&self) -> &'static str {
+        "mish"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        x * softplus(x).tanh()
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, _z: Option<f64>) -> f64 {
+        let y = expect(y, "MISH", "y");
+        let t = softplus(y).tanh();
+        dl_dz * (t + y * (1.0 - t * t) * sigmoid(y))
+    }
+}
+
+/// Piecewise linear approximation of the sigmoid, `clamp(x / 6 + 1 / 2, 0, 1)`.
+#[derive(Debug, Clone, Copy)]
+pub struct HardSigmoid;
+
+impl Activation for HardSigmoid {
+    fn name(&self) -> &'static str {
+        "hard_sigmoid"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        (x / 6.0 + 0.5).clamp(0.0, 1.0)
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        let z = z.unwrap_or_else(|| self.forward(expect(y, "HARD_SIGMOID", "y")));
+        if z > 0.0 && z < 1.0 {
+            dl_dz / 6.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Piecewise linear approximation of tanh, `clamp(x, -1, 1)`.
+#[derive(Debug, Clone, Copy)]
+pub struct HardTanh;
+
+impl Activation for HardTanh {
+    fn name(&self) -> &'static str {
+        "hard_tanh"
+    }
+
+    fn forward(&self, x: f64) -> f64 {
+        x.clamp(-1.0, 1.0)
+    }
+
+    fn backward(&self, dl_dz: f64, y: Option<f64>, z: Option<f64>) -> f64 {
+        let z = z.unwrap_or_else(|| self.forward(expect(y, "HARD_TANH", "y")));
+        if z > -1.0 && z < 1.0 {
+            dl_dz
         } else {
-            panic!("LELU::backwards: z argument is None")
+            0.0
+        }
+    }
+}
+
+fn expect(arg: Option<f64>, activation: &str, name: &str) -> f64 {
+    arg.unwrap_or_else(|| panic!("{}::backwards: {} argument is None", activation, name))
+}
+
+fn sigmoid(x: f64) -> f64 {
+    if x >= 0.0 {
+        1.0 / (1.0 + (-x).exp())
+    } else {
+        let exp = x.exp();
+        exp / (1.0 + exp)
+    }
+}
+
+fn softplus(x: f64) -> f64 {
+    x.max(0.0) + (-x.abs()).exp().ln_1p()
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+/// Error function, using its Taylor series near zero and the continued fraction of erfc in the
+/// tails.
+fn erf(x: f64) -> f64 {
+    if x.abs() < 2.5 {
+        let mut term = x;
+        let mut sum = x;
+        for n in 1..100 {
+            term *= -x * x / n as f64;
+            let next = term / (2 * n + 1) as f64;
+            sum += next;
+            if next.abs() < 1e-17 * sum.abs() {
+                break;
+            }
+        }
+        sum * std::f64::consts::FRAC_2_SQRT_PI
+    } else {
+        let a = x.abs();
+        let mut fraction = a;
+        for k in (1..60).rev() {
+            fraction = a + (k as f64 / 2.0) / fraction;
+        }
+        let erfc = (-a * a).exp() / (fraction * std::f64::consts::PI.sqrt());
+        (1.0 - erfc).copysign(x)
+    }
+}
+
+pub const RELU: Relu = Relu;
+
+pub const LELU: LeakyRelu = LeakyRelu { alpha: 0.1 };
+
+pub const SIGMOID: Sigmoid = Sigmoid;
+
+pub const TANH: Tanh = Tanh;
+
+pub const SOFTPLUS: Softplus = Softplus;
+
+pub const ELU: Elu = Elu { alpha: 1.0 };
+
+pub const SELU: Selu = Selu;
+
+pub const GELU: Gelu = Gelu;
+
+pub const GELU_TANH: GeluTanh = GeluTanh;
+
+pub const SILU: Swish = Swish { beta: 1.0 };
+
+pub const MISH: Mish = Mish;
+
+pub const HARD_SIGMOID: HardSigmoid = HardSigmoid;
+
+pub const HARD_TANH: HardTanh = HardTanh;
+
+/// Creates an activation from its name and `parameters`, e.g. the slope of a leaky relu.
+pub fn from_name(name: &str, parameters: &[f64]) -> Option<Box<dyn Activation>> {
+    let activation: Box<dyn Activation> = match (name, parameters) {
+        ("relu", []) => Box::new(RELU),
+        ("leaky_relu", [alpha]) if alpha.is_finite() => Box::new(LeakyRelu::new(*alpha)),
+        ("prelu", [alpha]) if alpha.is_finite() => Box::new(PRelu::new(*alpha)),
+        ("sigmoid", []) => Box::new(SIGMOID),
+        ("tanh", []) => Box::new(TANH),
+        ("softplus", []) => Box::new(SOFTPLUS),
+        ("elu", [alpha]) if *alpha > 0.0 => Box::new(Elu::new(*alpha)),
+        ("selu", []) => Box::new(SELU),
+        ("gelu", []) => Box::new(GELU),
+        ("gelu_tanh", []) => Box::new(GELU_TANH),
+        ("swish", [beta]) if beta.is_finite() => Box::new(Swish::new(*beta)),
+        ("mish", []) => Box::new(MISH),
+        ("hard_sigmoid", []) => Box::new(HARD_SIGMOID),
+        ("hard_tanh", []) => Box::new(HARD_TANH),
+        _ => return None,
+    };
+    Some(activation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 0.00001;
+
+    fn activations() -> Vec<Box<dyn Activation>> {
+        vec![
+            Box::new(RELU),
+            Box::new(LELU),
+            Box::new(PRelu::new(0.25)),
+            Box::new(LeakyRelu::new(0.0)),
+            Box::new(SIGMOID),
+            Box::new(TANH),
+            Box::new(SOFTPLUS),
+            Box::new(ELU),
+            Box::new(SELU),
+            Box::new(GELU),
+            Box::new(GELU_TANH),
+            Box::new(SILU),
+            Box::new(Swish::new(1.5)),
+            Box::new(MISH),
+            Box::new(HARD_SIGMOID),
+            Box::new(HARD_TANH),
+        ]
+    }
+
+    #[test]
+    fn test_erf() {
+        assert!((erf(0.5) - 0.520_499_877_813_046_5).abs() < 1e-14);
+        assert!((erf(-1.5) + 0.966_105_146_475_310_7).abs() < 1e-14);
+        assert!((erf(3.0) - 0.999_977_909_503_001_4).abs() < 1e-14);
+    }
+
+    #[test]
+    fn test_derivatives() {
+        let step = 1e-6;
+        for activation in activations() {
+            for x in [-4.2, -2.5, -0.7, -0.1, 0.3, 1.1, 2.9, 5.0] {
+                let numeric =
+                    (activation.forward(x + step) - activation.forward(x - step)) / (2.0 * step);
+                let z = activation.forward(x);
+                let both = activation.backward(1.0, Some(x), Some(z));
+                assert!(
+                    (both - numeric).abs() < EPSILON,
+                    "{} at {}: {} != {}",
+                    activation.name(),
+                    x,
+                    both,
+                    numeric
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_derivatives_from_y_or_z() {
+        let needs_y = [GELU.name(), GELU_TANH.name(), SILU.name(), MISH.name()];
+        for activation in activations() {
+            for x in [-2.5, -0.7, 0.3, 1.1] {
+                let z = activation.forward(x);
+                let both = activation.backward(0.7, Some(x), Some(z));
+                let from_y = activation.backward(0.7, Some(x), None);
+                assert!((from_y - both).abs() < EPSILON, "{}", activation.name());
+                if !needs_y.contains(&activation.name()) {
+                    let from_z = activation.backward(0.7, None, Some(z));
+                    assert!((from_z - both).abs() < EPSILON, "{}", activation.name());
+                }
+            }
         }
-    },
-};
+    }
+
+    #[test]
+    fn test_non_positive_slopes() {
+        for alpha in [0.0, -0.5] {
+            let activations: [Box<dyn Activation>; 2] =
+                [Box::new(LeakyRelu::new(alpha)), Box::new(PRelu::new(alpha))];
+            for activation in activations {
+                for x in [-2.5, -0.7, 0.3, 1.1] {
+                    let expected = if x > 0.0 { 0.7 } else { 0.7 * alpha };
+                    let z = activation.forward(x);
+                    assert_eq!(activation.backward(0.7, Some(x), Some(z)), expected);
+                }
+            }
+            let prelu = PRelu::new(alpha);
+            let dl_dz = Vector::from(vec![1.0, 2.0]);
+            let y = Vector::from(vec![-0.5, 1.5]);
+            let z = prelu.apply(y.clone());
+            assert_eq!(
+                prelu.parameter_gradients(&dl_dz, Some(&y), Some(&z)),
+                vec![-0.5]
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_finite_parameters() {
+        for name in ["leaky_relu", "prelu", "swish"] {
+            assert!(from_name(name, &[f64::NAN]).is_none());
+            assert!(from_name(name, &[f64::INFINITY]).is_none());
+        }
+    }
+}
@@ -0,0 +1,633 @@
+use crate::activation::GELU;
+use crate::matrix::{Matrix, Multiply};
+use crate::module::{Module, Parameter};
+use crate::normalization::LayerNorm;
+use crate::Layer;
+
+fn reshape(matrix: &Matrix, dims: (usize, usize)) -> Matrix {
+    Matrix::from_vector(matrix.to_vector(), dims)
+}
+
+/// Applies a module to every step of sequences stored as rows, one step of `input_size` values
+/// after the other, e.g. a dense `Layer` to every token.
+#[derive(Debug)]
+pub struct TimeDistributed<M: Module> {
+    pub module: M,
+    pub input_size: usize,
+    /// number of steps of the last forward pass
+    steps: usize,
+}
+
+impl<M: Module> TimeDistributed<M> {
+    pub fn new(module: M, input_size: usize) -> TimeDistributed<M> {
+        TimeDistributed {
+            module,
+            input_size,
+            steps: 0,
+        }
+    }
+}
+
+impl<M: Module> Module for TimeDistributed<M> {
+    fn name(&self) -> &'static str {
+        "time_distributed"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        assert!(input.dims[1].is_multiple_of(self.input_size));
+        let (rows, steps) = (input.dims[0], input.dims[1] / self.input_size);
+        self.steps = steps;
+        let output = self
+            .module
+            .forward_batch(&reshape(input, (rows * steps, self.input_size)));
+        reshape(&output, (rows, steps * output.dims[1]))
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let (rows, steps) = (dl_dz.dims[0], self.steps);
+        let dl_dx = self
+            .module
+            .backward_batch(&reshape(dl_dz, (rows * steps, dl_dz.dims[1] / steps)));
+        reshape(&dl_dx, (rows, steps * self.input_size))
+    }
+
+    fn penalty(&self) -> f64 {
+        self.module.penalty()
+    }
+
+    fn apply_constraints(&mut self) {
+        self.module.apply_constraints();
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        self.module.parameters()
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        self.module.gradients()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        self.module.parameters_mut()
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.module.set_training(training);
+    }
+
+    fn reset_state(&mut self) {
+        self.module.reset_state();
+    }
+
+    fn set_sequence_lengths(&mut self, lengths: Option<&[usize]>) {
+        self.module.set_sequence_lengths(lengths);
+    }
+}
+
+/// Multi-head scaled dot-product self-attention over sequences of `d_model` wide tokens stored as
+/// rows.
+///
+/// Tokens can be kept from attending to later tokens (causal mask) and to the padding after the
+/// length of every sequence set by `set_sequence_lengths` (padding mask).
+#[derive(Debug)]
+pub struct MultiHeadAttention {
+    pub d_model: usize,
+    pub heads: usize,
+    pub causal: bool,
+    query: Layer,
+    key: Layer,
+    value: Layer,
+    output: Layer,
+    lengths: Option<Vec<usize>>,
+    /// (queries, keys, values, attention weights of every sample and head) of the last forward
+    /// pass
+    cache: Option<(Matrix, Matrix, Matrix, Vec<Matrix>)>,
+}
+
+impl MultiHeadAttention {
+    pub fn new(d_model: usize, heads: usize) -> MultiHeadAttention {
+        assert!(d_model.is_multiple_of(heads));
+        let bound = 1.0 / (d_model as f64).sqrt();
+        let projection = || Layer::random((d_model, d_model), (-bound, bound));
+        MultiHeadAttention {
+            d_model,
+            heads,
+            causal: false,
+            query: projection(),
+            key: projection(),
+            value: projection(),
+            output: projection(),
+            lengths: None,
+            cache: None,
+        }
+    }
+
+    /// Keeps every token from attending to later tokens.
+    pub fn causal(mut self) -> Self {
+        self.causal = true;
+        self
+    }
+
+    fn layers(&self) -> [&Layer; 4] {
+        [&self.query, &self.key, &self.value, &self.output]
+    }
+
+    /// Computes the attention weights of a head from its queries and keys, with one row per query.
+    fn attend(&self, sample: usize, queries: &Matrix, keys: &Matrix) -> Matrix {
+        let scale = 1.0 / ((self.d_model / self.heads) as f64).sqrt();
+        let scores = queries.multiply(&keys.transpose());
+        let length = self.lengths.as_ref().map_or(keys.dims[0], |l| l[sample]);
+        assert!(length > 0, "MultiHeadAttention: empty sequence");
+        assert!(
+            length <= keys.dims[0],
+            "MultiHeadAttention: sequence length {} exceeds {} steps",
+            length,
+            keys.dims[0]
+        );
+        let mut weights = Matrix::zero((scores.dims[0], scores.dims[1]));
+        for i in 0..scores.dims[0] {
+            let visible = if self.causal {
+                length.min(i + 1)
+            } else {
+                length
+            };
+            let max = (0..visible)
+                .map(|j| scores.get(i, j) * scale)
+                .fold(f64::NEG_INFINITY, f64::max);
+            let mut sum = 0.0;
+            for j in 0..visible {
+                let e = (scores.get(i, j) * scale - max).exp();
+                *weights.get_mut(i, j) = e;
+                sum += e;
+            }
+            for j in 0..visible {
+                *weights.get_mut(i, j) /= sum;
+            }
+        }
+        weights
+    }
+}
+
+impl Module for MultiHeadAttention {
+    fn name(&self) -> &'static str {
+        "multi_head_attention"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        assert!(input.dims[1].is_multiple_of(self.d_model));
+        let (rows, steps) = (input.dims[0], input.dims[1] / self.d_model);
+        if let Some(lengths) = &self.lengths {
+            assert!(lengths.len() == rows);
+        }
+        let tokens = reshape(input, (rows * steps, self.d_model));
+        let (queries, keys, values) = (
+            self.query.forward_batch(&tokens),
+            self.key.forward_batch(&tokens),
+            self.value.forward_batch(&tokens),
+        );
+        let head_size = self.d_model / self.heads;
+        let mut attended = Matrix::zero((rows * steps, self.d_model));
+        let mut weights = Vec::with_capacity(rows * self.heads);
+        for s in 0..rows {
+            for h in 0..self.heads {
+                let (tokens, head) = (
+                    s * steps..(s + 1) * steps,
+                    h * head_size..(h + 1) * head_size,
+                );
+                let head_weights = self.attend(
+                    s,
+                    &queries.slice(tokens.clone(), head.clone()),
+                    &keys.slice(tokens.clone(), head.clone()),
+                );
+                let output = head_weights.multiply(&values.slice(tokens, head));
+                attended.set_slice((s * steps, h * head_size), &output);
+                weights.push(head_weights);
+            }
+        }
+        let output = self.output.forward_batch(&attended);
+        self.cache = Some((queries, keys, values, weights));
+        reshape(&output, (rows, steps * self.d_model))
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let (queries, keys, values, weights) = self
+            .cache
+            .as_ref()
+            .expect("MultiHeadAttention::backward: forward was not called");
+        let (rows, steps) = (dl_dz.dims[0], dl_dz.dims[1] / self.d_model);
+        let head_size = self.d_model / self.heads;
+        let scale = 1.0 / (head_size as f64).sqrt();
+        let dl_dattended = self
+            .output
+            .backward_batch(&reshape(dl_dz, (rows * steps, self.d_model)));
+        let mut dl_dqueries = Matrix::zero((rows * steps, self.d_model));
+        let mut dl_dkeys = Matrix::zero((rows * steps, self.d_model));
+        let mut dl_dvalues = Matrix::zero((rows * steps, self.d_model));
+        for s in 0..rows {
+            for h in 0..self.heads {
+                let (tokens, head) = (
+                    s * steps..(s + 1) * steps,
+                    h * head_size..(h + 1) * head_size,
+                );
+                let head_weights = &weights[s * self.heads + h];
+                let dl_doutput = dl_dattended.slice(tokens.clone(), head.clone());
+                let dl_dweights =
+                    dl_doutput.multiply(&values.slice(tokens.clone(), head.clone()).transpose());
+                let dl_dv = head_weights.transpose().multiply(&dl_doutput);
+                // backpropagate through the softmax of every row
+                let mut dl_dscores = head_weights.hadamard(&dl_dweights);
+                for i in 0..steps {
+                    let dot = (0..steps).map(|j| dl_dscores.get(i, j)).sum::<f64>();
+                    for j in 0..steps {
+                        *dl_dscores.get_mut(i, j) -= head_weights.get(i, j) * dot;
+                        *dl_dscores.get_mut(i, j) *= scale;
+                    }
+                }
+                let dl_dq = dl_dscores.multiply(&keys.slice(tokens.clone(), head.clone()));
+                let dl_dk = dl_dscores
+                    .transpose()
+                    .multiply(&queries.slice(tokens, head));
+                dl_dqueries.set_slice((s * steps, h * head_size), &dl_dq);
+                dl_dkeys.set_slice((s * steps, h * head_size), &dl_dk);
+                dl_dvalues.set_slice((s * steps, h * head_size), &dl_dv);
+            }
+        }
+        let dl_dx = self
+            .query
+            .backward_batch(&dl_dqueries)
+            .add(&self.key.backward_batch(&dl_dkeys))
+            .add(&self.value.backward_batch(&dl_dvalues));
+        reshape(&dl_dx, (rows, steps * self.d_model))
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        self.layers()
+            .into_iter()
+            .flat_map(|layer| layer.parameters())
+            .collect()
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        self.layers()
+            .into_iter()
+            .flat_map(|layer| layer.gradients())
+            .collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        let mut parameters = self.query.parameters_mut();
+        parameters.extend(self.key.parameters_mut());
+        parameters.extend(self.value.parameters_mut());
+        parameters.extend(self.output.parameters_mut());
+        parameters
+    }
+
+    fn set_sequence_lengths(&mut self, lengths: Option<&[usize]>) {
+        self.lengths = lengths.map(|l| l.to_vec());
+    }
+}
+
+/// Adds the fixed sinusoidal encoding of the position of every token to it.
+#[derive(Debug, Clone)]
+pub struct SinusoidalEncoding {
+    pub d_model: usize,
+}
+
+impl SinusoidalEncoding {
+    pub fn new(d_model: usize) -> SinusoidalEncoding {
+        SinusoidalEncoding { d_model }
+    }
+
+    /// Returns the encoding of the position `t`, alternating sines and cosines of decreasing
+    /// frequencies.
+    pub fn encoding(&self, t: usize) -> Vec<f64> {
+        (0..self.d_model)
+            .map(|i| {
+                let frequency = 10000_f64.powf(-((i - i % 2) as f64) / self.d_model as f64);
+                let angle = t as f64 * frequency;
+                if i % 2 == 0 {
+                    angle.sin()
+                } else {
+                    angle.cos()
+                }
+            })
+            .collect()
+    }
+}
+
+impl Module for SinusoidalEncoding {
+    fn name(&self) -> &'static str {
+        "sinusoidal_encoding"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        assert!(input.dims[1].is_multiple_of(self.d_model));
+        let steps = input.dims[1] / self.d_model;
+        let encodings = (0..steps)
+            .flat_map(|t| self.encoding(t))
+            .collect::<Vec<f64>>();
+        input.add_row(&encodings.into())
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        dl_dz.clone()
+    }
+}
+
+/// Adds a learned encoding of the position of every token to it, for up to `max_len` tokens.
+#[derive(Debug, Clone)]
+pub struct LearnedPositionalEncoding {
+    /// encoding of every position, one per row
+    pub table: Matrix,
+    gradients: Matrix,
+}
+
+impl LearnedPositionalEncoding {
+    pub fn new(max_len: usize, d_model: usize) -> LearnedPositionalEncoding {
+        let table = Matrix::random((max_len, d_model), (-0.1, 0.1));
+        LearnedPositionalEncoding {
+            gradients: Matrix::zero_like(&table),
+            table,
+        }
+    }
+}
+
+impl Module for LearnedPositionalEncoding {
+    fn name(&self) -> &'static str {
+        "learned_positional_encoding"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let (max_len, d_model) = (self.table.dims[0], self.table.dims[1]);
+        assert!(input.dims[1].is_multiple_of(d_model));
+        let steps = input.dims[1] / d_model;
+        assert!(
+            steps <= max_len,
+            "LearnedPositionalEncoding: sequence too long"
+        );
+        input.add_row(&self.table.slice(0..steps, 0..d_model).to_vector())
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let d_model = self.table.dims[1];
+        let steps = dl_dz.dims[1] / d_model;
+        let sums = dl_dz.sum_rows();
+        self.gradients = Matrix::zero_like(&self.table);
+        for t in 0..steps {
+            for j in 0..d_model {
+                *self.gradients.get_mut(t, j) = sums[t * d_model + j];
+            }
+        }
+        dl_dz.clone()
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        vec![&self.table.data[..]]
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        vec![&self.gradients.data[..]]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        vec![Parameter {
+            name: "table",
+            value: &mut self.table.data[..],
+            gradient: &mut self.gradients.data[..],
+            frozen: false,
+            sparse: None,
+        }]
+    }
+}
+
+/// Pre-norm Transformer block: `x + attention(norm(x))` followed by
+/// `x + feed_forward(norm(x))`, where the feed-forward network applies two dense layers with a
+/// `GELU` in between to every token.
+#[derive(Debug)]
+pub struct TransformerBlock {
+    pub attention: MultiHeadAttention,
+    attention_norm: TimeDistributed<LayerNorm>,
+    feed_forward_norm: TimeDistributed<LayerNorm>,
+    hidden: TimeDistributed<Layer>,
+    projection: TimeDistributed<Layer>,
+}
+
+impl TransformerBlock {
+    /// Creates an encoder block, where every token attends to the whole sequence.
+    pub fn encoder(d_model: usize, heads: usize, hidden_size: usize) -> TransformerBlock {
+        let hidden_bound = 1.0 / (d_model as f64).sqrt();
+        let projection_bound = 1.0 / (hidden_size as f64).sqrt();
+        TransformerBlock {
+            attention: MultiHeadAttention::new(d_model, heads),
+            attention_norm: TimeDistributed::new(LayerNorm::new(d_model), d_model),
+            feed_forward_norm: TimeDistributed::new(LayerNorm::new(d_model), d_model),
+            hidden: TimeDistributed::new(
+                Layer::random((d_model, hidden_size), (-hidden_bound, hidden_bound))
+                    .with_activation(GELU),
+                d_model,
+            ),
+            projection: TimeDistributed::new(
+                Layer::random(
+                    (hidden_size, d_model),
+                    (-projection_bound, projection_bound),
+                ),
+                hidden_size,
+            ),
+        }
+    }
+
+    /// Creates a decoder block, where every token only attends to itself and earlier tokens.
+    ///
+    /// Since a `NeuralNetwork` is a single stack of modules, the block has no cross-attention to
+    /// an encoder, as in decoder-only language models.
+    pub fn decoder(d_model: usize, heads: usize, hidden_size: usize) -> TransformerBlock {
+        let mut block = TransformerBlock::encoder(d_model, heads, hidden_size);
+        block.attention.causal = true;
+        block
+    }
+
+    fn modules(&self) -> [&dyn Module; 5] {
+        [
+            &self.attention_norm,
+            &self.attention,
+            &self.feed_forward_norm,
+            &self.hidden,
+            &self.projection,
+        ]
+    }
+}
+
+impl Module for TransformerBlock {
+    fn name(&self) -> &'static str {
+        "transformer_block"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let attended = self
+            .attention
+            .forward_batch(&self.attention_norm.forward_batch(input));
+        let x = input.add(&attended);
+        let normalized = self.feed_forward_norm.forward_batch(&x);
+        let hidden = self.hidden.forward_batch(&normalized);
+        x.add(&self.projection.forward_batch(&hidden))
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let dl_dhidden = self.projection.backward_batch(dl_dz);
+        let dl_dnormalized = self.hidden.backward_batch(&dl_dhidden);
+        let dl_dx = dl_dz.add(&self.feed_forward_norm.backward_batch(&dl_dnormalized));
+        let dl_dattended = self.attention.backward_batch(&dl_dx);
+        dl_dx.add(&self.attention_norm.backward_batch(&dl_dattended))
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        self.modules()
+            .into_iter()
+            .flat_map(|module| module.parameters())
+            .collect()
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        self.modules()
+            .into_iter()
+            .flat_map(|module| module.gradients())
+            .collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        let mut parameters = self.attention_norm.parameters_mut();
+        parameters.extend(self.attention.parameters_mut());
+        parameters.extend(self.feed_forward_norm.parameters_mut());
+        parameters.extend(self.hidden.parameters_mut());
+        parameters.extend(self.projection.parameters_mut());
+        parameters
+    }
+
+    fn set_sequence_lengths(&mut self, lengths: Option<&[usize]>) {
+        self.attention.set_sequence_lengths(lengths);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        LearnedPositionalEncoding, MultiHeadAttention, SinusoidalEncoding, TimeDistributed,
+        TransformerBlock,
+    };
+    use crate::activation::TANH;
+    use crate::gradcheck::{check_module_batch, max_error};
+    use crate::head::IDENTITY;
+    use crate::matrix::Matrix;
+    use crate::module::Module;
+    use crate::optimizer::Adam;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    #[test]
+    fn test_gradients() {
+        let mut padded = MultiHeadAttention::new(4, 2);
+        padded.set_sequence_lengths(Some(&[3, 1]));
+        let mut padded_block = TransformerBlock::decoder(4, 2, 6);
+        padded_block.set_sequence_lengths(Some(&[2, 3]));
+        let modules: Vec<Box<dyn Module>> = vec![
+            Box::new(MultiHeadAttention::new(4, 2)),
+            Box::new(MultiHeadAttention::new(4, 1).causal()),
+            Box::new(padded),
+            Box::new(TransformerBlock::encoder(4, 2, 6)),
+            Box::new(padded_block),
+            Box::new(SinusoidalEncoding::new(4)),
+            Box::new(LearnedPositionalEncoding::new(5, 4)),
+            Box::new(TimeDistributed::new(
+                Layer::random((4, 3), (-1.0, 1.0)).with_activation(TANH),
+                4,
+            )),
+        ];
+        for mut module in modules {
+            let input = Matrix::random((2, 12), (-1.0, 1.0));
+            let output_width = module.forward_batch(&input).dims[1];
+            let target = Matrix::random((2, output_width), (-1.0, 1.0));
+            let result = check_module_batch(module.as_mut(), &input, &target);
+            assert!(max_error(&result) < 1e-6, "{}: {:?}", module.name(), result);
+        }
+    }
+
+    /// Returns the outputs for a random `input` and for the same `input` with `changed` columns
+    /// perturbed (unevenly, since layer normalization ignores shifts of whole tokens).
+    fn perturb(module: &mut dyn Module, changed: std::ops::Range<usize>) -> (Matrix, Matrix) {
+        let input = Matrix::random((1, 12), (-1.0, 1.0));
+        let mut perturbed = input.clone();
+        for j in changed {
+            *perturbed.get_mut(0, j) += 0.1 * (j % 4) as f64;
+        }
+        (
+            module.forward_batch(&input),
+            module.forward_batch(&perturbed),
+        )
+    }
+
+    #[test]
+    fn test_masks() {
+        // with a causal mask, changing the last token leaves the others unchanged
+        let (output, perturbed) = perturb(&mut TransformerBlock::decoder(4, 2, 6), 8..12);
+        for j in 0..8 {
+            assert!((output.get(0, j) - perturbed.get(0, j)).abs() < EPSILON);
+        }
+        assert!((8..12).any(|j| (output.get(0, j) - perturbed.get(0, j)).abs() > EPSILON));
+
+        // without one, it changes all of them
+        let (output, perturbed) = perturb(&mut TransformerBlock::encoder(4, 2, 6), 8..12);
+        assert!((0..4).any(|j| (output.get(0, j) - perturbed.get(0, j)).abs() > EPSILON));
+
+        // padding is invisible to the other tokens
+        let mut attention = MultiHeadAttention::new(4, 2);
+        attention.set_sequence_lengths(Some(&[2]));
+        let (output, perturbed) = perturb(&mut attention, 8..12);
+        for j in 0..8 {
+            assert!((output.get(0, j) - perturbed.get(0, j)).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "sequence length 4 exceeds 3 steps")]
+    fn test_sequence_too_long() {
+        let mut attention = MultiHeadAttention::new(4, 2);
+        attention.set_sequence_lengths(Some(&[4]));
+        attention.forward_batch(&Matrix::zero((1, 12)));
+    }
+
+    #[test]
+    fn test_sinusoidal_encoding() {
+        let encoding = SinusoidalEncoding::new(4);
+        assert_eq!(encoding.encoding(0), vec![0.0, 1.0, 0.0, 1.0]);
+        let second = encoding.encoding(1);
+        assert!((second[0] - 1.0_f64.sin()).abs() < EPSILON);
+        assert!((second[3] - 0.01_f64.cos()).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_training() {
+        // every token has to output the first token of its sequence, which requires attention
+        let inputs = Matrix::random((8, 12), (-1.0, 1.0));
+        let mut targets = Matrix::zero((8, 3));
+        for i in 0..8 {
+            for t in 0..3 {
+                *targets.get_mut(i, t) = inputs.get(i, 0);
+            }
+        }
+        let mut nn = NeuralNetwork::from_modules(vec![
+            Box::new(LearnedPositionalEncoding::new(3, 4)),
+            Box::new(TransformerBlock::encoder(4, 2, 8)),
+            Box::new(TimeDistributed::new(Layer::random((4, 1), (-0.5, 0.5)), 4)),
+        ])
+        .with_head(IDENTITY)
+        .with_optimizer(Adam::new(0.01));
+        let output = nn.forward_batch(&inputs);
+        let initial = nn.loss_batch(&output, &targets);
+        for _ in 0..200 {
+            nn.train_batch(&inputs, &targets);
+        }
+        let output = nn.forward_batch(&inputs);
+        assert!(nn.loss_batch(&output, &targets) < initial / 4.0);
+    }
+}
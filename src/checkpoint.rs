@@ -0,0 +1,776 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::matrix::Matrix;
+use crate::module::Module;
+use crate::vector::Vector;
+use crate::{activation, head, loss, optimizer, Layer, NeuralNetwork};
+
+const MAGIC: &[u8; 4] = b"BAMF";
+
+/// Version of the checkpoint format written by `NeuralNetwork::save`.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    /// The file is not a checkpoint, or is truncated.
+    InvalidFormat(String),
+    UnsupportedVersion(u32),
+    /// The checkpoint refers to something that cannot be rebuilt from it, e.g. an unknown module.
+    Unsupported(String),
+    /// The checkpoint does not fit the network it is loaded into.
+    Mismatch(String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(error) => write!(f, "checkpoint i/o error: {}", error),
+            CheckpointError::InvalidFormat(message) => {
+                write!(f, "invalid checkpoint: {}", message)
+            }
+            CheckpointError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported checkpoint version {} (supported: {})",
+                version, VERSION
+            ),
+            CheckpointError::Unsupported(message) => {
+                write!(f, "cannot restore checkpoint: {}", message)
+            }
+            CheckpointError::Mismatch(message) => {
+                write!(f, "checkpoint does not match the network: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(error: io::Error) -> Self {
+        CheckpointError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ParameterRecord {
+    name: String,
+    /// whether the module keeps the parameter constant, e.g. with `Layer::set_constant`
+    constant: bool,
+    /// whether the network freezes the parameter with `NeuralNetwork::freeze`
+    frozen: bool,
+    values: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ModuleRecord {
+    name: String,
+    /// name and parameters of the activation
+    activation: Option<(String, Vec<f64>)>,
+    parameters: Vec<ParameterRecord>,
+    /// names and values of the state that is not trained
+    buffers: Vec<(String, Vec<f64>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TrainingRecord {
+    optimizer: String,
+    learning_rate: f64,
+    steps: usize,
+    epochs: usize,
+    state: Vec<(String, Vec<Vec<f64>>)>,
+}
+
+/// Contents of a checkpoint file:
+///
+/// ```text
+/// "BAMF" version:u32
+/// head:str loss:opt(str f64s)
+/// modules:u32 { name:str activation:opt(str f64s) parameters:u32 { name:str constant:u8 frozen:u8
+///               f64s }
+///               buffers:u32 { name:str f64s } }
+/// training:opt(optimizer:str learning_rate:f64 steps:u64 epochs:u64
+///              state:u32 { name:str buffers:u32 { f64s } })
+/// ```
+///
+/// in little endian, where `str` is a u32 length and UTF-8 bytes, `f64s` a u64 length and the
+/// values, and `opt(..)` a u8 flag followed by the contents if it is set.
+#[derive(Debug, Clone, PartialEq)]
+struct Checkpoint {
+    head: String,
+    loss: Option<(String, Vec<f64>)>,
+    modules: Vec<ModuleRecord>,
+    training: Option<TrainingRecord>,
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: usize) {
+        self.0.extend((value as u32).to_le_bytes());
+    }
+
+    fn u64(&mut self, value: usize) {
+        self.0.extend((value as u64).to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len());
+        self.0.extend(value.as_bytes());
+    }
+
+    fn f64s(&mut self, values: &[f64]) {
+        self.u64(values.len());
+        values.iter().for_each(|v| self.f64(*v));
+    }
+
+    fn named_f64s(&mut self, value: &Option<(String, Vec<f64>)>) {
+        self.u8(value.is_some() as u8);
+        if let Some((name, values)) = value {
+            self.str(name);
+            self.f64s(values);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], CheckpointError> {
+        if self.bytes.len() - self.position < n {
+            return Err(CheckpointError::InvalidFormat(format!(
+                "unexpected end of file at byte {}",
+                self.position
+            )));
+        }
+        self.position += n;
+        Ok(&self.bytes[self.position - n..self.position])
+    }
+
+    fn u8(&mut self) -> Result<u8, CheckpointError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, CheckpointError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            flag => Err(CheckpointError::InvalidFormat(format!(
+                "invalid flag {} at byte {}",
+                flag,
+                self.position - 1
+            ))),
+        }
+    }
+
+    fn u32(&mut self) -> Result<usize, CheckpointError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn u64(&mut self) -> Result<usize, CheckpointError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()) as usize)
+    }
+
+    fn f64(&mut self) -> Result<f64, CheckpointError> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> Result<String, CheckpointError> {
+        let length = self.u32()?;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| CheckpointError::InvalidFormat("invalid UTF-8 in a name".to_owned()))
+    }
+
+    fn f64s(&mut self) -> Result<Vec<f64>, CheckpointError> {
+        let length = self.u64()?;
+        let bytes = self.take(length.saturating_mul(8))?;
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+
+    fn named_f64s(&mut self) -> Result<Option<(String, Vec<f64>)>, CheckpointError> {
+        match self.bool()? {
+            true => Ok(Some((self.str()?, self.f64s()?))),
+            false => Ok(None),
+        }
+    }
+}
+
+impl Checkpoint {
+    fn encode(&self) -> Vec<u8> {
+        let mut w = Writer(MAGIC.to_vec());
+        w.u32(VERSION as usize);
+        w.str(&self.head);
+        w.named_f64s(&self.loss);
+        w.u32(self.modules.len());
+        for module in &self.modules {
+            w.str(&module.name);
+            w.named_f64s(&module.activation);
+            w.u32(module.parameters.len());
+            for parameter in &module.parameters {
+                w.str(&parameter.name);
+                w.u8(parameter.constant as u8);
+                w.u8(parameter.frozen as u8);
+                w.f64s(&parameter.values);
+            }
+            w.u32(module.buffers.len());
+            for (name, values) in &module.buffers {
+                w.str(name);
+                w.f64s(values);
+            }
+        }
+        w.u8(self.training.is_some() as u8);
+        if let Some(training) = &self.training {
+            w.str(&training.optimizer);
+            w.f64(training.learning_rate);
+            w.u64(training.steps);
+            w.u64(training.epochs);
+            w.u32(training.state.len());
+            for (name, buffers) in &training.state {
+                w.str(name);
+                w.u32(buffers.len());
+                buffers.iter().for_each(|buffer| w.f64s(buffer));
+            }
+        }
+        w.0
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Checkpoint, CheckpointError> {
+        let mut r = Reader { bytes, position: 0 };
+        if r.take(4).ok() != Some(&MAGIC[..]) {
+            return Err(CheckpointError::InvalidFormat(
+                "not a bamf checkpoint".to_owned(),
+            ));
+        }
+        let version = r.u32()? as u32;
+        if version != VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+        let head = r.str()?;
+        let loss = r.named_f64s()?;
+        let modules = (0..r.u32()?)
+            .map(|_| {
+                let name = r.str()?;
+                let activation = r.named_f64s()?;
+                let parameters = (0..r.u32()?)
+                    .map(|_| {
+                        Ok(ParameterRecord {
+                            name: r.str()?,
+                            constant: r.bool()?,
+                            frozen: r.bool()?,
+                            values: r.f64s()?,
+                        })
+                    })
+                    .collect::<Result<_, CheckpointError>>()?;
+                let buffers = (0..r.u32()?)
+                    .map(|_| Ok((r.str()?, r.f64s()?)))
+                    .collect::<Result<_, CheckpointError>>()?;
+                Ok(ModuleRecord {
+                    name,
+                    activation,
+                    parameters,
+                    buffers,
+                })
+            })
+            .collect::<Result<_, CheckpointError>>()?;
+        let training = match r.bool()? {
+            true => Some(TrainingRecord {
+                optimizer: r.str()?,
+                learning_rate: r.f64()?,
+                steps: r.u64()?,
+                epochs: r.u64()?,
+                state: (0..r.u32()?)
+                    .map(|_| {
+                        let name = r.str()?;
+                        let buffers = (0..r.u32()?)
+                            .map(|_| r.f64s())
+                            .collect::<Result<_, CheckpointError>>()?;
+                        Ok((name, buffers))
+                    })
+                    .collect::<Result<_, CheckpointError>>()?,
+            }),
+            false => None,
+        };
+        if r.position != bytes.len() {
+            return Err(CheckpointError::InvalidFormat(format!(
+                "{} trailing bytes",
+                bytes.len() - r.position
+            )));
+        }
+        Ok(Checkpoint {
+            head,
+            loss,
+            modules,
+            training,
+        })
+    }
+}
+
+/// Rebuilds a module from its record, for the module types whose shape follows from their
+/// parameters.
+fn build_module(index: usize, record: &ModuleRecord) -> Result<Box<dyn Module>, CheckpointError> {
+    let find = |name: &str| record.parameters.iter().find(|p| p.name == name);
+    match (record.name.as_str(), find("weights"), find("biases")) {
+        ("dense", Some(weights), Some(biases))
+            if !biases.values.is_empty()
+                && weights.values.len().is_multiple_of(biases.values.len()) =>
+        {
+            let dims = (
+                weights.values.len() / biases.values.len(),
+                biases.values.len(),
+            );
+            let matrix = Matrix::from_vector(weights.values.clone().into(), dims);
+            let mut layer = Layer::new(matrix, Vector::from(biases.values.clone()));
+            if let Some((name, parameters)) = &record.activation {
+                let activation = activation::from_name(name, parameters).ok_or_else(|| {
+                    CheckpointError::Unsupported(format!("layer {}: activation {}", index, name))
+                })?;
+                layer = layer.with_boxed_activation(activation);
+            }
+            if record.parameters.iter().all(|p| p.constant) {
+                layer.set_constant();
+            }
+            Ok(Box::new(layer))
+        }
+        (name, _, _) => Err(CheckpointError::Unsupported(format!(
+            "layer {}: module {} (use `load_parameters` on a network built with it instead)",
+            index, name
+        ))),
+    }
+}
+
+fn invalid_state(optimizer: &str, name: &str) -> CheckpointError {
+    CheckpointError::InvalidFormat(format!("invalid {} state {}", optimizer, name))
+}
+
+impl NeuralNetwork {
+    fn checkpoint(&mut self, with_optimizer: bool) -> Checkpoint {
+        let modules = self
+            .layers
+            .iter_mut()
+            .enumerate()
+            .map(|(l, layer)| ModuleRecord {
+                name: layer.name().to_owned(),
+                activation: layer
+                    .activation()
+                    .map(|a| (a.name().to_owned(), a.parameters().to_vec())),
+                buffers: layer
+                    .buffers()
+                    .into_iter()
+                    .map(|(name, values)| (name.to_owned(), values.to_vec()))
+                    .collect(),
+                parameters: layer
+                    .parameters_mut()
+                    .into_iter()
+                    .map(|p| ParameterRecord {
+                        name: p.name.to_owned(),
+                        constant: p.frozen,
+                        frozen: self.frozen.contains(&(l, p.name)),
+                        values: p.value.to_vec(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let training = with_optimizer.then(|| TrainingRecord {
+            optimizer: self.optimizer.name().to_owned(),
+            learning_rate: self.optimizer.learning_rate(),
+            steps: self.steps,
+            epochs: self.epochs,
+            state: self
+                .optimizer
+                .state()
+                .into_iter()
+                .map(|(name, buffers)| (name.to_owned(), buffers))
+                .collect(),
+        });
+        Checkpoint {
+            head: self.head.name().to_owned(),
+            loss: self
+                .loss
+                .as_ref()
+                .map(|loss| (loss.name().to_owned(), loss.parameters())),
+            modules,
+            training,
+        }
+    }
+
+    /// Saves the layers, parameters, buffers, head and loss of the network to a checkpoint file.
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CheckpointError> {
+        Ok(fs::write(path, self.checkpoint(false).encode())?)
+    }
+
+    /// Like `save`, but also saves the optimizer with its state and the step and epoch counts,
+    /// to resume training later.
+    pub fn save_with_optimizer<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CheckpointError> {
+        Ok(fs::write(path, self.checkpoint(true).encode())?)
+    }
+
+    /// Rebuilds a network from a checkpoint file.
+    ///
+    /// Only networks of dense layers can be rebuilt. Networks with other modules can be restored
+    /// with `load_parameters` instead.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<NeuralNetwork, CheckpointError> {
+        let checkpoint = Checkpoint::decode(&fs::read(path)?)?;
+        let layers = checkpoint
+            .modules
+            .iter()
+            .enumerate()
+            .map(|(index, record)| build_module(index, record))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut nn = NeuralNetwork::from_modules(layers);
+        nn.head = head::from_name(&checkpoint.head)
+            .ok_or_else(|| CheckpointError::Unsupported(format!("head {}", checkpoint.head)))?;
+        if let Some((name, parameters)) = &checkpoint.loss {
+            nn.loss = Some(
+                loss::from_name(name, parameters)
+                    .ok_or_else(|| CheckpointError::Unsupported(format!("loss {}", name)))?,
+            );
+        }
+        if let Some(training) = &checkpoint.training {
+            nn.optimizer = optimizer::from_name(&training.optimizer, training.learning_rate)
+                .ok_or_else(|| {
+                    CheckpointError::Unsupported(format!("optimizer {}", training.optimizer))
+                })?;
+        }
+        nn.restore(checkpoint)?;
+        Ok(nn)
+    }
+
+    /// Loads the parameters and buffers, and the optimizer state if it was saved, from a
+    /// checkpoint file into a network with the same layers, e.g. one with modules that `load`
+    /// cannot rebuild.
+    pub fn load_parameters<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CheckpointError> {
+        let checkpoint = Checkpoint::decode(&fs::read(path)?)?;
+        self.restore(checkpoint)
+    }
+
+    /// Checks that a checkpoint fits the network before copying anything into it.
+    ///
+    /// The optimizer state is loaded first, and the previous one put back if it is invalid.
+    /// Parameters frozen with `NeuralNetwork::freeze` are frozen again, while constant ones are
+    /// left to the modules of the network.
+    fn restore(&mut self, checkpoint: Checkpoint) -> Result<(), CheckpointError> {
+        let mismatch = |message: String| Err(CheckpointError::Mismatch(message));
+        if checkpoint.modules.len() != self.layers.len() {
+            return mismatch(format!(
+                "{} layers in the checkpoint, {} in the network",
+                checkpoint.modules.len(),
+                self.layers.len()
+            ));
+        }
+        for (l, (layer, record)) in self.layers.iter_mut().zip(&checkpoint.modules).enumerate() {
+            if layer.name() != record.name {
+                return mismatch(format!(
+                    "layer {} is a {} in the checkpoint, a {} in the network",
+                    l,
+                    record.name,
+                    layer.name()
+                ));
+            }
+            let parameters = layer.parameters_mut();
+            if parameters.len() != record.parameters.len() {
+                return mismatch(format!(
+                    "layer {} ({}) has {} parameters in the checkpoint, {} in the network",
+                    l,
+                    record.name,
+                    record.parameters.len(),
+                    parameters.len()
+                ));
+            }
+            for (parameter, saved) in parameters.iter().zip(&record.parameters) {
+                if parameter.name != saved.name || parameter.value.len() != saved.values.len() {
+                    return mismatch(format!(
+                        "layer {} ({}): parameter {} of {} values in the checkpoint, {} of {} in \
+                         the network",
+                        l,
+                        record.name,
+                        saved.name,
+                        saved.values.len(),
+                        parameter.name,
+                        parameter.value.len()
+                    ));
+                }
+            }
+            let buffers = layer.buffers();
+            let fits = buffers.len() == record.buffers.len()
+                && buffers
+                    .iter()
+                    .zip(&record.buffers)
+                    .all(|((name, values), saved)| {
+                        *name == saved.0 && values.len() == saved.1.len()
+                    });
+            if !fits {
+                return mismatch(format!(
+                    "layer {} ({}): buffers {:?} in the checkpoint, {:?} in the network",
+                    l,
+                    record.name,
+                    record
+                        .buffers
+                        .iter()
+                        .map(|(name, _)| name)
+                        .collect::<Vec<_>>(),
+                    buffers.iter().map(|(name, _)| name).collect::<Vec<_>>()
+                ));
+            }
+        }
+        if let Some(training) = &checkpoint.training {
+            if training.optimizer != self.optimizer.name() {
+                return mismatch(format!(
+                    "optimizer {} in the checkpoint, {} in the network",
+                    training.optimizer,
+                    self.optimizer.name()
+                ));
+            }
+            let previous = self.optimizer.state();
+            for (name, buffers) in &training.state {
+                if !self.optimizer.load_state(name, buffers.clone()) {
+                    for (name, buffers) in previous {
+                        self.optimizer.load_state(name, buffers);
+                    }
+                    return Err(invalid_state(&training.optimizer, name));
+                }
+            }
+        }
+
+        let mut frozen = vec![];
+        for (l, (layer, record)) in self.layers.iter_mut().zip(checkpoint.modules).enumerate() {
+            for ((_, buffer), (_, saved)) in layer.buffers_mut().into_iter().zip(record.buffers) {
+                buffer.copy_from_slice(&saved);
+            }
+            for (parameter, saved) in layer.parameters_mut().into_iter().zip(record.parameters) {
+                parameter.value.copy_from_slice(&saved.values);
+                if saved.frozen {
+                    frozen.push((l, saved.name));
+                }
+            }
+        }
+        for (l, name) in frozen {
+            self.freeze(l, Some(&name));
+        }
+        if let Some(training) = checkpoint.training {
+            self.optimizer.set_learning_rate(training.learning_rate);
+            self.steps = training.steps;
+            self.epochs = training.epochs;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checkpoint, CheckpointError, VERSION};
+    use crate::activation::{PRelu, ELU, TANH};
+    use crate::conv::Conv1d;
+    use crate::loss::Huber;
+    use crate::matrix::Matrix;
+    use crate::module::Module;
+    use crate::normalization::BatchNorm;
+    use crate::optimizer::{Adam, Sgd};
+    use crate::{Layer, NeuralNetwork};
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("bamf_{}_{}.ckpt", name, std::process::id()))
+    }
+
+    fn network() -> NeuralNetwork {
+        NeuralNetwork::regression(vec![
+            Layer::random((3, 4), (-1.0, 1.0)).with_activation(PRelu::new(0.2)),
+            Layer::random((4, 4), (-1.0, 1.0)).with_activation(ELU),
+            Layer::random((4, 2), (-1.0, 1.0)),
+        ])
+        .with_loss(Huber::new(0.5))
+        .with_optimizer(Adam::new(0.01))
+    }
+
+    fn train(nn: &mut NeuralNetwork, steps: usize) -> Matrix {
+        let input = Matrix::from(vec![vec![0.1, 0.2, 0.3], vec![-0.5, 0.4, 0.0]]);
+        let target = Matrix::from(vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        for _ in 0..steps {
+            nn.train_batch(&input, &target);
+        }
+        nn.forward_batch(&input)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut nn = network();
+        train(&mut nn, 5);
+        nn.freeze(2, Some("biases"));
+        let file = path("round_trip");
+        nn.save_with_optimizer(&file).unwrap();
+        let mut loaded = NeuralNetwork::load(&file).unwrap();
+        assert_eq!(
+            Checkpoint::decode(&std::fs::read(&file).unwrap()).unwrap(),
+            loaded.checkpoint(true)
+        );
+        assert_eq!(loaded.steps(), 5);
+        assert!(loaded.is_frozen(2, "biases"));
+        // frozen parameters are not made constant, so they can be unfrozen
+        assert!(loaded.layers[2].parameters_mut().iter().all(|p| !p.frozen));
+        loaded.unfreeze(2, None);
+        assert!(!loaded.is_frozen(2, "biases"));
+        loaded.freeze(2, Some("biases"));
+
+        // training resumes exactly where it stopped
+        assert_eq!(train(&mut nn, 3).data, train(&mut loaded, 3).data);
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_constant_layer() {
+        let mut layer = Layer::random((2, 2), (-1.0, 1.0)).with_activation(TANH);
+        layer.set_constant();
+        let mut nn = NeuralNetwork::new(vec![layer, Layer::random((2, 2), (-1.0, 1.0))]);
+        let file = path("constant");
+        nn.save(&file).unwrap();
+        let mut loaded = NeuralNetwork::load(&file).unwrap();
+        assert!(loaded.layers[0].parameters_mut().iter().all(|p| p.frozen));
+        assert!(loaded.layers[1].parameters_mut().iter().all(|p| !p.frozen));
+        assert_eq!(loaded.steps(), 0);
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_batch_norm_statistics() {
+        let modules = || -> Vec<Box<dyn Module>> {
+            vec![
+                Box::new(Layer::random((3, 4), (-1.0, 1.0))),
+                Box::new(BatchNorm::new(4)),
+                Box::new(Layer::random((4, 2), (-1.0, 1.0))),
+            ]
+        };
+        let mut nn = NeuralNetwork::from_modules(modules());
+        train(&mut nn, 5);
+        nn.set_training(false);
+        let file = path("batch_norm");
+        nn.save(&file).unwrap();
+        let mut other = NeuralNetwork::from_modules(modules());
+        other.load_parameters(&file).unwrap();
+        other.set_training(false);
+        let input = Matrix::random((3, 3), (-1.0, 1.0));
+        assert_eq!(
+            nn.forward_batch(&input).data,
+            other.forward_batch(&input).data
+        );
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_non_positive_prelu() {
+        // training can push the slope of a prelu to zero or below
+        let mut nn = NeuralNetwork::new(vec![
+            Layer::random((2, 3), (-1.0, 1.0)).with_activation(PRelu::new(-0.1)),
+            Layer::random((3, 2), (-1.0, 1.0)).with_activation(PRelu::new(0.0)),
+        ]);
+        let file = path("non_positive_prelu");
+        nn.save(&file).unwrap();
+        let mut loaded = NeuralNetwork::load(&file).unwrap();
+        let input = Matrix::from(vec![vec![-0.5, 0.7], vec![0.3, -0.2]]);
+        assert_eq!(
+            nn.forward_batch(&input).data,
+            loaded.forward_batch(&input).data
+        );
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_load_parameters() {
+        let modules = || -> Vec<Box<dyn Module>> {
+            vec![
+                Box::new(Conv1d::new((1, 2), 3, 6).with_activation(TANH)),
+                Box::new(Layer::random((8, 2), (-1.0, 1.0))),
+            ]
+        };
+        let mut nn = NeuralNetwork::from_modules(modules());
+        let file = path("load_parameters");
+        nn.save(&file).unwrap();
+        assert!(matches!(
+            NeuralNetwork::load(&file),
+            Err(CheckpointError::Unsupported(_))
+        ));
+        let mut other = NeuralNetwork::from_modules(modules());
+        other.load_parameters(&file).unwrap();
+        let input = Matrix::random((2, 6), (-1.0, 1.0));
+        assert_eq!(
+            nn.forward_batch(&input).data,
+            other.forward_batch(&input).data
+        );
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut nn = network();
+        let file = path("errors");
+        nn.save_with_optimizer(&file).unwrap();
+
+        let mut wider = NeuralNetwork::new(vec![
+            Layer::random((3, 5), (-1.0, 1.0)),
+            Layer::random((5, 4), (-1.0, 1.0)),
+            Layer::random((4, 2), (-1.0, 1.0)),
+        ]);
+        let error = wider.load_parameters(&file).unwrap_err();
+        assert!(matches!(error, CheckpointError::Mismatch(_)));
+        assert!(error.to_string().contains("layer 0"));
+        let mut sgd = network().with_optimizer(Sgd::new(0.1));
+        assert!(matches!(
+            sgd.load_parameters(&file),
+            Err(CheckpointError::Mismatch(_))
+        ));
+
+        // a malformed optimizer state leaves the network untouched
+        let mut checkpoint = nn.checkpoint(true);
+        let training = checkpoint.training.as_mut().unwrap();
+        let beta1 = training.state.iter_mut().find(|(name, _)| name == "beta1");
+        beta1.unwrap().1 = vec![vec![0.9, 0.9]];
+        std::fs::write(&file, checkpoint.encode()).unwrap();
+        let mut other = network();
+        let before = other.checkpoint(true);
+        assert!(matches!(
+            other.load_parameters(&file),
+            Err(CheckpointError::InvalidFormat(_))
+        ));
+        assert_eq!(other.checkpoint(true), before);
+
+        nn.save_with_optimizer(&file).unwrap();
+        let mut bytes = std::fs::read(&file).unwrap();
+        bytes[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        std::fs::write(&file, &bytes).unwrap();
+        assert!(matches!(
+            NeuralNetwork::load(&file),
+            Err(CheckpointError::UnsupportedVersion(v)) if v == VERSION + 1
+        ));
+        bytes[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        std::fs::write(&file, &bytes[..bytes.len() - 3]).unwrap();
+        assert!(matches!(
+            NeuralNetwork::load(&file),
+            Err(CheckpointError::InvalidFormat(_))
+        ));
+        std::fs::write(&file, b"not a checkpoint").unwrap();
+        assert!(matches!(
+            NeuralNetwork::load(&file),
+            Err(CheckpointError::InvalidFormat(_))
+        ));
+        std::fs::remove_file(&file).unwrap();
+        assert!(matches!(
+            NeuralNetwork::load(&file),
+            Err(CheckpointError::Io(_))
+        ));
+    }
+}
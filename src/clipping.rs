@@ -0,0 +1,117 @@
+/// Limits the gradients of a `NeuralNetwork` before every training update, against exploding
+/// gradients.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientClipping {
+    /// Clamps every element of the gradients to `[-c, c]`.
+    Value(f64),
+    /// Rescales the gradient of every parameter with an L2 norm above `c` down to `c`.
+    Norm(f64),
+    /// Rescales all the gradients together so that their combined L2 norm is at most `c`.
+    GlobalNorm(f64),
+}
+
+impl GradientClipping {
+    /// Clips the gradients in place.
+    ///
+    /// Returns their global norm before clipping
+    pub fn clip(&self, gradients: &mut [&mut [f64]]) -> f64 {
+        let norm = global_norm(gradients);
+        match *self {
+            GradientClipping::Value(c) => {
+                for g in gradients.iter_mut().flat_map(|g| g.iter_mut()) {
+                    *g = g.clamp(-c, c);
+                }
+            }
+            GradientClipping::Norm(c) => {
+                for gradient in gradients.iter_mut() {
+                    scale_to(gradient, norm_of(gradient), c);
+                }
+            }
+            GradientClipping::GlobalNorm(c) => {
+                for gradient in gradients.iter_mut() {
+                    scale_to(gradient, norm, c);
+                }
+            }
+        }
+        norm
+    }
+}
+
+fn norm_of(gradient: &[f64]) -> f64 {
+    gradient.iter().map(|g| g * g).sum::<f64>().sqrt()
+}
+
+/// Rescales a gradient from `norm` to `max_norm` if it is larger.
+fn scale_to(gradient: &mut [f64], norm: f64, max_norm: f64) {
+    if norm > max_norm {
+        let scale = max_norm / norm;
+        gradient.iter_mut().for_each(|g| *g *= scale);
+    }
+}
+
+/// Computes the L2 norm of all the gradients together.
+pub fn global_norm<G>(gradients: &[G]) -> f64
+where
+    G: AsRef<[f64]>,
+{
+    gradients
+        .iter()
+        .flat_map(|g| g.as_ref())
+        .map(|g| g * g)
+        .sum::<f64>()
+        .sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{global_norm, GradientClipping};
+    use crate::matrix::Matrix;
+    use crate::optimizer::Sgd;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    fn clip(clipping: GradientClipping) -> (Vec<f64>, Vec<f64>, f64) {
+        let (mut a, mut b) = (vec![3.0, -4.0], vec![0.5, 0.0]);
+        let norm = clipping.clip(&mut [&mut a[..], &mut b[..]]);
+        (a, b, norm)
+    }
+
+    #[test]
+    fn test_clip() {
+        let norm = 25.25_f64.sqrt();
+        let (a, b, n) = clip(GradientClipping::Value(1.0));
+        assert_eq!((a, b), (vec![1.0, -1.0], vec![0.5, 0.0]));
+        assert!((n - norm).abs() < EPSILON);
+
+        let (a, b, _) = clip(GradientClipping::Norm(1.0));
+        assert!((a[0] - 0.6).abs() < EPSILON && (a[1] + 0.8).abs() < EPSILON);
+        assert_eq!(b, vec![0.5, 0.0]);
+
+        let (a, b, _) = clip(GradientClipping::GlobalNorm(1.0));
+        assert!((global_norm(&[&a, &b]) - 1.0).abs() < EPSILON);
+        assert!((a[0] / b[0] - 6.0).abs() < EPSILON);
+
+        let (a, b, _) = clip(GradientClipping::GlobalNorm(10.0));
+        assert_eq!((a, b), (vec![3.0, -4.0], vec![0.5, 0.0]));
+    }
+
+    #[test]
+    fn test_network_clipping() {
+        let mut nn = NeuralNetwork::new(vec![Layer::new(Matrix::zero((2, 2)), Vector::zero(2))])
+            .with_optimizer(Sgd::new(1.0))
+            .with_gradient_clipping(GradientClipping::GlobalNorm(0.01));
+        let before = nn.layers[0].parameters().concat();
+        nn.train(Vector::from(vec![5.0, -5.0]), &Vector::from(vec![1.0, 0.0]));
+        let after = nn.layers[0].parameters().concat();
+        let step = before
+            .iter()
+            .zip(&after)
+            .map(|(w, v)| (w - v).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        assert!((step - 0.01).abs() < EPSILON);
+        assert!(nn.gradient_norm() > 0.01);
+    }
+}
@@ -0,0 +1,854 @@
+use crate::activation::Activation;
+use crate::dual::DualVector;
+use crate::matrix::{Matrix, Multiply};
+use crate::module::{Module, Parameter};
+use crate::vector::Vector;
+
+/// Spatial layout shared by convolutions and pooling: a window of `kernel` positions spaced by
+/// `dilation` slides by `stride` over the input, zero-padded by `padding` on every side.
+///
+/// Samples are stored as rows holding one `input_size` feature map per channel, row by row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub kernel: (usize, usize),
+    pub input_size: (usize, usize),
+    pub stride: (usize, usize),
+    pub padding: (usize, usize),
+    pub dilation: (usize, usize),
+}
+
+impl Window {
+    pub fn new(kernel: (usize, usize), input_size: (usize, usize)) -> Window {
+        assert!(kernel.0 > 0 && kernel.1 > 0);
+        Window {
+            kernel,
+            input_size,
+            stride: (1, 1),
+            padding: (0, 0),
+            dilation: (1, 1),
+        }
+    }
+
+    /// Returns the (height, width) of the output feature maps.
+    pub fn output_size(&self) -> (usize, usize) {
+        let size = |n: usize, k: usize, s: usize, p: usize, d: usize| {
+            let span = d * (k - 1) + 1;
+            assert!(
+                n + 2 * p >= span,
+                "Window: kernel larger than the padded input"
+            );
+            (n + 2 * p - span) / s + 1
+        };
+        (
+            size(
+                self.input_size.0,
+                self.kernel.0,
+                self.stride.0,
+                self.padding.0,
+                self.dilation.0,
+            ),
+            size(
+                self.input_size.1,
+                self.kernel.1,
+                self.stride.1,
+                self.padding.1,
+                self.dilation.1,
+            ),
+        )
+    }
+
+    /// Returns, for every output position in row-major order, the input positions covered by the
+    /// window in row-major order, with `None` for the padding.
+    fn positions(&self) -> Vec<Vec<Option<usize>>> {
+        let (output_height, output_width) = self.output_size();
+        let (height, width) = self.input_size;
+        let mut positions = Vec::with_capacity(output_height * output_width);
+        for oy in 0..output_height {
+            for ox in 0..output_width {
+                let mut window = Vec::with_capacity(self.kernel.0 * self.kernel.1);
+                for ky in 0..self.kernel.0 {
+                    for kx in 0..self.kernel.1 {
+                        let y = (oy * self.stride.0 + ky * self.dilation.0) as isize
+                            - self.padding.0 as isize;
+                        let x = (ox * self.stride.1 + kx * self.dilation.1) as isize
+                            - self.padding.1 as isize;
+                        let inside =
+                            y >= 0 && x >= 0 && (y as usize) < height && (x as usize) < width;
+                        window.push(if inside {
+                            Some(y as usize * width + x as usize)
+                        } else {
+                            None
+                        });
+                    }
+                }
+                positions.push(window);
+            }
+        }
+        positions
+    }
+
+    fn input_len(&self) -> usize {
+        self.input_size.0 * self.input_size.1
+    }
+
+    fn output_len(&self) -> usize {
+        let (height, width) = self.output_size();
+        height * width
+    }
+}
+
+/// 2D convolution, computed as a matrix multiplication of the input patches (im2col) with the
+/// kernels, which are stored like the weights of a `Layer` with one column per output channel.
+#[derive(Debug)]
+pub struct Conv2d {
+    pub in_channels: usize,
+    pub out_channels: usize,
+    pub window: Window,
+    pub weights: Matrix,
+    pub biases: Vector,
+    activation: Option<Box<dyn Activation>>,
+    weight_gradients: Matrix,
+    bias_gradients: Vector,
+    activation_gradients: Vec<f64>,
+    /// (patches, y, z) of the last forward pass
+    cache: Option<(Matrix, Matrix, Matrix)>,
+}
+
+impl Conv2d {
+    /// Creates a convolution with weights drawn uniformly from +-1/sqrt(fan in).
+    pub fn new(
+        channels: (usize, usize),
+        kernel: (usize, usize),
+        input_size: (usize, usize),
+    ) -> Conv2d {
+        let fan_in = channels.0 * kernel.0 * kernel.1;
+        let bound = 1.0 / (fan_in as f64).sqrt();
+        let weights = Matrix::random((fan_in, channels.1), (-bound, bound));
+        Conv2d {
+            in_channels: channels.0,
+            out_channels: channels.1,
+            window: Window::new(kernel, input_size),
+            weight_gradients: Matrix::zero_like(&weights),
+            weights,
+            biases: Vector::random(channels.1, (-bound, bound)),
+            activation: None,
+            bias_gradients: Vector::zero(channels.1),
+            activation_gradients: vec![],
+            cache: None,
+        }
+    }
+
+    pub fn stride(mut self, stride: (usize, usize)) -> Self {
+        assert!(stride.0 > 0 && stride.1 > 0);
+        self.window.stride = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: (usize, usize)) -> Self {
+        self.window.padding = padding;
+        self
+    }
+
+    pub fn dilation(mut self, dilation: (usize, usize)) -> Self {
+        assert!(dilation.0 > 0 && dilation.1 > 0);
+        self.window.dilation = dilation;
+        self
+    }
+
+    pub fn with_activation<A>(mut self, activation: A) -> Self
+    where
+        A: Activation + 'static,
+    {
+        self.activation_gradients = vec![0.0; activation.parameters().len()];
+        self.activation = Some(Box::new(activation));
+        self
+    }
+
+    /// Number of outputs per sample.
+    pub fn output_len(&self) -> usize {
+        self.out_channels * self.window.output_len()
+    }
+
+    /// Gathers the input patches of every output position into the rows of a matrix, with the
+    /// rows of a sample next to each other.
+    fn im2col(&self, input: &Matrix, positions: &[Vec<Option<usize>>]) -> Matrix {
+        let (input_len, kernel_len) = (self.window.input_len(), positions[0].len());
+        let mut patches = Matrix::zero((
+            input.dims[0] * positions.len(),
+            self.in_channels * kernel_len,
+        ));
+        for s in 0..input.dims[0] {
+            for (p, window) in positions.iter().enumerate() {
+                for c in 0..self.in_channels {
+                    for (k, position) in window.iter().enumerate() {
+                        if let Some(position) = position {
+                            *patches.get_mut(s * positions.len() + p, c * kernel_len + k) =
+                                input.get(s, c * input_len + position);
+                        }
+                    }
+                }
+            }
+        }
+        patches
+    }
+
+    /// Accumulates the gradients wrt the patches back into the gradient wrt the input.
+    fn col2im(&self, dl_dpatches: &Matrix, positions: &[Vec<Option<usize>>]) -> Matrix {
+        let (input_len, kernel_len) = (self.window.input_len(), positions[0].len());
+        let samples = dl_dpatches.dims[0] / positions.len();
+        let mut dl_dx = Matrix::zero((samples, self.in_channels * input_len));
+        for s in 0..samples {
+            for (p, window) in positions.iter().enumerate() {
+                for c in 0..self.in_channels {
+                    for (k, position) in window.iter().enumerate() {
+                        if let Some(position) = position {
+                            *dl_dx.get_mut(s, c * input_len + position) +=
+                                dl_dpatches.get(s * positions.len() + p, c * kernel_len + k);
+                        }
+                    }
+                }
+            }
+        }
+        dl_dx
+    }
+
+    fn learnable_activation(&self) -> Option<&dyn Activation> {
+        self.activation.as_deref().filter(|a| a.learnable())
+    }
+}
+
+impl Module for Conv2d {
+    fn name(&self) -> &'static str {
+        "conv2d"
+    }
+
+    fn activation(&self) -> Option<&dyn Activation> {
+        self.activation.as_deref()
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        assert!(input.dims[1] == self.in_channels * self.window.input_len());
+        let positions = self.window.positions();
+        let patches = self.im2col(input, &positions);
+        let products = patches.multiply(&self.weights).add_row(&self.biases);
+        // one row per sample, with the feature maps of the output channels one after the other
+        let mut y = Matrix::zero((input.dims[0], self.output_len()));
+        for s in 0..input.dims[0] {
+            for p in 0..positions.len() {
+                for c in 0..self.out_channels {
+                    *y.get_mut(s, c * positions.len() + p) =
+                        products.get(s * positions.len() + p, c);
+                }
+            }
+        }
+        let z = if let Some(activation) = &self.activation {
+            Matrix::from_vector(activation.apply(y.to_vector()), (y.dims[0], y.dims[1]))
+        } else {
+            y.clone()
+        };
+        self.cache = Some((patches, y, z.clone()));
+        z
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let (patches, y, z) = self
+            .cache
+            .as_ref()
+            .expect("Conv2d::backward: forward was not called");
+        let (dl_dy, dl_da) = if let Some(activation) = &self.activation {
+            let (dl_dz, y, z) = (dl_dz.to_vector(), y.to_vector(), z.to_vector());
+            (
+                Matrix::from_vector(
+                    activation.backpropagate(&dl_dz, Some(&y), Some(&z)),
+                    (dl_dz.len() / self.output_len(), self.output_len()),
+                ),
+                activation.parameter_gradients(&dl_dz, Some(&y), Some(&z)),
+            )
+        } else {
+            (dl_dz.clone(), vec![])
+        };
+
+        let positions = self.window.positions();
+        let mut dl_dproducts = Matrix::zero((patches.dims[0], self.out_channels));
+        for s in 0..dl_dy.dims[0] {
+            for p in 0..positions.len() {
+                for c in 0..self.out_channels {
+                    *dl_dproducts.get_mut(s * positions.len() + p, c) =
+                        dl_dy.get(s, c * positions.len() + p);
+                }
+            }
+        }
+        self.weight_gradients = patches.transpose().multiply(&dl_dproducts);
+        self.bias_gradients = dl_dproducts.sum_rows();
+        self.activation_gradients = dl_da;
+        self.col2im(
+            &dl_dproducts.multiply(&self.weights.transpose()),
+            &positions,
+        )
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        let mut parameters = vec![&self.weights.data[..], &self.biases.data[..]];
+        if let Some(activation) = self.learnable_activation() {
+            parameters.push(activation.parameters());
+        }
+        parameters
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        let mut gradients = vec![
+            &self.weight_gradients.data[..],
+            &self.bias_gradients.data[..],
+        ];
+        if self.learnable_activation().is_some() {
+            gradients.push(&self.activation_gradients[..]);
+        }
+        gradients
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        let mut parameters = vec![
+            Parameter {
+                name: "weights",
+                value: &mut self.weights.data[..],
+                gradient: &mut self.weight_gradients.data[..],
+                frozen: false,
+                sparse: None,
+            },
+            Parameter {
+                name: "biases",
+                value: &mut self.biases.data[..],
+                gradient: &mut self.bias_gradients.data[..],
+                frozen: false,
+                sparse: None,
+            },
+        ];
+        if let Some(activation) = self.activation.as_mut().filter(|a| a.learnable()) {
+            parameters.push(Parameter {
+                name: "activation",
+                value: activation.parameters_mut(),
+                gradient: &mut self.activation_gradients[..],
+                frozen: false,
+                sparse: None,
+            });
+        }
+        parameters
+    }
+}
+
+/// 1D convolution over signals of `length` samples per channel.
+#[derive(Debug)]
+pub struct Conv1d {
+    pub conv: Conv2d,
+}
+
+impl Conv1d {
+    pub fn new(channels: (usize, usize), kernel: usize, length: usize) -> Conv1d {
+        Conv1d {
+            conv: Conv2d::new(channels, (1, kernel), (1, length)),
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        self.conv = self.conv.stride((1, stride));
+        self
+    }
+
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.conv = self.conv.padding((0, padding));
+        self
+    }
+
+    pub fn dilation(mut self, dilation: usize) -> Self {
+        self.conv = self.conv.dilation((1, dilation));
+        self
+    }
+
+    pub fn with_activation<A>(mut self, activation: A) -> Self
+    where
+        A: Activation + 'static,
+    {
+        self.conv = self.conv.with_activation(activation);
+        self
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.conv.output_len()
+    }
+}
+
+impl Module for Conv1d {
+    fn name(&self) -> &'static str {
+        "conv1d"
+    }
+
+    fn activation(&self) -> Option<&dyn Activation> {
+        self.conv.activation()
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        self.conv.forward_batch(input)
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        self.conv.backward_batch(dl_dz)
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        self.conv.parameters()
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        self.conv.gradients()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        self.conv.parameters_mut()
+    }
+}
+
+/// Pools every window of every channel to its maximum.
+#[derive(Debug, Clone)]
+pub struct MaxPool {
+    pub channels: usize,
+    pub window: Window,
+    /// input column of the maximum of every output of the last forward pass
+    cache: Option<Vec<Vec<usize>>>,
+}
+
+impl MaxPool {
+    /// Creates a pool over non-overlapping `kernel` windows.
+    pub fn new(channels: usize, kernel: (usize, usize), input_size: (usize, usize)) -> MaxPool {
+        MaxPool {
+            channels,
+            window: Window {
+                stride: kernel,
+                ..Window::new(kernel, input_size)
+            },
+            cache: None,
+        }
+    }
+
+    pub fn new_1d(channels: usize, kernel: usize, length: usize) -> MaxPool {
+        MaxPool::new(channels, (1, kernel), (1, length))
+    }
+
+    pub fn stride(mut self, stride: (usize, usize)) -> Self {
+        assert!(stride.0 > 0 && stride.1 > 0);
+        self.window.stride = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: (usize, usize)) -> Self {
+        assert!(padding.0 < self.window.kernel.0 && padding.1 < self.window.kernel.1);
+        self.window.padding = padding;
+        self
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.channels * self.window.output_len()
+    }
+}
+
+impl Module for MaxPool {
+    fn name(&self) -> &'static str {
+        "max_pool"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let input_len = self.window.input_len();
+        assert!(input.dims[1] == self.channels * input_len);
+        let positions = self.window.positions();
+        let mut output = Matrix::zero((input.dims[0], self.output_len()));
+        let mut argmax = vec![vec![0; self.output_len()]; input.dims[0]];
+        for (s, columns) in argmax.iter_mut().enumerate() {
+            for c in 0..self.channels {
+                for (p, window) in positions.iter().enumerate() {
+                    let (column, max) = window
+                        .iter()
+                        .flatten()
+                        .map(|position| c * input_len + position)
+                        .map(|column| (column, input.get(s, column)))
+                        .fold((0, f64::NEG_INFINITY), |a, b| if b.1 > a.1 { b } else { a });
+                    *output.get_mut(s, c * positions.len() + p) = max;
+                    columns[c * positions.len() + p] = column;
+                }
+            }
+        }
+        self.cache = Some(argmax);
+        output
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let argmax = self
+            .cache
+            .as_ref()
+            .expect("MaxPool::backward: forward was not called");
+        let mut dl_dx = Matrix::zero((dl_dz.dims[0], self.channels * self.window.input_len()));
+        for (s, columns) in argmax.iter().enumerate() {
+            for (o, column) in columns.iter().enumerate() {
+                *dl_dx.get_mut(s, *column) += dl_dz.get(s, o);
+            }
+        }
+        dl_dx
+    }
+}
+
+/// Pools every window of every channel to its mean, counting the padding as zeros.
+#[derive(Debug, Clone)]
+pub struct AvgPool {
+    pub channels: usize,
+    pub window: Window,
+}
+
+impl AvgPool {
+    /// Creates a pool over non-overlapping `kernel` windows.
+    pub fn new(channels: usize, kernel: (usize, usize), input_size: (usize, usize)) -> AvgPool {
+        AvgPool {
+            channels,
+            window: Window {
+                stride: kernel,
+                ..Window::new(kernel, input_size)
+            },
+        }
+    }
+
+    pub fn new_1d(channels: usize, kernel: usize, length: usize) -> AvgPool {
+        AvgPool::new(channels, (1, kernel), (1, length))
+    }
+
+    pub fn stride(mut self, stride: (usize, usize)) -> Self {
+        assert!(stride.0 > 0 && stride.1 > 0);
+        self.window.stride = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: (usize, usize)) -> Self {
+        self.window.padding = padding;
+        self
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.channels * self.window.output_len()
+    }
+}
+
+impl Module for AvgPool {
+    fn name(&self) -> &'static str {
+        "avg_pool"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let input_len = self.window.input_len();
+        assert!(input.dims[1] == self.channels * input_len);
+        let positions = self.window.positions();
+        let mut output = Matrix::zero((input.dims[0], self.output_len()));
+        for s in 0..input.dims[0] {
+            for c in 0..self.channels {
+                for (p, window) in positions.iter().enumerate() {
+                    let sum = window
+                        .iter()
+                        .flatten()
+                        .map(|position| input.get(s, c * input_len + position))
+                        .sum::<f64>();
+                    *output.get_mut(s, c * positions.len() + p) = sum / window.len() as f64;
+                }
+            }
+        }
+        output
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let input_len = self.window.input_len();
+        let positions = self.window.positions();
+        let mut dl_dx = Matrix::zero((dl_dz.dims[0], self.channels * input_len));
+        for s in 0..dl_dz.dims[0] {
+            for c in 0..self.channels {
+                for (p, window) in positions.iter().enumerate() {
+                    let gradient = dl_dz.get(s, c * positions.len() + p) / window.len() as f64;
+                    for position in window.iter().flatten() {
+                        *dl_dx.get_mut(s, c * input_len + position) += gradient;
+                    }
+                }
+            }
+        }
+        dl_dx
+    }
+}
+
+/// Pools every channel to its maximum over all positions.
+#[derive(Debug, Clone)]
+pub struct GlobalMaxPool {
+    pub channels: usize,
+    /// (input column of the maximum of every output, input width) of the last forward pass
+    cache: Option<(Vec<Vec<usize>>, usize)>,
+}
+
+impl GlobalMaxPool {
+    pub fn new(channels: usize) -> GlobalMaxPool {
+        GlobalMaxPool {
+            channels,
+            cache: None,
+        }
+    }
+}
+
+impl Module for GlobalMaxPool {
+    fn name(&self) -> &'static str {
+        "global_max_pool"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        assert!(input.dims[1].is_multiple_of(self.channels));
+        let input_len = input.dims[1] / self.channels;
+        let mut output = Matrix::zero((input.dims[0], self.channels));
+        let mut argmax = vec![vec![0; self.channels]; input.dims[0]];
+        for (s, columns) in argmax.iter_mut().enumerate() {
+            for (c, argmax_column) in columns.iter_mut().enumerate() {
+                let (column, max) = (c * input_len..(c + 1) * input_len)
+                    .map(|column| (column, input.get(s, column)))
+                    .fold((0, f64::NEG_INFINITY), |a, b| if b.1 > a.1 { b } else { a });
+                *output.get_mut(s, c) = max;
+                *argmax_column = column;
+            }
+        }
+        self.cache = Some((argmax, input.dims[1]));
+        output
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let (argmax, width) = self
+            .cache
+            .as_ref()
+            .expect("GlobalMaxPool::backward: forward was not called");
+        let mut dl_dx = Matrix::zero((dl_dz.dims[0], *width));
+        for (s, columns) in argmax.iter().enumerate() {
+            for (c, column) in columns.iter().enumerate() {
+                *dl_dx.get_mut(s, *column) += dl_dz.get(s, c);
+            }
+        }
+        dl_dx
+    }
+}
+
+/// Pools every channel to its mean over all positions.
+#[derive(Debug, Clone)]
+pub struct GlobalAvgPool {
+    pub channels: usize,
+    /// input width of the last forward pass
+    cache: Option<usize>,
+}
+
+impl GlobalAvgPool {
+    pub fn new(channels: usize) -> GlobalAvgPool {
+        GlobalAvgPool {
+            channels,
+            cache: None,
+        }
+    }
+}
+
+impl Module for GlobalAvgPool {
+    fn name(&self) -> &'static str {
+        "global_avg_pool"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        assert!(input.dims[1].is_multiple_of(self.channels));
+        let input_len = input.dims[1] / self.channels;
+        let mut output = Matrix::zero((input.dims[0], self.channels));
+        for s in 0..input.dims[0] {
+            for c in 0..self.channels {
+                *output.get_mut(s, c) = (c * input_len..(c + 1) * input_len)
+                    .map(|column| input.get(s, column))
+                    .sum::<f64>()
+                    / input_len as f64;
+            }
+        }
+        self.cache = Some(input.dims[1]);
+        output
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let width = self
+            .cache
+            .expect("GlobalAvgPool::backward: forward was not called");
+        let input_len = width / self.channels;
+        let mut dl_dx = Matrix::zero((dl_dz.dims[0], width));
+        for s in 0..dl_dz.dims[0] {
+            for column in 0..width {
+                *dl_dx.get_mut(s, column) = dl_dz.get(s, column / input_len) / input_len as f64;
+            }
+        }
+        dl_dx
+    }
+}
+
+/// Marks the transition from feature maps to dense layers. Samples are already stored flattened,
+/// so the batch passes through unchanged.
+#[derive(Debug, Clone, Copy)]
+pub struct Flatten;
+
+impl Module for Flatten {
+    fn name(&self) -> &'static str {
+        "flatten"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        input.clone()
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        dl_dz.clone()
+    }
+
+    fn forward_dual(&self, input: &DualVector) -> Option<DualVector> {
+        Some(input.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AvgPool, Conv1d, Conv2d, Flatten, GlobalAvgPool, GlobalMaxPool, MaxPool, Window};
+    use crate::activation::{PRelu, TANH};
+    use crate::gradcheck::{check_module_batch, max_error};
+    use crate::matrix::Matrix;
+    use crate::module::Module;
+    use crate::optimizer::Adam;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    #[test]
+    fn test_output_size() {
+        let window = Window::new((3, 3), (7, 8));
+        assert_eq!(window.output_size(), (5, 6));
+        let window = Window {
+            stride: (2, 3),
+            padding: (1, 1),
+            dilation: (2, 1),
+            ..window
+        };
+        assert_eq!(window.output_size(), (3, 3));
+    }
+
+    #[test]
+    fn test_conv1d() {
+        let mut conv = Conv1d::new((1, 1), 3, 5).padding(1);
+        conv.conv.weights = Matrix::from(vec![vec![1.0], vec![0.0], vec![-1.0]]);
+        conv.conv.biases = Vector::from(vec![0.5]);
+        let output = conv.forward_batch(&Matrix::from(vec![vec![1.0, 2.0, 3.0, 4.0, 5.0]]));
+        assert_eq!(output.row(0), vec![-1.5, -1.5, -1.5, -1.5, 4.5]);
+
+        let mut dilated = Conv1d::new((1, 1), 2, 5).dilation(3);
+        dilated.conv.weights = Matrix::from(vec![vec![1.0], vec![1.0]]);
+        dilated.conv.biases = Vector::from(vec![0.0]);
+        let output = dilated.forward_batch(&Matrix::from(vec![vec![1.0, 2.0, 3.0, 4.0, 5.0]]));
+        assert_eq!(output.row(0), vec![5.0, 7.0]);
+
+        let activated = Conv1d::new((1, 1), 2, 5).with_activation(TANH);
+        assert_eq!(activated.activation().map(|a| a.name()), Some("tanh"));
+    }
+
+    #[test]
+    fn test_pooling() {
+        let input = Matrix::from(vec![(0..16).map(|x| x as f64).collect::<Vec<f64>>()]);
+        let mut max = MaxPool::new(1, (2, 2), (4, 4));
+        assert_eq!(max.forward_batch(&input).row(0), vec![5.0, 7.0, 13.0, 15.0]);
+        let mut avg = AvgPool::new(1, (2, 2), (4, 4));
+        assert_eq!(avg.forward_batch(&input).row(0), vec![2.5, 4.5, 10.5, 12.5]);
+        let mut global = GlobalMaxPool::new(2);
+        assert_eq!(global.forward_batch(&input).row(0), vec![7.0, 15.0]);
+        let mut global = GlobalAvgPool::new(2);
+        assert_eq!(global.forward_batch(&input).row(0), vec![3.5, 11.5]);
+    }
+
+    #[test]
+    fn test_gradients() {
+        let modules: Vec<(Box<dyn Module>, usize)> = vec![
+            (Box::new(Conv2d::new((2, 3), (3, 3), (5, 5))), 50),
+            (
+                Box::new(
+                    Conv2d::new((2, 2), (2, 3), (5, 6))
+                        .stride((2, 1))
+                        .padding((1, 2))
+                        .dilation((2, 2))
+                        .with_activation(TANH),
+                ),
+                60,
+            ),
+            (Box::new(Conv1d::new((3, 2), 3, 7).stride(2).padding(1)), 21),
+            (
+                Box::new(Conv1d::new((1, 2), 2, 6).with_activation(PRelu::new(0.2))),
+                6,
+            ),
+            (Box::new(MaxPool::new(2, (2, 2), (4, 4))), 32),
+            (
+                Box::new(
+                    AvgPool::new(2, (3, 3), (4, 4))
+                        .stride((1, 1))
+                        .padding((1, 1)),
+                ),
+                32,
+            ),
+            (Box::new(MaxPool::new_1d(3, 2, 6)), 18),
+            (Box::new(GlobalMaxPool::new(3)), 12),
+            (Box::new(GlobalAvgPool::new(3)), 12),
+            (Box::new(Flatten), 5),
+        ];
+        for (mut module, width) in modules {
+            let input = Matrix::random((2, width), (-1.0, 1.0));
+            let output_width = module.forward_batch(&input).dims[1];
+            let target = Matrix::random((2, output_width), (-1.0, 1.0));
+            let result = check_module_batch(module.as_mut(), &input, &target);
+            assert!(max_error(&result) < 1e-6, "{}: {:?}", module.name(), result);
+        }
+    }
+
+    #[test]
+    fn test_image_classifier() {
+        // tells horizontal from vertical bars in 6x6 images
+        let image = |horizontal: bool, k: usize| {
+            let pixels = (0..36)
+                .map(|i| {
+                    let on = if horizontal { i / 6 == k } else { i % 6 == k };
+                    if on {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                })
+                .collect::<Vec<f64>>();
+            Vector::from(pixels)
+        };
+        let mut inputs = vec![];
+        let mut targets = vec![];
+        for k in 0..6 {
+            for horizontal in [true, false] {
+                inputs.push(image(horizontal, k));
+                let target = if horizontal { [1.0, 0.0] } else { [0.0, 1.0] };
+                targets.push(Vector::from(target.to_vec()));
+            }
+        }
+        let (inputs, targets) = (Matrix::from_rows(&inputs), Matrix::from_rows(&targets));
+
+        let conv = Conv2d::new((1, 8), (3, 3), (6, 6)).with_activation(TANH);
+        let pool = MaxPool::new(8, (2, 2), (4, 4));
+        let dense = Layer::random((pool.output_len(), 2), (-0.5, 0.5));
+        let mut nn = NeuralNetwork::from_modules(vec![
+            Box::new(conv),
+            Box::new(pool),
+            Box::new(Flatten),
+            Box::new(dense),
+        ])
+        .with_optimizer(Adam::new(0.05));
+        for _ in 0..200 {
+            nn.train_batch(&inputs, &targets);
+        }
+        let output = nn.forward_batch(&inputs);
+        let loss = nn.loss_batch(&output, &targets);
+        assert!(loss < 0.05, "{}", loss);
+        for i in 0..12 {
+            assert!((output.get(i, 0) > 0.5) == (targets.get(i, 0) > 0.5));
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt::{self, Debug};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::matrix::Matrix;
+use crate::vector::Vector;
+
+/// Indexed collection of `(input, target)` samples.
+pub trait Dataset {
+    fn len(&self) -> usize;
+
+    fn get(&self, index: usize) -> (Vector, Vector);
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stacks the samples into an input and a target matrix, with one sample per row.
+    ///
+    /// An empty dataset gives 0x0 matrices, having no sample to tell their widths.
+    fn to_matrices(&self) -> (Matrix, Matrix) {
+        let indices = (0..self.len()).collect::<Vec<usize>>();
+        stack(self, &indices)
+    }
+}
+
+/// Dataset of samples stored in memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InMemoryDataset {
+    pub inputs: Vec<Vector>,
+    pub targets: Vec<Vector>,
+}
+
+impl InMemoryDataset {
+    pub fn new(inputs: Vec<Vector>, targets: Vec<Vector>) -> InMemoryDataset {
+        assert!(inputs.len() == targets.len());
+        InMemoryDataset { inputs, targets }
+    }
+
+    /// Splits matrices with one sample per row into samples.
+    pub fn from_matrices(inputs: &Matrix, targets: &Matrix) -> InMemoryDataset {
+        InMemoryDataset::new(inputs.rows(), targets.rows())
+    }
+}
+
+impl Dataset for InMemoryDataset {
+    fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    fn get(&self, index: usize) -> (Vector, Vector) {
+        (self.inputs[index].clone(), self.targets[index].clone())
+    }
+}
+
+/// Dataset of `len` samples computed on demand from their index, e.g. random points for
+/// synthetic tasks.
+pub struct GeneratedDataset<F> {
+    len: usize,
+    generator: F,
+}
+
+impl<F> GeneratedDataset<F>
+where
+    F: Fn(usize) -> (Vector, Vector),
+{
+    pub fn new(len: usize, generator: F) -> GeneratedDataset<F> {
+        GeneratedDataset { len, generator }
+    }
+}
+
+impl<F> Dataset for GeneratedDataset<F>
+where
+    F: Fn(usize) -> (Vector, Vector),
+{
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, index: usize) -> (Vector, Vector) {
+        assert!(index < self.len);
+        (self.generator)(index)
+    }
+}
+
+impl<F> Debug for GeneratedDataset<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeneratedDataset")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+/// View of some samples of another dataset.
+#[derive(Debug, Clone)]
+pub struct Subset<'a, D: ?Sized> {
+    dataset: &'a D,
+    pub indices: Vec<usize>,
+}
+
+impl<'a, D> Subset<'a, D>
+where
+    D: Dataset + ?Sized,
+{
+    pub fn new(dataset: &'a D, indices: Vec<usize>) -> Subset<'a, D> {
+        assert!(indices.iter().all(|i| *i < dataset.len()));
+        Subset { dataset, indices }
+    }
+}
+
+impl<D> Dataset for Subset<'_, D>
+where
+    D: Dataset + ?Sized,
+{
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn get(&self, index: usize) -> (Vector, Vector) {
+        self.dataset.get(self.indices[index])
+    }
+}
+
+/// Handling of the last batch of an epoch when the batch size doesn't divide the dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LastBatch {
+    /// Keeps the smaller batch.
+    Keep,
+    /// Skips the remaining samples.
+    Drop,
+    /// Fills the batch up with samples from the start of the epoch.
+    Pad,
+}
+
+/// Iterates over a dataset in batches of samples stacked into matrices, with one sample per row.
+///
+/// `Trainer::fit_dataset` trains a network on the batches of a loader.
+#[derive(Debug)]
+pub struct DataLoader<'a, D: ?Sized> {
+    dataset: &'a D,
+    pub batch_size: usize,
+    pub last_batch: LastBatch,
+    /// shuffles the samples every epoch if set
+    rng: Option<StdRng>,
+}
+
+impl<'a, D> DataLoader<'a, D>
+where
+    D: Dataset + ?Sized,
+{
+    pub fn new(dataset: &'a D, batch_size: usize) -> DataLoader<'a, D> {
+        assert!(batch_size > 0);
+        DataLoader {
+            dataset,
+            batch_size,
+            last_batch: LastBatch::Keep,
+            rng: None,
+        }
+    }
+
+    /// Shuffles the samples every epoch, in an order determined by `seed`.
+    pub fn shuffle(mut self, seed: u64) -> Self {
+        self.rng = Some(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    pub fn drop_last(mut self) -> Self {
+        self.last_batch = LastBatch::Drop;
+        self
+    }
+
+    pub fn pad_last(mut self) -> Self {
+        self.last_batch = LastBatch::Pad;
+        self
+    }
+
+    /// Number of batches in an epoch.
+    pub fn num_batches(&self) -> usize {
+        let (len, size) = (self.dataset.len(), self.batch_size);
+        match self.last_batch {
+            LastBatch::Drop => len / size,
+            LastBatch::Keep | LastBatch::Pad => len.div_ceil(size),
+        }
+    }
+
+    /// Returns the batches of a new epoch as `(inputs, targets)` pairs.
+    pub fn epoch(&mut self) -> impl Iterator<Item = (Matrix, Matrix)> + '_ {
+        let mut order = (0..self.dataset.len()).collect::<Vec<usize>>();
+        if let Some(rng) = &mut self.rng {
+            order.shuffle(rng);
+        }
+        let (dataset, size, last_batch) = (self.dataset, self.batch_size, self.last_batch);
+        let batches = self.num_batches();
+        (0..batches).map(move |b| {
+            let mut indices = order[b * size..order.len().min((b + 1) * size)].to_vec();
+            if last_batch == LastBatch::Pad {
+                let missing = size - indices.len();
+                indices.extend(order.iter().cycle().take(missing));
+            }
+            stack(dataset, &indices)
+        })
+    }
+}
+
+fn stack<D>(dataset: &D, indices: &[usize]) -> (Matrix, Matrix)
+where
+    D: Dataset + ?Sized,
+{
+    if indices.is_empty() {
+        return (Matrix::zero((0, 0)), Matrix::zero((0, 0)));
+    }
+    let (inputs, targets): (Vec<Vector>, Vec<Vector>) =
+        indices.iter().map(|i| dataset.get(*i)).unzip();
+    (Matrix::from_rows(&inputs), Matrix::from_rows(&targets))
+}
+
+fn shuffled(len: usize, seed: u64) -> Vec<usize> {
+    let mut order = (0..len).collect::<Vec<usize>>();
+    order.shuffle(&mut StdRng::seed_from_u64(seed));
+    order
+}
+
+/// Randomly splits a dataset into a training and a test subset, with `test_fraction` of the
+/// samples in the test subset.
+pub fn train_test_split<D>(
+    dataset: &D,
+    test_fraction: f64,
+    seed: u64,
+) -> (Subset<'_, D>, Subset<'_, D>)
+where
+    D: Dataset + ?Sized,
+{
+    assert!((0.0..=1.0).contains(&test_fraction));
+    let mut order = shuffled(dataset.len(), seed);
+    let test =
+        order.split_off(dataset.len() - (dataset.len() as f64 * test_fraction).round() as usize);
+    (Subset::new(dataset, order), Subset::new(dataset, test))
+}
+
+/// Randomly splits a dataset into `k` folds and returns a `(train, validation)` pair for each,
+/// validating on the fold and training on the others.
+pub fn k_fold<D>(dataset: &D, k: usize, seed: u64) -> Vec<(Subset<'_, D>, Subset<'_, D>)>
+where
+    D: Dataset + ?Sized,
+{
+    assert!(k > 1 && k <= dataset.len());
+    let order = shuffled(dataset.len(), seed);
+    // the first len % k folds get one more sample
+    let bounds = (0..=k)
+        .map(|i| i * (dataset.len() / k) + i.min(dataset.len() % k))
+        .collect::<Vec<usize>>();
+    bounds
+        .windows(2)
+        .map(|fold| {
+            let validation = order[fold[0]..fold[1]].to_vec();
+            let train = [&order[..fold[0]], &order[fold[1]..]].concat();
+            (
+                Subset::new(dataset, train),
+                Subset::new(dataset, validation),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{k_fold, train_test_split, DataLoader, Dataset, GeneratedDataset};
+    use crate::vector::Vector;
+
+    /// Samples whose input and target are both their index.
+    fn dataset(len: usize) -> GeneratedDataset<impl Fn(usize) -> (Vector, Vector)> {
+        GeneratedDataset::new(len, |i| {
+            (Vector::from(vec![i as f64]), Vector::from(vec![i as f64]))
+        })
+    }
+
+    /// Returns the indices in every batch of an epoch.
+    fn epoch<D: Dataset>(loader: &mut DataLoader<'_, D>) -> Vec<Vec<usize>> {
+        loader
+            .epoch()
+            .map(|(inputs, targets)| {
+                assert_eq!(inputs.to_vector(), targets.to_vector());
+                inputs.to_vector().iter().map(|x| *x as usize).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_data_loader() {
+        let data = dataset(10);
+        let batches = epoch(&mut DataLoader::new(&data, 4));
+        assert_eq!(
+            batches,
+            vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]
+        );
+        let batches = epoch(&mut DataLoader::new(&data, 4).drop_last());
+        assert_eq!(batches.len(), 2);
+        let batches = epoch(&mut DataLoader::new(&data, 4).pad_last());
+        assert_eq!(batches[2], vec![8, 9, 0, 1]);
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let data = dataset(20);
+        let mut loader = DataLoader::new(&data, 6).shuffle(7);
+        let (first, second) = (epoch(&mut loader), epoch(&mut loader));
+        assert!(first != second);
+        assert_eq!(first, epoch(&mut DataLoader::new(&data, 6).shuffle(7)));
+        let mut samples = first.concat();
+        samples.sort();
+        assert_eq!(samples, (0..20).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_splits() {
+        let data = dataset(10);
+        let (train, test) = train_test_split(&data, 0.3, 1);
+        assert_eq!((train.len(), test.len()), (7, 3));
+        assert!(test.indices.iter().all(|i| !train.indices.contains(i)));
+        let (_, empty) = train_test_split(&data, 0.0, 1);
+        assert!(empty.is_empty());
+        assert_eq!(empty.to_matrices().0.dims, vec![0, 0]);
+
+        let folds = k_fold(&data, 3, 1);
+        assert_eq!(
+            folds.iter().map(|(_, v)| v.len()).collect::<Vec<usize>>(),
+            vec![4, 3, 3]
+        );
+        let mut validated = folds
+            .iter()
+            .flat_map(|(train, validation)| {
+                assert_eq!(train.len() + validation.len(), 10);
+                validation.indices.clone()
+            })
+            .collect::<Vec<usize>>();
+        validated.sort();
+        assert_eq!(validated, (0..10).collect::<Vec<usize>>());
+        let (inputs, _) = folds[0].1.to_matrices();
+        assert_eq!(inputs.dims, vec![4, 1]);
+    }
+}
@@ -0,0 +1,299 @@
+use rand::Rng;
+
+use crate::activation::Selu;
+use crate::dual::DualVector;
+use crate::matrix::Matrix;
+use crate::module::Module;
+
+/// Samples from the standard normal distribution with the Box-Muller transform.
+fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Samples a mask with the dims of `input` that is 1 with probability `1 - p` and 0 otherwise.
+fn keep_mask(input: &Matrix, p: f64) -> Matrix {
+    let mut rng = rand::thread_rng();
+    let mut mask = Matrix::zero((input.dims[0], input.dims[1]));
+    for m in mask.data.iter_mut() {
+        if rng.gen::<f64>() >= p {
+            *m = 1.0;
+        }
+    }
+    mask
+}
+
+/// Zeroes every input with probability `p` during training, scaling the others by `1 / (1 - p)`
+/// so that nothing needs to change at inference, where it is the identity.
+#[derive(Debug, Clone)]
+pub struct Dropout {
+    pub p: f64,
+    training: bool,
+    /// scaled mask of the last forward pass
+    mask: Option<Matrix>,
+}
+
+impl Dropout {
+    pub fn new(p: f64) -> Dropout {
+        assert!((0.0..1.0).contains(&p));
+        Dropout {
+            p,
+            training: true,
+            mask: None,
+        }
+    }
+}
+
+impl Module for Dropout {
+    fn name(&self) -> &'static str {
+        "dropout"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        if !self.training {
+            self.mask = None;
+            return input.clone();
+        }
+        let mut mask = keep_mask(input, self.p);
+        for m in mask.data.iter_mut() {
+            *m /= 1.0 - self.p;
+        }
+        let output = input.hadamard(&mask);
+        self.mask = Some(mask);
+        output
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        match &self.mask {
+            Some(mask) => dl_dz.hadamard(mask),
+            None => dl_dz.clone(),
+        }
+    }
+
+    /// Runs as at inference.
+    fn forward_dual(&self, input: &DualVector) -> Option<DualVector> {
+        Some(input.clone())
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+/// Dropout for self-normalizing networks with `SELU` activations: dropped inputs are set to the
+/// negative saturation value of `SELU`, and the output is rescaled so that inputs with zero mean
+/// and unit variance keep them.
+#[derive(Debug, Clone)]
+pub struct AlphaDropout {
+    pub p: f64,
+    training: bool,
+    /// keep mask of the last forward pass
+    mask: Option<Matrix>,
+}
+
+impl AlphaDropout {
+    /// Value that `SELU` saturates to for large negative inputs
+    const SATURATION: f64 = -Selu::SCALE * Selu::ALPHA;
+
+    pub fn new(p: f64) -> AlphaDropout {
+        assert!((0.0..1.0).contains(&p));
+        AlphaDropout {
+            p,
+            training: true,
+            mask: None,
+        }
+    }
+
+    /// Returns the affine correction (a, b) applied after dropping.
+    fn affine(&self) -> (f64, f64) {
+        let alpha = AlphaDropout::SATURATION;
+        let a = ((1.0 - self.p) * (1.0 + self.p * alpha * alpha)).powf(-0.5);
+        (a, -a * alpha * self.p)
+    }
+}
+
+impl Module for AlphaDropout {
+    fn name(&self) -> &'static str {
+        "alpha_dropout"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        if !self.training {
+            self.mask = None;
+            return input.clone();
+        }
+        let mask = keep_mask(input, self.p);
+        let (a, b) = self.affine();
+        let mut output = Matrix::zero((input.dims[0], input.dims[1]));
+        for i in 0..input.dims[0] {
+            for j in 0..input.dims[1] {
+                let keep = mask.get(i, j);
+                let dropped = input.get(i, j) * keep + AlphaDropout::SATURATION * (1.0 - keep);
+                *output.get_mut(i, j) = a * dropped + b;
+            }
+        }
+        self.mask = Some(mask);
+        output
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        match &self.mask {
+            Some(mask) => {
+                let (a, _) = self.affine();
+                let mut dl_dx = dl_dz.hadamard(mask);
+                for d in dl_dx.data.iter_mut() {
+                    *d *= a;
+                }
+                dl_dx
+            }
+            None => dl_dz.clone(),
+        }
+    }
+
+    /// Runs as at inference.
+    fn forward_dual(&self, input: &DualVector) -> Option<DualVector> {
+        Some(input.clone())
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+/// Adds zero-mean gaussian noise with standard deviation `stddev` to every input during training.
+#[derive(Debug, Clone)]
+pub struct GaussianNoise {
+    pub stddev: f64,
+    training: bool,
+}
+
+impl GaussianNoise {
+    pub fn new(stddev: f64) -> GaussianNoise {
+        assert!(stddev >= 0.0);
+        GaussianNoise {
+            stddev,
+            training: true,
+        }
+    }
+}
+
+impl Module for GaussianNoise {
+    fn name(&self) -> &'static str {
+        "gaussian_noise"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let mut output = Matrix::zero((input.dims[0], input.dims[1]));
+        let mut rng = rand::thread_rng();
+        for i in 0..input.dims[0] {
+            for j in 0..input.dims[1] {
+                let noise = if self.training {
+                    self.stddev * standard_normal(&mut rng)
+                } else {
+                    0.0
+                };
+                *output.get_mut(i, j) = input.get(i, j) + noise;
+            }
+        }
+        output
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        dl_dz.clone()
+    }
+
+    /// Runs as at inference.
+    fn forward_dual(&self, input: &DualVector) -> Option<DualVector> {
+        Some(input.clone())
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{standard_normal, AlphaDropout, Dropout, GaussianNoise};
+    use crate::dual::jvp;
+    use crate::matrix::Matrix;
+    use crate::module::Module;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const SAMPLES: usize = 20000;
+
+    /// Returns the mean and variance of the entries of `matrix`.
+    fn moments(matrix: &Matrix) -> (f64, f64) {
+        let n = matrix.size as f64;
+        let mean = matrix.data.iter().sum::<f64>() / n;
+        let variance = matrix.data.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        (mean, variance)
+    }
+
+    fn normal_batch() -> Matrix {
+        let mut rng = rand::thread_rng();
+        let mut batch = Matrix::zero((SAMPLES, 2));
+        for x in batch.data.iter_mut() {
+            *x = standard_normal(&mut rng);
+        }
+        batch
+    }
+
+    #[test]
+    fn test_dropout() {
+        let mut dropout = Dropout::new(0.25);
+        let input = Matrix::from(vec![vec![1.0; 2]; SAMPLES]);
+        let output = dropout.forward_batch(&input);
+        let (mean, _) = moments(&output);
+        assert!((mean - 1.0).abs() < 0.05);
+        assert!(output
+            .data
+            .iter()
+            .all(|x| *x == 0.0 || (x - 4.0 / 3.0).abs() < 1e-12));
+
+        // the gradient flows through the kept inputs only
+        let dl_dx = dropout.backward_batch(&input);
+        assert_eq!(dl_dx.data, output.data);
+
+        dropout.set_training(false);
+        assert_eq!(dropout.forward_batch(&input).data, input.data);
+    }
+
+    #[test]
+    fn test_alpha_dropout() {
+        let mut dropout = AlphaDropout::new(0.2);
+        let (mean, variance) = moments(&dropout.forward_batch(&normal_batch()));
+        assert!(mean.abs() < 0.05, "{}", mean);
+        assert!((variance - 1.0).abs() < 0.05, "{}", variance);
+    }
+
+    #[test]
+    fn test_gaussian_noise() {
+        let mut noise = GaussianNoise::new(0.5);
+        let input = Matrix::zero((SAMPLES, 2));
+        let (mean, variance) = moments(&noise.forward_batch(&input));
+        assert!(mean.abs() < 0.05);
+        assert!((variance - 0.25).abs() < 0.05);
+        noise.set_training(false);
+        assert_eq!(noise.forward_batch(&input).data, input.data);
+    }
+
+    #[test]
+    fn test_network_mode() {
+        let mut nn = NeuralNetwork::from_modules(vec![
+            Box::new(Layer::random((2, 8), (-1.0, 1.0))),
+            Box::new(Dropout::new(0.5)),
+            Box::new(Layer::random((8, 2), (-1.0, 1.0))),
+        ]);
+        assert!(nn.is_training());
+        nn.set_training(false);
+        let input = Vector::from(vec![0.3, -0.8]);
+        let first = nn.forward(input.clone()).clone();
+        let second = nn.forward(input.clone()).clone();
+        assert_eq!(first, second);
+        // noise layers are the identity for forward-mode differentiation
+        assert_eq!(jvp(&nn, &input, &input).unwrap().0, first);
+    }
+}
@@ -0,0 +1,337 @@
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::matrix::{Matrix, Multiply};
+use crate::vector::Vector;
+use crate::NeuralNetwork;
+
+/// A dual number `re + eps * ε` with `ε^2 = 0`.
+///
+/// Evaluating a function on `x + v * ε` yields `f(x) + (f'(x) * v) * ε`, so the `eps` part carries
+/// the directional derivative alongside the value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dual {
+    pub re: f64,
+    pub eps: f64,
+}
+
+impl Dual {
+    pub fn new(re: f64, eps: f64) -> Dual {
+        Dual { re, eps }
+    }
+
+    pub fn constant(re: f64) -> Dual {
+        Dual::new(re, 0.0)
+    }
+
+    pub fn variable(re: f64) -> Dual {
+        Dual::new(re, 1.0)
+    }
+
+    pub fn exp(self) -> Dual {
+        let exp = self.re.exp();
+        Dual::new(exp, self.eps * exp)
+    }
+
+    pub fn ln(self) -> Dual {
+        Dual::new(self.re.ln(), self.eps / self.re)
+    }
+}
+
+impl From<f64> for Dual {
+    fn from(re: f64) -> Dual {
+        Dual::constant(re)
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+
+    fn add(self, other: Dual) -> Dual {
+        Dual::new(self.re + other.re, self.eps + other.eps)
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+
+    fn sub(self, other: Dual) -> Dual {
+        Dual::new(self.re - other.re, self.eps - other.eps)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+
+    fn mul(self, other: Dual) -> Dual {
+        Dual::new(
+            self.re * other.re,
+            self.re * other.eps + self.eps * other.re,
+        )
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+
+    fn div(self, other: Dual) -> Dual {
+        Dual::new(
+            self.re / other.re,
+            (self.eps * other.re - self.re * other.eps) / (other.re * other.re),
+        )
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+
+    fn neg(self) -> Dual {
+        Dual::new(-self.re, -self.eps)
+    }
+}
+
+impl Add<f64> for Dual {
+    type Output = Dual;
+
+    fn add(self, c: f64) -> Dual {
+        Dual::new(self.re + c, self.eps)
+    }
+}
+
+impl Mul<f64> for Dual {
+    type Output = Dual;
+
+    fn mul(self, c: f64) -> Dual {
+        Dual::new(self.re * c, self.eps * c)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DualVector {
+    pub data: Vec<Dual>,
+}
+
+impl DualVector {
+    /// Builds the dual vector `primal + tangent * ε`.
+    pub fn new(primal: &Vector, tangent: &Vector) -> DualVector {
+        assert!(primal.len() == tangent.len());
+        DualVector {
+            data: primal
+                .iter()
+                .zip(tangent.iter())
+                .map(|(re, eps)| Dual::new(*re, *eps))
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn primal(&self) -> Vector {
+        self.data.iter().map(|x| x.re).collect::<Vec<f64>>().into()
+    }
+
+    pub fn tangent(&self) -> Vector {
+        self.data.iter().map(|x| x.eps).collect::<Vec<f64>>().into()
+    }
+
+    pub fn add(&self, vec2: &Vector) -> DualVector {
+        assert!(self.len() == vec2.len());
+        self.data
+            .iter()
+            .zip(vec2.iter())
+            .map(|(x, y)| *x + *y)
+            .collect::<Vec<Dual>>()
+            .into()
+    }
+
+    pub fn map<F>(&self, f: F) -> DualVector
+    where
+        F: Fn(Dual) -> Dual,
+    {
+        self.data
+            .iter()
+            .map(|x| f(*x))
+            .collect::<Vec<Dual>>()
+            .into()
+    }
+
+    pub fn softmax(&self) -> DualVector {
+        let exp = self.map(Dual::exp);
+        let exp_sum = exp.data.iter().fold(Dual::constant(0.0), |acc, x| acc + *x);
+        exp.map(|x| x / exp_sum)
+    }
+}
+
+impl From<Vec<Dual>> for DualVector {
+    fn from(data: Vec<Dual>) -> DualVector {
+        DualVector { data }
+    }
+}
+
+impl Multiply<DualVector, DualVector> for Matrix {
+    fn multiply(&self, vec: &DualVector) -> DualVector {
+        assert!(self.dims[1] == vec.len());
+        let mut res = vec![Dual::constant(0.0); self.dims[0]];
+        for (i, r) in res.iter_mut().enumerate() {
+            for j in 0..self.dims[1] {
+                *r = *r + vec.data[j] * self.get(i, j);
+            }
+        }
+        DualVector::from(res)
+    }
+}
+
+/// A module of a network does not support forward-mode differentiation, e.g. a recurrent layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedModule {
+    pub name: &'static str,
+}
+
+impl fmt::Display for UnsupportedModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} does not support forward-mode differentiation",
+            self.name
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedModule {}
+
+/// Computes the output of `nn` at `x` and the Jacobian-vector product `J(x) v`, i.e. the
+/// directional derivative of the output along `v`, in a single forward pass.
+///
+/// Returns the tuple (output, jvp)
+pub fn jvp(
+    nn: &NeuralNetwork,
+    x: &Vector,
+    v: &Vector,
+) -> Result<(Vector, Vector), UnsupportedModule> {
+    let output = nn.forward_dual(DualVector::new(x, v))?;
+    Ok((output.primal(), output.tangent()))
+}
+
+/// Computes the Jacobian of the output of `nn` wrt its input at `x`, with one row per output and
+/// one column per input.
+pub fn jacobian(nn: &NeuralNetwork, x: &Vector) -> Result<Matrix, UnsupportedModule> {
+    let mut columns = Vec::with_capacity(x.len());
+    for i in 0..x.len() {
+        let mut basis = Vector::zero(x.len());
+        basis.data[i] = 1.0;
+        columns.push(jvp(nn, x, &basis)?.1.data);
+    }
+    Ok(Matrix::from(columns).transpose())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{jacobian, jvp, Dual, DualVector, UnsupportedModule};
+    use crate::activation::{LELU, RELU};
+    use crate::matrix::Matrix;
+    use crate::module::Module;
+    use crate::ops::Scale;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    /// Identity module without forward-mode support.
+    #[derive(Debug)]
+    struct Opaque;
+
+    impl Module for Opaque {
+        fn name(&self) -> &'static str {
+            "opaque"
+        }
+
+        fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+            input.clone()
+        }
+
+        fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+            dl_dz.clone()
+        }
+    }
+
+    #[test]
+    fn test_dual_arithmetic() {
+        // f(x) = x * exp(x) / (x + 1), f'(x) = exp(x) * (x^2 + x + 1) / (x + 1)^2
+        let x = Dual::variable(0.5);
+        let result = x * x.exp() / (x + 1.0);
+        let expected = 0.5_f64.exp() * 1.75 / 2.25;
+        assert!((result.re - 0.5 * 0.5_f64.exp() / 1.5).abs() < EPSILON);
+        assert!((result.eps - expected).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_dual_softmax() {
+        let x = Vector::from(vec![-1.0, 0.0, 0.5]);
+        let v = Vector::from(vec![1.0, 0.0, 0.0]);
+        let result = DualVector::new(&x, &v).softmax();
+        let s = x.softmax();
+        assert_eq!(result.primal(), s);
+        // d s_i / d x_0 = s_i * (delta_i0 - s_0)
+        for i in 0..3 {
+            let delta = if i == 0 { 1.0 } else { 0.0 };
+            assert!((result.tangent()[i] - s[i] * (delta - s[0])).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_jvp() {
+        let mut nn = NeuralNetwork::new(vec![
+            Layer::random((3, 4), (-1.0, 1.0)).with_activation(LELU),
+            Layer::random((4, 4), (-1.0, 1.0)).with_activation(RELU),
+            Layer::random((4, 2), (-1.0, 1.0)),
+        ]);
+        let x = Vector::random(3, (-1.0, 1.0));
+        let v = Vector::random(3, (-1.0, 1.0));
+        let (output, tangent) = jvp(&nn, &x, &v).unwrap();
+        assert_eq!(&output, nn.forward(x.clone()));
+
+        let step = 1e-6;
+        let plus = nn.forward(x.add(&v.scale(step))).clone();
+        let minus = nn.forward(x.subtract(&v.scale(step))).clone();
+        for i in 0..2 {
+            let numeric = (plus[i] - minus[i]) / (2.0 * step);
+            assert!((tangent[i] - numeric).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_jacobian() {
+        let nn = NeuralNetwork::new(vec![
+            Layer::random((3, 4), (-1.0, 1.0)).with_activation(LELU),
+            Layer::random((4, 2), (-1.0, 1.0)),
+        ]);
+        let x = Vector::random(3, (-1.0, 1.0));
+        let v = Vector::from(vec![0.5, -1.0, 2.0]);
+        let result = jacobian(&nn, &x).unwrap();
+        assert_eq!(result.dims, vec![2, 3]);
+        let (_, tangent) = jvp(&nn, &x, &v).unwrap();
+        for i in 0..2 {
+            let row = (0..3).map(|j| result.get(i, j) * v[j]).sum::<f64>();
+            assert!((row - tangent[i]).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_module() {
+        let x = Vector::random(3, (-1.0, 1.0));
+        let nn = NeuralNetwork::from_modules(vec![
+            Box::new(Layer::random((3, 4), (-1.0, 1.0))),
+            Box::new(Opaque),
+            Box::new(Layer::random((4, 2), (-1.0, 1.0))),
+        ]);
+        let unsupported = UnsupportedModule { name: "opaque" };
+        assert_eq!(jvp(&nn, &x, &x).unwrap_err(), unsupported);
+        assert!(jacobian(&nn, &x).is_err());
+    }
+}
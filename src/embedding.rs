@@ -0,0 +1,222 @@
+use crate::matrix::Matrix;
+use crate::module::{Module, Parameter, SparseRows};
+
+/// Looks up a row of a learned table for every token id, turning rows of ids into rows of
+/// embeddings, one after the other.
+///
+/// Equivalent to a dense `Layer` without biases on one-hot inputs, but only the rows of the ids
+/// of the last batch get a gradient, and optimizers that support it only update those.
+#[derive(Debug, Clone)]
+pub struct EmbeddingLayer {
+    /// embedding of every id, one per row
+    pub table: Matrix,
+    gradients: Matrix,
+    /// ids of the last forward pass, one row per sample
+    ids: Vec<Vec<usize>>,
+    /// distinct ids of the last backward pass, the only rows where `gradients` is non-zero
+    rows: Vec<usize>,
+}
+
+impl EmbeddingLayer {
+    pub fn new(vocab_size: usize, dim: usize) -> EmbeddingLayer {
+        EmbeddingLayer::from_table(Matrix::random((vocab_size, dim), (-0.1, 0.1)))
+    }
+
+    /// Creates a layer from existing embeddings, e.g. pre-trained ones.
+    pub fn from_table(table: Matrix) -> EmbeddingLayer {
+        EmbeddingLayer {
+            gradients: Matrix::zero_like(&table),
+            table,
+            ids: vec![],
+            rows: vec![],
+        }
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        self.table.dims[0]
+    }
+
+    pub fn dim(&self) -> usize {
+        self.table.dims[1]
+    }
+
+    fn id(&self, value: f64) -> usize {
+        assert!(
+            value >= 0.0 && value.fract() == 0.0 && (value as usize) < self.vocab_size(),
+            "EmbeddingLayer: invalid id {} for a vocabulary of {}",
+            value,
+            self.vocab_size()
+        );
+        value as usize
+    }
+}
+
+impl Module for EmbeddingLayer {
+    fn name(&self) -> &'static str {
+        "embedding"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let dim = self.dim();
+        self.ids = (0..input.dims[0])
+            .map(|i| {
+                (0..input.dims[1])
+                    .map(|t| self.id(input.get(i, t)))
+                    .collect()
+            })
+            .collect();
+        let mut output = Matrix::zero((input.dims[0], input.dims[1] * dim));
+        for (i, ids) in self.ids.iter().enumerate() {
+            for (t, &id) in ids.iter().enumerate() {
+                output.set_slice((i, t * dim), &self.table.slice(id..id + 1, 0..dim));
+            }
+        }
+        output
+    }
+
+    /// Ids are not differentiable, so the returned gradient is zero.
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let dim = self.dim();
+        for &row in &self.rows {
+            for j in 0..dim {
+                *self.gradients.get_mut(row, j) = 0.0;
+            }
+        }
+        for (i, ids) in self.ids.iter().enumerate() {
+            for (t, &id) in ids.iter().enumerate() {
+                for j in 0..dim {
+                    *self.gradients.get_mut(id, j) += dl_dz.get(i, t * dim + j);
+                }
+            }
+        }
+        self.rows = self.ids.concat();
+        self.rows.sort_unstable();
+        self.rows.dedup();
+        Matrix::zero((dl_dz.dims[0], dl_dz.dims[1] / dim))
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        vec![&self.table.data[..]]
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        vec![&self.gradients.data[..]]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        vec![Parameter {
+            name: "table",
+            value: &mut self.table.data[..],
+            gradient: &mut self.gradients.data[..],
+            frozen: false,
+            sparse: Some(SparseRows {
+                width: self.table.dims[1],
+                rows: &self.rows,
+            }),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmbeddingLayer;
+    use crate::activation::TANH;
+    use crate::matrix::Matrix;
+    use crate::module::Module;
+    use crate::optimizer::{Adam, Sgd};
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    #[test]
+    fn test_matches_one_hot_layer() {
+        let mut embedding = EmbeddingLayer::new(5, 3);
+        let mut layer = Layer::new(embedding.table.clone(), Vector::zero(3));
+        let ids = Matrix::from(vec![vec![4.0], vec![1.0], vec![4.0]]);
+        let mut one_hot = Matrix::zero((3, 5));
+        for i in 0..3 {
+            *one_hot.get_mut(i, ids.get(i, 0) as usize) = 1.0;
+        }
+        let output = embedding.forward_batch(&ids);
+        assert!(output
+            .to_vector()
+            .data
+            .iter()
+            .zip(&layer.forward_batch(&one_hot).to_vector().data)
+            .all(|(a, b)| (a - b).abs() < EPSILON));
+
+        let dl_dz = Matrix::random((3, 3), (-1.0, 1.0));
+        let dl_dx = embedding.backward_batch(&dl_dz);
+        layer.backward_batch(&dl_dz);
+        assert_eq!(dl_dx.dims, [3, 1]);
+        assert!(embedding.gradients()[0]
+            .iter()
+            .zip(layer.gradients()[0])
+            .all(|(a, b)| (a - b).abs() < EPSILON));
+        assert_eq!(embedding.rows, vec![1, 4]);
+
+        // rows of earlier batches are cleared
+        embedding.forward_batch(&Matrix::from(vec![vec![0.0]]));
+        embedding.backward_batch(&Matrix::from(vec![vec![1.0, 1.0, 1.0]]));
+        assert_eq!(embedding.rows, vec![0]);
+        assert_eq!(embedding.gradients()[0].iter().sum::<f64>(), 3.0);
+    }
+
+    #[test]
+    fn test_sparse_update() {
+        let embedding = EmbeddingLayer::new(6, 2);
+        let table = embedding.table.clone();
+        let mut nn = NeuralNetwork::from_modules(vec![
+            Box::new(embedding),
+            Box::new(Layer::random((4, 2), (-1.0, 1.0))),
+        ])
+        .with_optimizer(Sgd::new(0.5));
+        let ids = Matrix::from(vec![vec![2.0, 5.0], vec![5.0, 0.0]]);
+        nn.train_batch(&ids, &Matrix::from(vec![vec![1.0, 0.0], vec![0.0, 1.0]]));
+        let updated = nn.layers[0].parameters()[0];
+        for row in 0..6 {
+            let changed = (0..2).any(|j| updated[row * 2 + j] != table.get(row, j));
+            assert_eq!(changed, [0, 2, 5].contains(&row));
+        }
+    }
+
+    #[test]
+    fn test_sequence_classifier() {
+        // is the first token of each sequence also its last?
+        let sequences = [
+            [0, 1, 0],
+            [2, 1, 2],
+            [1, 1, 1],
+            [0, 2, 1],
+            [1, 0, 2],
+            [2, 0, 0],
+        ];
+        let inputs = Matrix::from(
+            sequences
+                .iter()
+                .map(|s| s.iter().map(|&id| id as f64).collect())
+                .collect::<Vec<Vec<f64>>>(),
+        );
+        let targets = Matrix::from(
+            sequences
+                .iter()
+                .map(|s| match s[0] == s[2] {
+                    true => vec![1.0, 0.0],
+                    false => vec![0.0, 1.0],
+                })
+                .collect::<Vec<Vec<f64>>>(),
+        );
+        let mut nn = NeuralNetwork::from_modules(vec![
+            Box::new(EmbeddingLayer::new(3, 4)),
+            Box::new(Layer::random((12, 8), (-0.5, 0.5)).with_activation(TANH)),
+            Box::new(Layer::random((8, 2), (-0.5, 0.5))),
+        ])
+        .with_optimizer(Adam::new(0.05));
+        for _ in 0..300 {
+            nn.train_batch(&inputs, &targets);
+        }
+        let output = nn.forward_batch(&inputs);
+        assert!(nn.loss_batch(&output, &targets) < 0.05);
+    }
+}
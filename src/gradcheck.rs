@@ -0,0 +1,248 @@
+use crate::{matrix::Matrix, module::Module, vector::Vector, NeuralNetwork};
+
+/// Step used for the central differences.
+pub const STEP: f64 = 1e-6;
+
+/// Worst relative error between the analytic and numerical gradient of a parameter tensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradCheck {
+    pub name: &'static str,
+    pub error: f64,
+}
+
+/// Worst error over all the checked parameter tensors.
+pub fn max_error(results: &[GradCheck]) -> f64 {
+    results.iter().map(|r| r.error).fold(0.0, f64::max)
+}
+
+/// Checks `Module::backward` against central differences of the loss `0.5 * |z - target|^2`
+/// plus the penalty of the module, where `z` is the output of the module for `input`.
+///
+/// Returns one `GradCheck` per parameter tensor, followed by one named `input` for the gradient
+/// wrt the input.
+pub fn check_module<M>(module: &mut M, input: &Vector, target: &Vector) -> Vec<GradCheck>
+where
+    M: Module + ?Sized,
+{
+    check_module_batch(
+        module,
+        &Matrix::from_rows(std::slice::from_ref(input)),
+        &Matrix::from_rows(std::slice::from_ref(target)),
+    )
+}
+
+/// Batch version of `check_module`, for modules whose output depends on the whole batch.
+pub fn check_module_batch<M>(module: &mut M, input: &Matrix, target: &Matrix) -> Vec<GradCheck>
+where
+    M: Module + ?Sized,
+{
+    let loss = |module: &mut M, input: &Matrix| {
+        let diff = module.forward_batch(input).subtract(target).to_vector();
+        0.5 * diff.dot(&diff) + module.penalty()
+    };
+    let z = module.forward_batch(input);
+    let dl_dx = module.backward_batch(&z.subtract(target));
+    let analytic = module.gradients().iter().map(|g| g.to_vec()).collect();
+
+    let mut results = check_parameters(
+        module,
+        |module: &mut M| module,
+        analytic,
+        |module| loss(module, input),
+    );
+    let mut state = (module, input.clone());
+    let mut error = 0.0_f64;
+    for i in 0..input.dims[0] {
+        for j in 0..input.dims[1] {
+            let numeric = central_difference(
+                &mut state,
+                |(_, x)| x.get(i, j),
+                |(_, x), value| *x.get_mut(i, j) = value,
+                |(module, x)| loss(module, x),
+            );
+            error = error.max(relative_error(dl_dx.get(i, j), numeric));
+        }
+    }
+    results.push(GradCheck {
+        name: "input",
+        error,
+    });
+    results
+}
+
+/// Checks `NeuralNetwork::backward` against central differences of `NeuralNetwork::loss`.
+///
+/// Returns the `GradCheck`s of every layer.
+pub fn check_network(
+    nn: &mut NeuralNetwork,
+    input: &Vector,
+    target: &Vector,
+) -> Vec<Vec<GradCheck>> {
+    nn.forward(input.clone());
+    nn.backward(target);
+
+    let loss = |nn: &mut NeuralNetwork| {
+        nn.forward(input.clone());
+        nn.loss(target)
+    };
+    (0..nn.layers.len())
+        .map(|i| {
+            let analytic = nn.layers[i]
+                .gradients()
+                .iter()
+                .map(|g| g.to_vec())
+                .collect();
+            check_parameters(nn, |nn| nn.layers[i].as_mut(), analytic, loss)
+        })
+        .collect()
+}
+
+fn check_parameters<T, M, S, L>(
+    target: &mut T,
+    select: S,
+    analytic: Vec<Vec<f64>>,
+    loss: L,
+) -> Vec<GradCheck>
+where
+    T: ?Sized,
+    M: Module + ?Sized,
+    S: Fn(&mut T) -> &mut M + Copy,
+    L: Fn(&mut T) -> f64 + Copy,
+{
+    let names = select(target)
+        .parameters_mut()
+        .iter()
+        .map(|p| p.name)
+        .collect::<Vec<&str>>();
+    let mut results = Vec::new();
+    for (k, (name, gradient)) in names.into_iter().zip(analytic).enumerate() {
+        let mut error = 0.0_f64;
+        for (i, dl_dp) in gradient.iter().enumerate() {
+            let numeric = central_difference(
+                target,
+                |t| select(t).parameters()[k][i],
+                |t, value| select(t).parameters_mut()[k].value[i] = value,
+                loss,
+            );
+            error = error.max(relative_error(*dl_dp, numeric));
+        }
+        results.push(GradCheck { name, error });
+    }
+    results
+}
+
+/// Estimates the derivative of `loss` wrt the parameter read by `get` and written by `set`,
+/// restoring the parameter afterwards.
+fn central_difference<T, G, S, L>(target: &mut T, get: G, set: S, loss: L) -> f64
+where
+    T: ?Sized,
+    G: Fn(&mut T) -> f64,
+    S: Fn(&mut T, f64),
+    L: Fn(&mut T) -> f64,
+{
+    let original = get(target);
+    set(target, original + STEP);
+    let loss_plus = loss(target);
+    set(target, original - STEP);
+    let loss_minus = loss(target);
+    set(target, original);
+    (loss_plus - loss_minus) / (2.0 * STEP)
+}
+
+/// Relative error between two derivatives, falling back to the absolute error when both are
+/// smaller than one so that vanishing gradients don't blow up the ratio.
+pub fn relative_error(analytic: f64, numeric: f64) -> f64 {
+    (analytic - numeric).abs() / analytic.abs().max(numeric.abs()).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_module, check_network, max_error, relative_error};
+    use crate::activation::{
+        LeakyRelu, PRelu, ELU, GELU, GELU_TANH, HARD_SIGMOID, HARD_TANH, LELU, MISH, RELU, SELU,
+        SIGMOID, SILU, SOFTPLUS, TANH,
+    };
+    use crate::head::{self, IDENTITY, LOG_SOFTMAX};
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const TOLERANCE: f64 = 1e-6;
+
+    type WithActivation = fn(Layer) -> Layer;
+
+    fn activations() -> Vec<(&'static str, WithActivation)> {
+        vec![
+            ("none", |layer| layer),
+            ("relu", |layer| layer.with_activation(RELU)),
+            ("lelu", |layer| layer.with_activation(LELU)),
+            ("leaky_relu", |layer| {
+                layer.with_activation(LeakyRelu::new(0.3))
+            }),
+            ("prelu", |layer| layer.with_activation(PRelu::new(0.25))),
+            ("sigmoid", |layer| layer.with_activation(SIGMOID)),
+            ("tanh", |layer| layer.with_activation(TANH)),
+            ("softplus", |layer| layer.with_activation(SOFTPLUS)),
+            ("elu", |layer| layer.with_activation(ELU)),
+            ("selu", |layer| layer.with_activation(SELU)),
+            ("gelu", |layer| layer.with_activation(GELU)),
+            ("gelu_tanh", |layer| layer.with_activation(GELU_TANH)),
+            ("silu", |layer| layer.with_activation(SILU)),
+            ("mish", |layer| layer.with_activation(MISH)),
+            ("hard_sigmoid", |layer| layer.with_activation(HARD_SIGMOID)),
+            ("hard_tanh", |layer| layer.with_activation(HARD_TANH)),
+        ]
+    }
+
+    #[test]
+    fn test_relative_error() {
+        assert_eq!(relative_error(2.0, 1.0), 0.5);
+        assert_eq!(relative_error(0.0, 1e-9), 1e-9);
+    }
+
+    #[test]
+    fn test_check_module() {
+        for (name, activation) in activations() {
+            let mut layer = activation(Layer::random((4, 3), (-1.0, 1.0)));
+            let input = Vector::random(4, (-1.0, 1.0));
+            let target = Vector::random(3, (-1.0, 1.0));
+            let result = check_module(&mut layer, &input, &target);
+            assert!(max_error(&result) < TOLERANCE, "{}: {:?}", name, result);
+        }
+    }
+
+    #[test]
+    fn test_check_network() {
+        for (name, activation) in activations() {
+            let mut nn = NeuralNetwork::new(vec![
+                activation(Layer::random((4, 5), (-1.0, 1.0))),
+                Layer::random((5, 3), (-1.0, 1.0)),
+            ]);
+            let input = Vector::random(4, (-1.0, 1.0));
+            let target = Vector::from(vec![0.0, 1.0, 0.0]);
+            for result in check_network(&mut nn, &input, &target) {
+                assert!(max_error(&result) < TOLERANCE, "{}: {:?}", name, result);
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_network_heads() {
+        type WithHead = fn(NeuralNetwork) -> NeuralNetwork;
+        let heads: Vec<(&str, WithHead)> = vec![
+            ("identity", |nn| nn.with_head(IDENTITY)),
+            ("sigmoid", |nn| nn.with_head(head::SIGMOID)),
+            ("log_softmax", |nn| nn.with_head(LOG_SOFTMAX)),
+        ];
+        for (name, with_head) in heads {
+            let mut nn = with_head(NeuralNetwork::new(vec![
+                Layer::random((4, 5), (-1.0, 1.0)).with_activation(TANH),
+                Layer::random((5, 3), (-1.0, 1.0)),
+            ]));
+            let input = Vector::random(4, (-1.0, 1.0));
+            let target = Vector::from(vec![0.0, 1.0, 1.0]);
+            for result in check_network(&mut nn, &input, &target) {
+                assert!(max_error(&result) < TOLERANCE, "{}: {:?}", name, result);
+            }
+        }
+    }
+}
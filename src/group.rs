@@ -0,0 +1,165 @@
+/// Training settings shared by parameters of a `NeuralNetwork`, e.g. a lower learning rate for
+/// pretrained layers.
+///
+/// A parameter follows the last group added to the network that contains it, and the default
+/// settings if there is none.
+#[derive(Debug, Clone)]
+pub struct ParameterGroup {
+    pub name: String,
+    /// indices of the layers in the group
+    pub layers: Vec<usize>,
+    /// names of the parameters of those layers in the group, e.g. "weights", or all of them if
+    /// `None`
+    pub parameters: Option<Vec<String>>,
+    /// factor applied to the learning rate of the optimizer
+    pub learning_rate_scale: f64,
+    /// decay of the parameters towards zero, decoupled from the gradient as in `AdamW`
+    pub weight_decay: f64,
+    pub frozen: bool,
+}
+
+impl ParameterGroup {
+    pub fn new(name: &str, layers: &[usize]) -> ParameterGroup {
+        ParameterGroup {
+            name: name.to_owned(),
+            layers: layers.to_vec(),
+            parameters: None,
+            learning_rate_scale: 1.0,
+            weight_decay: 0.0,
+            frozen: false,
+        }
+    }
+
+    /// Restricts the group to the parameters with these names.
+    pub fn only(mut self, parameters: &[&str]) -> Self {
+        self.parameters = Some(parameters.iter().map(|p| p.to_string()).collect());
+        self
+    }
+
+    pub fn learning_rate_scale(mut self, scale: f64) -> Self {
+        self.learning_rate_scale = scale;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+
+    pub fn frozen(mut self) -> Self {
+        self.frozen = true;
+        self
+    }
+
+    pub fn contains(&self, layer: usize, parameter: &str) -> bool {
+        self.layers.contains(&layer)
+            && self
+                .parameters
+                .as_ref()
+                .is_none_or(|names| names.iter().any(|name| name == parameter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParameterGroup;
+    use crate::optimizer::Sgd;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    fn network() -> NeuralNetwork {
+        NeuralNetwork::new(vec![
+            Layer::random((2, 3), (-1.0, 1.0)),
+            Layer::random((3, 2), (-1.0, 1.0)),
+        ])
+        .with_optimizer(Sgd::new(0.1))
+    }
+
+    /// Returns the parameters of every layer, flattened.
+    fn parameters(nn: &NeuralNetwork) -> Vec<Vec<f64>> {
+        nn.layers
+            .iter()
+            .flat_map(|layer| layer.parameters())
+            .map(|p| p.to_vec())
+            .collect()
+    }
+
+    fn train(nn: &mut NeuralNetwork) {
+        nn.train(Vector::from(vec![0.5, -0.3]), &Vector::from(vec![1.0, 0.0]));
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut nn = network();
+        nn.freeze(0, Some("biases"));
+        nn.freeze(1, None);
+        assert!(nn.is_frozen(0, "biases") && !nn.is_frozen(0, "weights"));
+        let before = parameters(&nn);
+        train(&mut nn);
+        let after = parameters(&nn);
+        assert!(before[0] != after[0]);
+        assert_eq!(before[1..], after[1..]);
+
+        nn.unfreeze(1, Some("weights"));
+        train(&mut nn);
+        let last = parameters(&nn);
+        assert!(after[2] != last[2]);
+        assert_eq!(after[1], last[1]);
+        assert_eq!(after[3], last[3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "layer 0 has no parameter bias")]
+    fn test_freeze_unknown_parameter() {
+        network().freeze(0, Some("bias"));
+    }
+
+    #[test]
+    #[should_panic(expected = "layer 1 has no parameter weight")]
+    fn test_unfreeze_unknown_parameter() {
+        network().unfreeze(1, Some("weight"));
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut nn = network();
+        let mut scaled = network();
+        for (layer, other) in nn.layers.iter_mut().zip(&scaled.layers) {
+            for (p, q) in layer.parameters_mut().into_iter().zip(other.parameters()) {
+                p.value.copy_from_slice(q);
+            }
+        }
+        scaled = scaled
+            .with_group(ParameterGroup::new("first", &[0]).learning_rate_scale(0.5))
+            .with_group(
+                ParameterGroup::new("decayed", &[1])
+                    .only(&["weights"])
+                    .weight_decay(0.2),
+            );
+        let before = parameters(&nn);
+        train(&mut nn);
+        train(&mut scaled);
+        let (plain, scaled_after) = (parameters(&nn), parameters(&scaled));
+
+        // half the step in the first layer
+        for i in 0..2 {
+            for (j, w) in before[i].iter().enumerate() {
+                let expected = w + 0.5 * (plain[i][j] - w);
+                assert!((scaled_after[i][j] - expected).abs() < EPSILON);
+            }
+        }
+        // weights of the second layer decay by lr * weight_decay, its biases don't
+        for (j, w) in before[2].iter().enumerate() {
+            let expected = plain[2][j] - 0.1 * 0.2 * w;
+            assert!((scaled_after[2][j] - expected).abs() < EPSILON);
+        }
+        assert_eq!(plain[3], scaled_after[3]);
+
+        scaled.group_mut("first").unwrap().frozen = true;
+        train(&mut scaled);
+        assert_eq!(parameters(&scaled)[0], scaled_after[0]);
+        assert!(scaled.group("missing").is_none());
+    }
+}
@@ -0,0 +1,289 @@
+use std::fmt::Debug;
+
+use crate::activation::{self, Activation};
+use crate::dual::{Dual, DualVector};
+use crate::vector::Vector;
+
+/// Final transformation applied to the output of the last layer of a `NeuralNetwork`, together
+/// with the loss it is trained with.
+pub trait Head: Debug {
+    fn name(&self) -> &'static str;
+
+    fn forward(&self, logits: &Vector) -> Vector;
+
+    fn forward_dual(&self, logits: DualVector) -> DualVector;
+
+    /// Computes the loss paired with the head from its output.
+    fn loss(&self, output: &Vector, target: &Vector) -> f64;
+
+    /// Computes the gradient of the paired loss wrt the input of the head (the logits).
+    fn backward(&self, output: &Vector, target: &Vector) -> Vector;
+
+    /// Name of the `Loss` that `loss` and `backward` compute, if any, which lets a network use the
+    /// simpler combined gradient when it is configured with that loss.
+    fn paired_loss(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Computes the gradient wrt the logits from the gradient of any loss wrt the output (dl_do).
+    fn backpropagate(&self, dl_do: &Vector, output: &Vector) -> Vector;
+}
+
+/// Raw linear output, trained with the squared error `0.5 * |output - target|^2`.
+#[derive(Debug, Clone, Copy)]
+pub struct Identity;
+
+impl Head for Identity {
+    fn name(&self) -> &'static str {
+        "identity"
+    }
+
+    fn forward(&self, logits: &Vector) -> Vector {
+        logits.clone()
+    }
+
+    fn forward_dual(&self, logits: DualVector) -> DualVector {
+        logits
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        let diff = output.subtract(target);
+        0.5 * diff.dot(&diff)
+    }
+
+    fn backward(&self, output: &Vector, target: &Vector) -> Vector {
+        output.subtract(target)
+    }
+
+    fn backpropagate(&self, dl_do: &Vector, _output: &Vector) -> Vector {
+        dl_do.clone()
+    }
+}
+
+/// Softmax over the outputs, trained with the categorical cross entropy.
+#[derive(Debug, Clone, Copy)]
+pub struct Softmax;
+
+impl Head for Softmax {
+    fn name(&self) -> &'static str {
+        "softmax"
+    }
+
+    fn forward(&self, logits: &Vector) -> Vector {
+        logits.softmax()
+    }
+
+    fn forward_dual(&self, logits: DualVector) -> DualVector {
+        logits.softmax()
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        output.cross_entropy_loss(target)
+    }
+
+    fn backward(&self, output: &Vector, target: &Vector) -> Vector {
+        output.subtract(target)
+    }
+
+    fn paired_loss(&self) -> Option<&'static str> {
+        Some("categorical_cross_entropy")
+    }
+
+    fn backpropagate(&self, dl_do: &Vector, output: &Vector) -> Vector {
+        let weighted = dl_do.dot(output);
+        dl_do
+            .iter()
+            .zip(output.iter())
+            .map(|(g, o)| o * (g - weighted))
+            .collect::<Vec<f64>>()
+            .into()
+    }
+}
+
+/// Independent sigmoid per output for multi-label targets, trained with the binary cross entropy.
+#[derive(Debug, Clone, Copy)]
+pub struct Sigmoid;
+
+impl Head for Sigmoid {
+    fn name(&self) -> &'static str {
+        "sigmoid"
+    }
+
+    fn forward(&self, logits: &Vector) -> Vector {
+        activation::SIGMOID.apply(logits.clone())
+    }
+
+    fn forward_dual(&self, logits: DualVector) -> DualVector {
+        activation::SIGMOID.apply_dual(logits)
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        output.binary_cross_entropy_loss(target)
+    }
+
+    fn backward(&self, output: &Vector, target: &Vector) -> Vector {
+        output.subtract(target)
+    }
+
+    fn paired_loss(&self) -> Option<&'static str> {
+        Some("binary_cross_entropy")
+    }
+
+    fn backpropagate(&self, dl_do: &Vector, output: &Vector) -> Vector {
+        dl_do
+            .iter()
+            .zip(output.iter())
+            .map(|(g, o)| g * o * (1.0 - o))
+            .collect::<Vec<f64>>()
+            .into()
+    }
+}
+
+/// Log-probabilities of a softmax, trained with the negative log likelihood `-target . output`.
+#[derive(Debug, Clone, Copy)]
+pub struct LogSoftmax;
+
+impl Head for LogSoftmax {
+    fn name(&self) -> &'static str {
+        "log_softmax"
+    }
+
+    fn forward(&self, logits: &Vector) -> Vector {
+        logits.log_softmax()
+    }
+
+    fn forward_dual(&self, logits: DualVector) -> DualVector {
+        let exp_sum = logits
+            .data
+            .iter()
+            .fold(Dual::constant(0.0), |acc, x| acc + x.exp());
+        logits.map(|x| x - exp_sum.ln())
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        -output.dot(target)
+    }
+
+    fn backward(&self, output: &Vector, target: &Vector) -> Vector {
+        let target_sum = target.iter().sum::<f64>();
+        output
+            .iter()
+            .zip(target.iter())
+            .map(|(o, t)| o.exp() * target_sum - t)
+            .collect::<Vec<f64>>()
+            .into()
+    }
+
+    fn paired_loss(&self) -> Option<&'static str> {
+        Some("negative_log_likelihood")
+    }
+
+    fn backpropagate(&self, dl_do: &Vector, output: &Vector) -> Vector {
+        let gradient_sum = dl_do.iter().sum::<f64>();
+        dl_do
+            .iter()
+            .zip(output.iter())
+            .map(|(g, o)| g - o.exp() * gradient_sum)
+            .collect::<Vec<f64>>()
+            .into()
+    }
+}
+
+pub const IDENTITY: Identity = Identity;
+
+pub const SOFTMAX: Softmax = Softmax;
+
+pub const SIGMOID: Sigmoid = Sigmoid;
+
+pub const LOG_SOFTMAX: LogSoftmax = LogSoftmax;
+
+/// Creates a head from its name.
+pub fn from_name(name: &str) -> Option<Box<dyn Head>> {
+    let head: Box<dyn Head> = match name {
+        "identity" => Box::new(IDENTITY),
+        "softmax" => Box::new(SOFTMAX),
+        "sigmoid" => Box::new(SIGMOID),
+        "log_softmax" => Box::new(LOG_SOFTMAX),
+        _ => return None,
+    };
+    Some(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Head, IDENTITY, LOG_SOFTMAX, SIGMOID, SOFTMAX};
+    use crate::dual::DualVector;
+    use crate::vector::Vector;
+
+    const EPSILON: f64 = 0.00001;
+
+    fn heads() -> Vec<(Box<dyn Head>, Vector)> {
+        vec![
+            (Box::new(IDENTITY), Vector::from(vec![0.5, -1.0, 2.0])),
+            (Box::new(SOFTMAX), Vector::from(vec![0.0, 1.0, 0.0])),
+            (Box::new(SIGMOID), Vector::from(vec![1.0, 0.0, 1.0])),
+            (Box::new(LOG_SOFTMAX), Vector::from(vec![0.2, 0.0, 0.8])),
+        ]
+    }
+
+    #[test]
+    fn test_backward() {
+        let logits = Vector::from(vec![-0.3, 1.2, 0.4]);
+        let step = 1e-6;
+        for (head, target) in heads() {
+            let result = head.backward(&head.forward(&logits), &target);
+            for i in 0..logits.len() {
+                let mut plus = logits.clone();
+                plus.data[i] += step;
+                let mut minus = logits.clone();
+                minus.data[i] -= step;
+                let numeric = (head.loss(&head.forward(&plus), &target)
+                    - head.loss(&head.forward(&minus), &target))
+                    / (2.0 * step);
+                assert!((result[i] - numeric).abs() < EPSILON, "{}", head.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_backpropagate() {
+        // the gradient of each paired loss wrt the output, chained through the head, must match
+        // the combined gradient
+        let logits = Vector::from(vec![-0.3, 1.2, 0.4]);
+        for (head, target) in heads() {
+            let output = head.forward(&logits);
+            let dl_do = match head.name() {
+                "softmax" => target
+                    .iter()
+                    .zip(output.iter())
+                    .map(|(t, o)| -t / o)
+                    .collect(),
+                "sigmoid" => target
+                    .iter()
+                    .zip(output.iter())
+                    .map(|(t, o)| (o - t) / (o * (1.0 - o)))
+                    .collect(),
+                "log_softmax" => target.iter().map(|t| -t).collect(),
+                _ => output.subtract(&target).data,
+            };
+            let result = head.backpropagate(&Vector::from(dl_do), &output);
+            let expected = head.backward(&output, &target);
+            for (r, e) in result.iter().zip(expected.iter()) {
+                assert!((r - e).abs() < EPSILON, "{}", head.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_forward_dual() {
+        let logits = Vector::from(vec![-0.3, 1.2, 0.4]);
+        let tangent = Vector::from(vec![0.0, 0.0, 0.0]);
+        for (head, _) in heads() {
+            let result = head.forward_dual(DualVector::new(&logits, &tangent));
+            let expected = head.forward(&logits);
+            for (r, e) in result.primal().iter().zip(expected.iter()) {
+                assert!((r - e).abs() < EPSILON, "{}", head.name());
+            }
+        }
+    }
+}
@@ -2,7 +2,10 @@ use rand::{thread_rng, Rng};
 use regex::Regex;
 use std::collections::HashMap;
 
-use crate::{activation::LELU, vector::Vector, Layer, NeuralNetwork};
+use crate::embedding::EmbeddingLayer;
+use crate::optimizer::{Optimizer, Sgd};
+use crate::scheduler::LinearDecay;
+use crate::{vector::Vector, Layer, NeuralNetwork};
 
 pub struct Embedding {
     pub word_to_embed: HashMap<String, Vector>,
@@ -30,6 +33,7 @@ pub struct EmbeddingBuilder {
     codex_size: usize,
     window: usize,
     dim: usize,
+    optimizer: Option<Box<dyn Optimizer>>,
 }
 
 impl EmbeddingBuilder {
@@ -45,6 +49,7 @@ impl EmbeddingBuilder {
             codex_size,
             window: 1,
             dim: 1,
+            optimizer: None,
         }
     }
 
@@ -58,6 +63,16 @@ impl EmbeddingBuilder {
         self
     }
 
+    /// Sets the optimizer used by the next call to `train`, which defaults to plain SGD with a
+    /// learning rate of 0.1. The learning rate decays linearly over the training runs.
+    pub fn optimizer<O>(mut self, optimizer: O) -> Self
+    where
+        O: Optimizer + 'static,
+    {
+        self.optimizer = Some(Box::new(optimizer));
+        self
+    }
+
     fn random_pairing(&self) -> (Vector, Vector) {
         let mut rng = thread_rng();
         let offset = rng.gen_range(1..=self.window);
@@ -66,7 +81,7 @@ impl EmbeddingBuilder {
             true => (self.codex[index], self.codex[index + offset]),
             false => (self.codex[index + offset], self.codex[index]),
         };
-        (self.one_hot(num1), self.one_hot(num2))
+        (Vector::from(vec![num1 as f64]), self.one_hot(num2))
     }
 
     fn one_hot(&self, num: usize) -> Vector {
@@ -75,10 +90,20 @@ impl EmbeddingBuilder {
         one_hot.into()
     }
 
+    /// Trains the embeddings on `runs` pairs of nearby words.
+    ///
+    /// The embedding of a word is its row of the lookup table as is, without any bias or
+    /// activation applied to it.
     pub fn train(&mut self, runs: usize) -> Embedding {
-        let nn_l1 = Layer::random((self.dict_size, self.dim), (0.0, 1.0)).with_activation(LELU);
+        let nn_l1 = EmbeddingLayer::new(self.dict_size, self.dim);
         let nn_l2 = Layer::random((self.dim, self.dict_size), (0.0, 1.0));
-        let mut nn = NeuralNetwork::new(vec![nn_l1, nn_l2]);
+        let optimizer = self
+            .optimizer
+            .take()
+            .unwrap_or_else(|| Box::new(Sgd::new(0.1)));
+        let mut nn = NeuralNetwork::from_modules(vec![Box::new(nn_l1), Box::new(nn_l2)])
+            .with_boxed_optimizer(optimizer)
+            .with_scheduler(LinearDecay::new(runs));
 
         let mut loss_sum = 0.0;
         for i in 0..runs {
@@ -86,21 +111,18 @@ impl EmbeddingBuilder {
             let loss = nn.train(input, &output);
             loss_sum += loss;
             // TODO: remove average loss printing
-            match i % 10000 {
-                0 => {
-                    println!("{}", loss_sum / 10000.0);
-                    loss_sum = 0.0;
-                }
-                _ => {}
+            if i % 10000 == 0 {
+                println!("{}", loss_sum / 10000.0);
+                loss_sum = 0.0;
             }
         }
 
-        // extract embedding from hidden layer
+        // extract embedding from the rows of the lookup table
+        let table = nn.layers[0].parameters()[0];
         let mut word_to_embed = HashMap::new();
         for (word, num) in &self.word_to_num {
-            nn.forward(self.one_hot(*num));
-            let embedding = nn.intermediates[1].clone();
-            word_to_embed.insert(word.to_owned(), embedding);
+            let embedding = table[num * self.dim..(num + 1) * self.dim].to_vec();
+            word_to_embed.insert(word.to_owned(), embedding.into());
         }
 
         Embedding {
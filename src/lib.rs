@@ -1,12 +1,42 @@
 use activation::Activation;
+use clipping::GradientClipping;
+use dual::{DualVector, UnsupportedModule};
+use group::ParameterGroup;
+use head::{Head, IDENTITY, SOFTMAX};
+use loss::{Loss, MSE};
 use matrix::{Matrix, Multiply};
+use module::{Module, Parameter};
 use ops::Scale;
+use optimizer::{Optimizer, Sgd};
+use regularization::Regularization;
+use scheduler::LrScheduler;
 use vector::Vector;
 
 pub mod activation;
+pub mod attention;
+pub mod checkpoint;
+pub mod clipping;
+pub mod conv;
+pub mod data;
+pub mod dropout;
+pub mod dual;
+pub mod embedding;
+pub mod gradcheck;
+pub mod group;
+pub mod head;
 pub mod language;
+pub mod loss;
 pub mod matrix;
+pub mod metrics;
+pub mod module;
+pub mod normalization;
+pub mod onnx;
 pub mod ops;
+pub mod optimizer;
+pub mod recurrent;
+pub mod regularization;
+pub mod scheduler;
+pub mod trainer;
 pub mod vector;
 
 #[derive(Debug)]
@@ -14,7 +44,15 @@ pub struct Layer {
     pub constant: bool,
     pub weights: Matrix,
     pub biases: Vector,
-    activation: Option<Activation>,
+    activation: Option<Box<dyn Activation>>,
+    regularization: Option<Regularization>,
+    /// maximum norm of the incoming weights of every output
+    max_norm: Option<f64>,
+    weight_gradients: Matrix,
+    bias_gradients: Vector,
+    activation_gradients: Vec<f64>,
+    /// (x, y, z) of the last forward pass
+    cache: Option<(Matrix, Matrix, Matrix)>,
 }
 
 impl Layer {
@@ -26,9 +64,15 @@ impl Layer {
         assert!(weights.dims[1] == biases.len());
         Layer {
             constant: false,
+            weight_gradients: Matrix::zero_like(&weights),
+            bias_gradients: Vector::zero(biases.len()),
             weights,
             biases,
             activation: None,
+            regularization: None,
+            max_norm: None,
+            activation_gradients: vec![],
+            cache: None,
         }
     }
 
@@ -36,117 +80,646 @@ impl Layer {
         self.constant = true;
     }
 
-    pub fn with_activation(mut self, activation: Activation) -> Self {
+    pub fn with_activation<A>(self, activation: A) -> Self
+    where
+        A: Activation + 'static,
+    {
+        self.with_boxed_activation(Box::new(activation))
+    }
+
+    pub fn with_boxed_activation(mut self, activation: Box<dyn Activation>) -> Self {
+        self.activation_gradients = vec![0.0; activation.parameters().len()];
         self.activation = Some(activation);
         self
     }
 
+    /// Adds a penalty on the parameters of the layer to the loss of the network.
+    pub fn with_regularization(mut self, regularization: Regularization) -> Self {
+        self.regularization = Some(regularization);
+        self
+    }
+
+    /// Constrains the norm of the incoming weights of every output after each training update.
+    pub fn with_max_norm(mut self, max_norm: f64) -> Self {
+        assert!(max_norm > 0.0);
+        self.max_norm = Some(max_norm);
+        self
+    }
+
     pub fn random(dims: (usize, usize), bounds: (f64, f64)) -> Layer {
         Layer::new(Matrix::random(dims, bounds), Vector::random(dims.1, bounds))
     }
 
+    /// Computes the output of the layer without caching anything for `backward`.
     pub fn forward(&self, input: &Vector) -> Vector {
-        let output = self.weights.transpose().multiply(&input).add(&self.biases);
-        if let Some(activation) = &self.activation {
-            activation.apply(output)
+        self.forward_intermediate(input).1
+    }
+
+    /// Returns the tuple (y, z) of the output of the weight multiplication and the output of the layer
+    pub fn forward_intermediate(&self, input: &Vector) -> (Vector, Vector) {
+        let y = self.weights.transpose().multiply(input).add(&self.biases);
+        let z = if let Some(activation) = &self.activation {
+            activation.apply(y.clone())
         } else {
-            output
-        }
+            y.clone()
+        };
+        (y, z)
     }
 
-    /// Computes the gradient of the loss wrt the input of the layer (x), the weights (w), and the biases (b).
-    ///
-    /// Returns the tuple (dl_dx, dl_dw, dl_db)
-    ///
-    /// # Arguments
-    ///
-    /// * `dl_dz` - gradient of loss wrt output of the layer (z)
-    /// * `x` - input to the layer
-    /// * `y` - output of weight multiplication, input to activation
-    /// * `z` - output of the layer
-    fn backward(
-        &self,
-        dl_dz: &Vector,
-        x: &Vector,
-        y: Option<&Vector>,
-        z: Option<&Vector>,
-    ) -> (Vector, Matrix, Vector) {
-        let dl_dy = if let Some(activation) = &self.activation {
-            activation.backpropagate(dl_dz, y, z)
+    fn learnable_activation(&self) -> Option<&dyn Activation> {
+        self.activation.as_deref().filter(|a| a.learnable())
+    }
+}
+
+impl Module for Layer {
+    fn name(&self) -> &'static str {
+        "dense"
+    }
+
+    fn activation(&self) -> Option<&dyn Activation> {
+        self.activation.as_deref()
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let y = input.multiply(&self.weights).add_row(&self.biases);
+        let z = if let Some(activation) = &self.activation {
+            Matrix::from_vector(activation.apply(y.to_vector()), (y.dims[0], y.dims[1]))
+        } else {
+            y.clone()
+        };
+        self.cache = Some((input.clone(), y, z.clone()));
+        z
+    }
+
+    /// Computes the gradient of the loss wrt the input of the layer (x), the weights (w), the biases (b),
+    /// and the parameters of the activation (a), where y is the output of the weight multiplication
+    /// and z the output of the layer.
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let (x, y, z) = self
+            .cache
+            .as_ref()
+            .expect("Layer::backward: forward was not called");
+        let (dl_dy, dl_da) = if let Some(activation) = &self.activation {
+            // activations are elementwise, so the batch can be flattened
+            let (dl_dz, y, z) = (dl_dz.to_vector(), y.to_vector(), z.to_vector());
+            (
+                Matrix::from_vector(
+                    activation.backpropagate(&dl_dz, Some(&y), Some(&z)),
+                    (x.dims[0], self.biases.len()),
+                ),
+                activation.parameter_gradients(&dl_dz, Some(&y), Some(&z)),
+            )
         } else {
-            dl_dz.clone()
+            (dl_dz.clone(), vec![])
         };
 
-        let dl_dx = self.weights.multiply(&dl_dy);
-        let mut dl_dw = Matrix::zero((self.weights.dims[0], self.weights.dims[1]));
+        let dl_dx = dl_dy.multiply(&self.weights.transpose());
+        let dl_dw = x.transpose().multiply(&dl_dy);
         for i in 0..self.weights.dims[0] {
             for j in 0..self.weights.dims[1] {
-                *dl_dw.get_mut(i, j) = x[i] * dl_dy[j];
+                *self.weight_gradients.get_mut(i, j) = dl_dw.get(i, j);
+            }
+        }
+        self.bias_gradients = dl_dy.sum_rows();
+        self.activation_gradients = dl_da;
+        if let Some(regularization) = &self.regularization {
+            regularization.add_gradient(&self.weights.data, &mut self.weight_gradients.data);
+            if regularization.biases {
+                regularization.add_gradient(&self.biases.data, &mut self.bias_gradients.data);
+            }
+        }
+        dl_dx
+    }
+
+    fn forward_dual(&self, input: &DualVector) -> Option<DualVector> {
+        let output = self.weights.transpose().multiply(input).add(&self.biases);
+        if let Some(activation) = &self.activation {
+            Some(activation.apply_dual(output))
+        } else {
+            Some(output)
+        }
+    }
+
+    fn penalty(&self) -> f64 {
+        match &self.regularization {
+            Some(regularization) if regularization.biases => {
+                regularization.penalty(&self.weights.data)
+                    + regularization.penalty(&self.biases.data)
             }
+            Some(regularization) => regularization.penalty(&self.weights.data),
+            None => 0.0,
         }
-        let dl_db = dl_dy;
-        (dl_dx, dl_dw, dl_db)
+    }
+
+    fn apply_constraints(&mut self) {
+        if let Some(max_norm) = self.max_norm {
+            regularization::clip_columns(&mut self.weights, max_norm);
+        }
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        let mut parameters = vec![&self.weights.data[..], &self.biases.data[..]];
+        if let Some(activation) = self.learnable_activation() {
+            parameters.push(activation.parameters());
+        }
+        parameters
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        let mut gradients = vec![
+            &self.weight_gradients.data[..],
+            &self.bias_gradients.data[..],
+        ];
+        if self.learnable_activation().is_some() {
+            gradients.push(&self.activation_gradients[..]);
+        }
+        gradients
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        let mut parameters = vec![
+            Parameter {
+                name: "weights",
+                value: &mut self.weights.data[..],
+                gradient: &mut self.weight_gradients.data[..],
+                frozen: self.constant,
+                sparse: None,
+            },
+            Parameter {
+                name: "biases",
+                value: &mut self.biases.data[..],
+                gradient: &mut self.bias_gradients.data[..],
+                frozen: self.constant,
+                sparse: None,
+            },
+        ];
+        if let Some(activation) = self.activation.as_mut().filter(|a| a.learnable()) {
+            parameters.push(Parameter {
+                name: "activation",
+                value: activation.parameters_mut(),
+                gradient: &mut self.activation_gradients[..],
+                frozen: self.constant,
+                sparse: None,
+            });
+        }
+        parameters
     }
 }
 
 pub struct NeuralNetwork {
-    pub layers: Vec<Layer>,
+    pub layers: Vec<Box<dyn Module>>,
     pub intermediates: Vec<Vector>,
+    head: Box<dyn Head>,
+    /// replaces the loss paired with the head
+    loss: Option<Box<dyn Loss>>,
+    optimizer: Box<dyn Optimizer>,
+    scheduler: Option<Box<dyn LrScheduler>>,
+    /// learning rate of the optimizer at the first scheduled step
+    base_learning_rate: Option<f64>,
+    steps: usize,
+    epochs: usize,
+    training: bool,
+    groups: Vec<ParameterGroup>,
+    /// (layer, parameter name) of every frozen parameter
+    frozen: Vec<(usize, &'static str)>,
+    clipping: Option<GradientClipping>,
+    /// global norm of the gradients at the last training step, before clipping
+    gradient_norm: f64,
 }
 
 impl NeuralNetwork {
-    pub fn new(layers: Vec<Layer>) -> NeuralNetwork {
+    pub fn new<M>(layers: Vec<M>) -> NeuralNetwork
+    where
+        M: Module + 'static,
+    {
+        NeuralNetwork::from_modules(
+            layers
+                .into_iter()
+                .map(|layer| Box::new(layer) as Box<dyn Module>)
+                .collect(),
+        )
+    }
+
+    /// Builds a network for regression, with a raw linear output trained with the mean squared
+    /// error. Use `with_loss` for other losses, e.g. `Huber`.
+    pub fn regression<M>(layers: Vec<M>) -> NeuralNetwork
+    where
+        M: Module + 'static,
+    {
+        NeuralNetwork::new(layers)
+            .with_head(IDENTITY)
+            .with_loss(MSE)
+    }
+
+    /// Builds a network from modules of different types, e.g. dense layers mixed with dropout.
+    pub fn from_modules(layers: Vec<Box<dyn Module>>) -> NeuralNetwork {
         NeuralNetwork {
             layers,
             intermediates: Vec::new(),
+            head: Box::new(SOFTMAX),
+            loss: None,
+            optimizer: Box::new(Sgd::new(0.1)),
+            scheduler: None,
+            base_learning_rate: None,
+            steps: 0,
+            epochs: 0,
+            training: true,
+            groups: vec![],
+            frozen: vec![],
+            clipping: None,
+            gradient_norm: 0.0,
+        }
+    }
+
+    /// Replaces the default optimizer, plain SGD with a learning rate of 0.1.
+    pub fn with_optimizer<O>(self, optimizer: O) -> Self
+    where
+        O: Optimizer + 'static,
+    {
+        self.with_boxed_optimizer(Box::new(optimizer))
+    }
+
+    pub fn with_boxed_optimizer(mut self, optimizer: Box<dyn Optimizer>) -> Self {
+        self.optimizer = optimizer;
+        self
+    }
+
+    pub fn optimizer(&self) -> &dyn Optimizer {
+        self.optimizer.as_ref()
+    }
+
+    pub fn optimizer_mut(&mut self) -> &mut dyn Optimizer {
+        self.optimizer.as_mut()
+    }
+
+    /// Sets a schedule for the learning rate, relative to the learning rate of the optimizer.
+    pub fn with_scheduler<S>(mut self, scheduler: S) -> Self
+    where
+        S: LrScheduler + 'static,
+    {
+        self.scheduler = Some(Box::new(scheduler));
+        self
+    }
+
+    /// Clips the gradients before every training update.
+    pub fn with_gradient_clipping(mut self, clipping: GradientClipping) -> Self {
+        self.clipping = Some(clipping);
+        self
+    }
+
+    /// Global L2 norm of the gradients of the trainable parameters at the last training step,
+    /// before clipping.
+    pub fn gradient_norm(&self) -> f64 {
+        self.gradient_norm
+    }
+
+    /// Number of training steps taken so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Number of epochs completed so far.
+    pub fn epochs(&self) -> usize {
+        self.epochs
+    }
+
+    /// Marks the end of an epoch for the learning rate scheduler, reporting the validation loss
+    /// if there is one.
+    pub fn end_epoch(&mut self, validation_loss: Option<f64>) {
+        self.epochs += 1;
+        if let (Some(scheduler), Some(loss)) = (&mut self.scheduler, validation_loss) {
+            scheduler.observe(loss);
+        }
+    }
+
+    /// Switches every layer between training mode, the default, and inference mode, where
+    /// stochastic layers such as `Dropout` become the identity.
+    pub fn set_training(&mut self, training: bool) {
+        self.training = training;
+        for layer in &mut self.layers {
+            layer.set_training(training);
+        }
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    /// Forgets the state of every stateful layer, e.g. before feeding a new sequence.
+    pub fn reset_state(&mut self) {
+        for layer in &mut self.layers {
+            layer.reset_state();
+        }
+    }
+
+    /// Sets the number of valid steps of every sequence of the next batches, so that attention
+    /// ignores the padding after them.
+    pub fn set_sequence_lengths(&mut self, lengths: Option<&[usize]>) {
+        for layer in &mut self.layers {
+            layer.set_sequence_lengths(lengths);
+        }
+    }
+
+    /// Adds a group of parameters with their own training settings, taking precedence over the
+    /// groups added before.
+    pub fn with_group(mut self, group: ParameterGroup) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ParameterGroup> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut ParameterGroup> {
+        self.groups.iter_mut().find(|group| group.name == name)
+    }
+
+    /// Excludes a parameter of a layer, e.g. "biases", or all of them if `None`, from training
+    /// updates.
+    ///
+    /// Panics if the layer has no parameter of that name.
+    pub fn freeze(&mut self, layer: usize, parameter: Option<&str>) {
+        self.check_parameter("freeze", layer, parameter);
+        for p in self.layers[layer].parameters_mut() {
+            let selected = parameter.is_none_or(|name| name == p.name);
+            if selected && !self.frozen.contains(&(layer, p.name)) {
+                self.frozen.push((layer, p.name));
+            }
+        }
+    }
+
+    /// Undoes `freeze`. Layers made constant with `Layer::set_constant` stay frozen.
+    ///
+    /// Panics if the layer has no parameter of that name.
+    pub fn unfreeze(&mut self, layer: usize, parameter: Option<&str>) {
+        self.check_parameter("unfreeze", layer, parameter);
+        self.frozen
+            .retain(|&(l, name)| l != layer || parameter.is_some_and(|p| p != name));
+    }
+
+    fn check_parameter(&mut self, method: &str, layer: usize, parameter: Option<&str>) {
+        if let Some(name) = parameter {
+            let found = self.layers[layer]
+                .parameters_mut()
+                .iter()
+                .any(|p| p.name == name);
+            assert!(
+                found,
+                "NeuralNetwork::{}: layer {} has no parameter {}",
+                method, layer, name
+            );
+        }
+    }
+
+    /// Whether a parameter of a layer is frozen by `freeze` or by its group.
+    pub fn is_frozen(&self, layer: usize, parameter: &str) -> bool {
+        self.frozen.contains(&(layer, parameter))
+            || find_group(&self.groups, layer, parameter).is_some_and(|group| group.frozen)
+    }
+
+    /// Replaces the default softmax output head.
+    pub fn with_head<H>(mut self, head: H) -> Self
+    where
+        H: Head + 'static,
+    {
+        self.head = Box::new(head);
+        self
+    }
+
+    /// Trains the network with `loss` on the output of the head instead of the loss paired with
+    /// the head, e.g. the mean squared error of softmax probabilities.
+    pub fn with_loss<L>(mut self, loss: L) -> Self
+    where
+        L: Loss + 'static,
+    {
+        self.loss = Some(Box::new(loss));
+        self
+    }
+
+    /// Computes the loss of an output of the network.
+    fn output_loss(&self, output: &Vector, target: &Vector) -> f64 {
+        match &self.loss {
+            Some(loss) => loss.loss(output, target),
+            None => self.head.loss(output, target),
+        }
+    }
+
+    /// Computes the gradient of the loss wrt the input of the head.
+    fn output_gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        match &self.loss {
+            Some(loss) if self.head.paired_loss() != Some(loss.name()) => self
+                .head
+                .backpropagate(&loss.gradient(output, target), output),
+            _ => self.head.backward(output, target),
         }
     }
 
     pub fn forward(&mut self, input: Vector) -> &Vector {
         self.intermediates = vec![input];
-        for layer in &self.layers {
+        for layer in &mut self.layers {
             let output = layer.forward(self.intermediates.last().unwrap());
             self.intermediates.push(output);
         }
         self.intermediates
-            .push(self.intermediates.last().unwrap().softmax());
-        return self.intermediates.last().unwrap();
-    }
-
-    pub fn backward(&mut self, target: &Vector) -> Vec<(Matrix, Vector)> {
-        let mut dl_dz = self.intermediates.pop().unwrap().subtract(target);
-        let mut gradients = Vec::new();
-        for i in (0..self.layers.len()).rev() {
-            let input = &self.intermediates[i];
-            let output = &self.intermediates[i + 1];
-            let layer = &self.layers[i];
-            let (dl_dx, dl_dw, dl_db) = layer.backward(&dl_dz, input, None, Some(output));
-            gradients.push((dl_dw, dl_db));
-            dl_dz = dl_dx;
-        }
-        gradients.reverse();
-        gradients
+            .push(self.head.forward(self.intermediates.last().unwrap()));
+        self.intermediates.last().unwrap()
+    }
+
+    /// Runs a forward pass on a dual vector without recording intermediates, carrying the
+    /// directional derivative of every output along the tangent of `input`.
+    ///
+    /// Fails on the first module that does not support forward-mode differentiation
+    pub fn forward_dual(&self, input: DualVector) -> Result<DualVector, UnsupportedModule> {
+        let mut output = input;
+        for layer in &self.layers {
+            output = layer
+                .forward_dual(&output)
+                .ok_or(UnsupportedModule { name: layer.name() })?;
+        }
+        Ok(self.head.forward_dual(output))
+    }
+
+    /// Computes the gradients of every layer for the last forward pass.
+    ///
+    /// Returns the gradient of the loss wrt the input of the network
+    pub fn backward(&mut self, target: &Vector) -> Vector {
+        let output = self.intermediates.pop().unwrap();
+        let mut dl_dz = self.output_gradient(&output, target);
+        for layer in self.layers.iter_mut().rev() {
+            dl_dz = layer.backward(&dl_dz);
+        }
+        dl_dz
     }
 
+    /// Computes the loss of the last forward pass, including the regularization penalty.
     pub fn loss(&self, target: &Vector) -> f64 {
-        self.intermediates
-            .last()
-            .unwrap()
-            .cross_entropy_loss(target)
+        self.output_loss(self.intermediates.last().unwrap(), target) + self.penalty()
+    }
+
+    /// Sums the regularization penalties of every layer.
+    pub fn penalty(&self) -> f64 {
+        self.layers.iter().map(|layer| layer.penalty()).sum()
+    }
+
+    /// Runs a forward pass on a batch with one sample per row.
+    pub fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let mut output = input.clone();
+        for layer in &mut self.layers {
+            output = layer.forward_batch(&output);
+        }
+        let rows = output
+            .rows()
+            .iter()
+            .map(|row| self.head.forward(row))
+            .collect::<Vec<Vector>>();
+        Matrix::from_rows(&rows)
+    }
+
+    /// Computes the gradients of every layer for the last `forward_batch`, averaged over the batch.
+    ///
+    /// Returns the gradient of the loss wrt the input of the network
+    pub fn backward_batch(&mut self, output: &Matrix, target: &Matrix) -> Matrix {
+        let batch_size = output.dims[0] as f64;
+        let rows = (0..output.dims[0])
+            .map(|i| {
+                self.output_gradient(&output.row(i), &target.row(i))
+                    .scale(1.0 / batch_size)
+            })
+            .collect::<Vec<Vector>>();
+        let mut dl_dz = Matrix::from_rows(&rows);
+        for layer in self.layers.iter_mut().rev() {
+            dl_dz = layer.backward_batch(&dl_dz);
+        }
+        dl_dz
+    }
+
+    /// Computes the mean loss over the rows of a batch, plus the regularization penalty.
+    pub fn loss_batch(&self, output: &Matrix, target: &Matrix) -> f64 {
+        assert!(output.dims == target.dims);
+        (0..output.dims[0])
+            .map(|i| self.output_loss(&output.row(i), &target.row(i)))
+            .sum::<f64>()
+            / output.dims[0] as f64
+            + self.penalty()
     }
 
     // returns loss
     pub fn train(&mut self, input: Vector, target: &Vector) -> f64 {
         self.forward(input);
         let loss = self.loss(target);
-        let gradients = self.backward(target);
-        for (i, (dl_dw, dl_db)) in gradients.iter().enumerate() {
-            if (self.layers[i].constant) {
-                continue;
+        self.backward(target);
+        self.update();
+        loss
+    }
+
+    /// Trains on a batch with one sample per row, stepping along the averaged gradient.
+    ///
+    /// Returns the mean loss of the batch
+    pub fn train_batch(&mut self, input: &Matrix, target: &Matrix) -> f64 {
+        let output = self.forward_batch(input);
+        let loss = self.loss_batch(&output, target);
+        self.backward_batch(&output, target);
+        self.update();
+        loss
+    }
+
+    fn update(&mut self) {
+        if let Some(scheduler) = &mut self.scheduler {
+            let base = *self
+                .base_learning_rate
+                .get_or_insert(self.optimizer.learning_rate());
+            let learning_rate = scheduler.learning_rate(base, self.steps, self.epochs);
+            self.optimizer.set_learning_rate(learning_rate);
+        }
+        self.steps += 1;
+        self.optimizer.begin_step();
+        let learning_rate = self.optimizer.learning_rate();
+        let (groups, frozen) = (&self.groups, &self.frozen);
+        let mut parameters = self
+            .layers
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(l, layer)| layer.parameters_mut().into_iter().map(move |p| (l, p)))
+            .enumerate()
+            .filter_map(|(i, (l, parameter))| {
+                let group = find_group(groups, l, parameter.name);
+                let trainable = !parameter.frozen
+                    && !frozen.contains(&(l, parameter.name))
+                    && !group.is_some_and(|group| group.frozen);
+                trainable.then_some((i, group, parameter))
+            })
+            .collect::<Vec<_>>();
+        let mut gradients = parameters
+            .iter_mut()
+            .map(|(_, _, parameter)| &mut *parameter.gradient)
+            .collect::<Vec<&mut [f64]>>();
+        self.gradient_norm = match &self.clipping {
+            Some(clipping) => clipping.clip(&mut gradients),
+            None => clipping::global_norm(&gradients),
+        };
+        for (i, group, parameter) in parameters {
+            let (scale, weight_decay) =
+                group.map_or((1.0, 0.0), |g| (g.learning_rate_scale, g.weight_decay));
+            self.optimizer.set_learning_rate(learning_rate * scale);
+            if weight_decay != 0.0 {
+                let decay = 1.0 - learning_rate * scale * weight_decay;
+                parameter.value.iter_mut().for_each(|w| *w *= decay);
+            }
+            match parameter.sparse {
+                Some(rows) => {
+                    self.optimizer
+                        .update_rows(i, parameter.value, parameter.gradient, rows)
+                }
+                None => self
+                    .optimizer
+                    .update(i, parameter.value, parameter.gradient),
             }
-            self.layers[i].weights = self.layers[i].weights.subtract(&dl_dw.scale(0.1));
-            self.layers[i].biases = self.layers[i].biases.subtract(&dl_db.scale(0.1));
         }
-        loss
+        self.optimizer.set_learning_rate(learning_rate);
+        for layer in &mut self.layers {
+            layer.apply_constraints();
+        }
+    }
+}
+
+/// Returns the last group containing a parameter of a layer.
+fn find_group<'a>(
+    groups: &'a [ParameterGroup],
+    layer: usize,
+    parameter: &str,
+) -> Option<&'a ParameterGroup> {
+    groups
+        .iter()
+        .rev()
+        .find(|group| group.contains(layer, parameter))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matrix::Matrix;
+    use crate::{Layer, NeuralNetwork};
+
+    #[test]
+    fn test_multi_output_regression() {
+        let mut nn = NeuralNetwork::regression(vec![Layer::random((2, 2), (-0.5, 0.5))]);
+        let inputs = Matrix::from(vec![
+            vec![0.0, 0.0],
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![1.0, 1.0],
+        ]);
+        // (x + 2y - 1, 3 - x)
+        let targets = Matrix::from(vec![
+            vec![-1.0, 3.0],
+            vec![0.0, 2.0],
+            vec![1.0, 3.0],
+            vec![2.0, 2.0],
+        ]);
+        for _ in 0..2000 {
+            nn.train_batch(&inputs, &targets);
+        }
+        let output = nn.forward_batch(&inputs);
+        assert!(nn.loss_batch(&output, &targets) < 1e-6);
     }
 }
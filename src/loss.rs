@@ -0,0 +1,507 @@
+use std::fmt::Debug;
+
+use crate::vector::{clamp_probability, Vector};
+
+/// Loss between the output of a `NeuralNetwork` (after its head) and a target.
+///
+/// Regression losses are averaged over the outputs, while the classification losses are summed
+/// over the classes like `Vector::cross_entropy_loss`.
+pub trait Loss: Debug {
+    fn name(&self) -> &'static str;
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64;
+
+    /// Computes the gradient of the loss wrt the output.
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector;
+
+    /// Settings of the loss, e.g. the `delta` of `Huber`, in the order `from_name` expects them.
+    fn parameters(&self) -> Vec<f64> {
+        vec![]
+    }
+}
+
+fn elementwise<F>(output: &Vector, target: &Vector, f: F) -> Vector
+where
+    F: Fn(f64, f64) -> f64,
+{
+    assert!(output.len() == target.len());
+    output
+        .iter()
+        .zip(target.iter())
+        .map(|(o, t)| f(*o, *t))
+        .collect::<Vec<f64>>()
+        .into()
+}
+
+/// Mean squared error.
+#[derive(Debug, Clone, Copy)]
+pub struct Mse;
+
+impl Loss for Mse {
+    fn name(&self) -> &'static str {
+        "mse"
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        let diff = output.subtract(target);
+        diff.dot(&diff) / output.len() as f64
+    }
+
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        let n = output.len() as f64;
+        elementwise(output, target, |o, t| 2.0 * (o - t) / n)
+    }
+}
+
+/// Mean absolute error.
+#[derive(Debug, Clone, Copy)]
+pub struct Mae;
+
+impl Loss for Mae {
+    fn name(&self) -> &'static str {
+        "mae"
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        elementwise(output, target, |o, t| (o - t).abs())
+            .iter()
+            .sum::<f64>()
+            / output.len() as f64
+    }
+
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        let n = output.len() as f64;
+        elementwise(output, target, |o, t| {
+            if o > t {
+                1.0 / n
+            } else if o < t {
+                -1.0 / n
+            } else {
+                0.0
+            }
+        })
+    }
+}
+
+/// Mean Huber loss: quadratic for errors up to `delta` and linear beyond.
+#[derive(Debug, Clone, Copy)]
+pub struct Huber {
+    pub delta: f64,
+}
+
+impl Huber {
+    pub fn new(delta: f64) -> Huber {
+        assert!(delta > 0.0);
+        Huber { delta }
+    }
+}
+
+impl Loss for Huber {
+    fn name(&self) -> &'static str {
+        "huber"
+    }
+
+    fn parameters(&self) -> Vec<f64> {
+        vec![self.delta]
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        let huber = |o: f64, t: f64| {
+            let diff = (o - t).abs();
+            if diff <= self.delta {
+                0.5 * diff * diff
+            } else {
+                self.delta * (diff - 0.5 * self.delta)
+            }
+        };
+        elementwise(output, target, huber).iter().sum::<f64>() / output.len() as f64
+    }
+
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        let n = output.len() as f64;
+        elementwise(output, target, |o, t| {
+            (o - t).clamp(-self.delta, self.delta) / n
+        })
+    }
+}
+
+/// Binary cross entropy of probabilities, e.g. from a sigmoid head.
+#[derive(Debug, Clone, Copy)]
+pub struct BinaryCrossEntropy;
+
+impl Loss for BinaryCrossEntropy {
+    fn name(&self) -> &'static str {
+        "binary_cross_entropy"
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        output.binary_cross_entropy_loss(target)
+    }
+
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        elementwise(output, target, |o, t| {
+            let o = clamp_probability(o);
+            (o - t) / (o * (1.0 - o))
+        })
+    }
+}
+
+/// Binary cross entropy of raw logits, computed stably without going through probabilities.
+#[derive(Debug, Clone, Copy)]
+pub struct BinaryCrossEntropyWithLogits;
+
+impl Loss for BinaryCrossEntropyWithLogits {
+    fn name(&self) -> &'static str {
+        "binary_cross_entropy_with_logits"
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        elementwise(output, target, |x, t| {
+            x.max(0.0) - x * t + (-x.abs()).exp().ln_1p()
+        })
+        .iter()
+        .sum::<f64>()
+    }
+
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        elementwise(output, target, |x, t| 1.0 / (1.0 + (-x).exp()) - t)
+    }
+}
+
+/// Categorical cross entropy of a probability distribution, e.g. from a softmax head.
+#[derive(Debug, Clone, Copy)]
+pub struct CategoricalCrossEntropy;
+
+impl Loss for CategoricalCrossEntropy {
+    fn name(&self) -> &'static str {
+        "categorical_cross_entropy"
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        elementwise(output, target, |o, t| -t * clamp_probability(o).ln())
+            .iter()
+            .sum::<f64>()
+    }
+
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        elementwise(output, target, |o, t| -t / clamp_probability(o))
+    }
+}
+
+/// Negative log likelihood of log-probabilities, e.g. from a log-softmax head.
+#[derive(Debug, Clone, Copy)]
+pub struct NegativeLogLikelihood;
+
+impl Loss for NegativeLogLikelihood {
+    fn name(&self) -> &'static str {
+        "negative_log_likelihood"
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        -output.dot(target)
+    }
+
+    fn gradient(&self, _output: &Vector, target: &Vector) -> Vector {
+        target.iter().map(|t| -t).collect::<Vec<f64>>().into()
+    }
+}
+
+/// Hinge loss summed over the outputs, with targets of -1 or 1.
+#[derive(Debug, Clone, Copy)]
+pub struct Hinge;
+
+impl Loss for Hinge {
+    fn name(&self) -> &'static str {
+        "hinge"
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        elementwise(output, target, |o, t| (1.0 - t * o).max(0.0))
+            .iter()
+            .sum::<f64>()
+    }
+
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        elementwise(output, target, |o, t| if t * o < 1.0 { -t } else { 0.0 })
+    }
+}
+
+/// Kullback-Leibler divergence of the output distribution from the target distribution.
+#[derive(Debug, Clone, Copy)]
+pub struct KlDivergence;
+
+impl Loss for KlDivergence {
+    fn name(&self) -> &'static str {
+        "kl_divergence"
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        elementwise(output, target, |o, t| {
+            if t > 0.0 {
+                t * (t / clamp_probability(o)).ln()
+            } else {
+                0.0
+            }
+        })
+        .iter()
+        .sum::<f64>()
+    }
+
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        elementwise(output, target, |o, t| -t / clamp_probability(o))
+    }
+}
+
+/// Cosine embedding loss: `1 - cos(output, target)` for similar pairs, and
+/// `max(0, cos(output, target) - margin)` for dissimilar ones.
+#[derive(Debug, Clone, Copy)]
+pub struct CosineEmbedding {
+    pub similar: bool,
+    pub margin: f64,
+}
+
+impl CosineEmbedding {
+    pub fn similar() -> CosineEmbedding {
+        CosineEmbedding {
+            similar: true,
+            margin: 0.0,
+        }
+    }
+
+    pub fn dissimilar(margin: f64) -> CosineEmbedding {
+        CosineEmbedding {
+            similar: false,
+            margin,
+        }
+    }
+
+    fn cosine(output: &Vector, target: &Vector) -> (f64, f64, f64) {
+        let output_norm = output.dot(output).sqrt().max(1e-12);
+        let target_norm = target.dot(target).sqrt().max(1e-12);
+        (
+            output.dot(target) / (output_norm * target_norm),
+            output_norm,
+            target_norm,
+        )
+    }
+}
+
+impl Loss for CosineEmbedding {
+    fn name(&self) -> &'static str {
+        "cosine_embedding"
+    }
+
+    fn parameters(&self) -> Vec<f64> {
+        vec![self.similar as u8 as f64, self.margin]
+    }
+
+    fn loss(&self, output: &Vector, target: &Vector) -> f64 {
+        let (cosine, _, _) = CosineEmbedding::cosine(output, target);
+        if self.similar {
+            1.0 - cosine
+        } else {
+            (cosine - self.margin).max(0.0)
+        }
+    }
+
+    fn gradient(&self, output: &Vector, target: &Vector) -> Vector {
+        let (cosine, output_norm, target_norm) = CosineEmbedding::cosine(output, target);
+        let sign = if self.similar {
+            -1.0
+        } else if cosine > self.margin {
+            1.0
+        } else {
+            0.0
+        };
+        // d cos / d output = target / (|o| |t|) - cos * output / |o|^2
+        elementwise(output, target, |o, t| {
+            sign * (t / (output_norm * target_norm) - cosine * o / (output_norm * output_norm))
+        })
+    }
+}
+
+pub const MSE: Mse = Mse;
+
+pub const MAE: Mae = Mae;
+
+pub const BINARY_CROSS_ENTROPY: BinaryCrossEntropy = BinaryCrossEntropy;
+
+pub const BINARY_CROSS_ENTROPY_WITH_LOGITS: BinaryCrossEntropyWithLogits =
+    BinaryCrossEntropyWithLogits;
+
+pub const CATEGORICAL_CROSS_ENTROPY: CategoricalCrossEntropy = CategoricalCrossEntropy;
+
+pub const NEGATIVE_LOG_LIKELIHOOD: NegativeLogLikelihood = NegativeLogLikelihood;
+
+pub const HINGE: Hinge = Hinge;
+
+pub const KL_DIVERGENCE: KlDivergence = KlDivergence;
+
+/// Creates a loss from its name and `parameters`.
+pub fn from_name(name: &str, parameters: &[f64]) -> Option<Box<dyn Loss>> {
+    let loss: Box<dyn Loss> = match (name, parameters) {
+        ("mse", []) => Box::new(MSE),
+        ("mae", []) => Box::new(MAE),
+        ("huber", [delta]) if *delta > 0.0 => Box::new(Huber::new(*delta)),
+        ("binary_cross_entropy", []) => Box::new(BINARY_CROSS_ENTROPY),
+        ("binary_cross_entropy_with_logits", []) => Box::new(BINARY_CROSS_ENTROPY_WITH_LOGITS),
+        ("categorical_cross_entropy", []) => Box::new(CATEGORICAL_CROSS_ENTROPY),
+        ("negative_log_likelihood", []) => Box::new(NEGATIVE_LOG_LIKELIHOOD),
+        ("hinge", []) => Box::new(HINGE),
+        ("kl_divergence", []) => Box::new(KL_DIVERGENCE),
+        ("cosine_embedding", [similar, margin]) => Box::new(CosineEmbedding {
+            similar: *similar != 0.0,
+            margin: *margin,
+        }),
+        _ => return None,
+    };
+    Some(loss)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{check_network, max_error};
+    use crate::head::{IDENTITY, SIGMOID};
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    fn losses() -> Vec<(Box<dyn Loss>, Vector, Vector)> {
+        let probabilities = Vector::from(vec![0.2, 0.5, 0.3]);
+        let distribution = Vector::from(vec![0.1, 0.6, 0.3]);
+        let values = Vector::from(vec![0.3, -1.7, 2.2]);
+        let targets = Vector::from(vec![1.0, -0.5, 0.1]);
+        let labels = Vector::from(vec![1.0, -1.0, 1.0]);
+        vec![
+            (Box::new(MSE), values.clone(), targets.clone()),
+            (Box::new(MAE), values.clone(), targets.clone()),
+            (Box::new(Huber::new(1.0)), values.clone(), targets.clone()),
+            (
+                Box::new(BINARY_CROSS_ENTROPY),
+                probabilities.clone(),
+                Vector::from(vec![1.0, 0.0, 1.0]),
+            ),
+            (
+                Box::new(BINARY_CROSS_ENTROPY_WITH_LOGITS),
+                values.clone(),
+                Vector::from(vec![1.0, 0.0, 0.5]),
+            ),
+            (
+                Box::new(CATEGORICAL_CROSS_ENTROPY),
+                probabilities.clone(),
+                distribution.clone(),
+            ),
+            (
+                Box::new(NEGATIVE_LOG_LIKELIHOOD),
+                values.clone(),
+                distribution.clone(),
+            ),
+            (Box::new(HINGE), values.clone(), labels),
+            (Box::new(KL_DIVERGENCE), probabilities, distribution),
+            (
+                Box::new(CosineEmbedding::similar()),
+                values.clone(),
+                targets.clone(),
+            ),
+            (Box::new(CosineEmbedding::dissimilar(-0.5)), values, targets),
+        ]
+    }
+
+    #[test]
+    fn test_gradients() {
+        let step = 1e-6;
+        for (loss, output, target) in losses() {
+            let gradient = loss.gradient(&output, &target);
+            for i in 0..output.len() {
+                let mut plus = output.clone();
+                plus.data[i] += step;
+                let mut minus = output.clone();
+                minus.data[i] -= step;
+                let numeric =
+                    (loss.loss(&plus, &target) - loss.loss(&minus, &target)) / (2.0 * step);
+                assert!((gradient[i] - numeric).abs() < EPSILON, "{}", loss.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_values() {
+        let output = Vector::from(vec![0.5, 2.0]);
+        let target = Vector::from(vec![0.0, 0.0]);
+        assert!((MSE.loss(&output, &target) - 2.125).abs() < EPSILON);
+        assert!((MAE.loss(&output, &target) - 1.25).abs() < EPSILON);
+        assert!((Huber::new(1.0).loss(&output, &target) - 0.8125).abs() < EPSILON);
+        let same = Vector::from(vec![0.25, 0.75]);
+        assert!(KL_DIVERGENCE.loss(&same, &same).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_saturated_binary_cross_entropy() {
+        let target = Vector::from(vec![1.0]);
+        for o in [1e-8, 1e-11, 1.0 - 1e-8] {
+            let output = Vector::from(vec![o]);
+            let gradient = BINARY_CROSS_ENTROPY.gradient(&output, &target)[0];
+            let step = o.min(1.0 - o) * 1e-3;
+            let plus = BINARY_CROSS_ENTROPY.loss(&Vector::from(vec![o + step]), &target);
+            let minus = BINARY_CROSS_ENTROPY.loss(&Vector::from(vec![o - step]), &target);
+            let numeric = (plus - minus) / (2.0 * step);
+            assert!(((gradient - numeric) / gradient).abs() < 1e-4);
+        }
+        // the loss and its gradient saturate at the same probability
+        let zero = Vector::from(vec![0.0]);
+        let tiny = Vector::from(vec![1e-14]);
+        assert_eq!(
+            BINARY_CROSS_ENTROPY.loss(&zero, &target),
+            BINARY_CROSS_ENTROPY.loss(&tiny, &target)
+        );
+        assert_eq!(
+            BINARY_CROSS_ENTROPY.gradient(&zero, &target),
+            BINARY_CROSS_ENTROPY.gradient(&tiny, &target)
+        );
+    }
+
+    #[test]
+    fn test_network_losses() {
+        type WithLoss = fn(NeuralNetwork) -> NeuralNetwork;
+        let networks: Vec<(&str, WithLoss)> = vec![
+            ("softmax + mse", |nn| nn.with_loss(MSE)),
+            ("softmax + kl", |nn| nn.with_loss(KL_DIVERGENCE)),
+            ("sigmoid + bce", |nn| {
+                nn.with_head(SIGMOID).with_loss(BINARY_CROSS_ENTROPY)
+            }),
+            ("identity + huber", |nn| {
+                nn.with_head(IDENTITY).with_loss(Huber::new(0.5))
+            }),
+            ("identity + hinge", |nn| {
+                nn.with_head(IDENTITY).with_loss(HINGE)
+            }),
+        ];
+        for (name, with_loss) in networks {
+            let mut nn = with_loss(NeuralNetwork::new(vec![
+                Layer::random((3, 4), (-1.0, 1.0)),
+                Layer::random((4, 2), (-1.0, 1.0)),
+            ]));
+            let input = Vector::from(vec![0.5, -0.3, 0.8]);
+            let target = Vector::from(vec![0.3, 0.7]);
+            for result in check_network(&mut nn, &input, &target) {
+                assert!(max_error(&result) < 1e-6, "{}: {:?}", name, result);
+            }
+        }
+    }
+
+    #[test]
+    fn test_paired_loss_matches_head() {
+        let mut plain = NeuralNetwork::new(vec![Layer::random((3, 3), (-1.0, 1.0))]);
+        let input = Vector::from(vec![0.5, -0.3, 0.8]);
+        let target = Vector::from(vec![0.0, 1.0, 0.0]);
+        plain.forward(input.clone());
+        let expected = (plain.loss(&target), plain.backward(&target));
+        let mut nn = NeuralNetwork::from_modules(plain.layers).with_loss(CATEGORICAL_CROSS_ENTROPY);
+        nn.forward(input);
+        assert!((nn.loss(&target) - expected.0).abs() < EPSILON);
+        for (r, e) in nn.backward(&target).iter().zip(expected.1.iter()) {
+            assert!((r - e).abs() < EPSILON);
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use crate::ops::Scale;
 use crate::vector::Vector;
 use rand::Rng;
+use std::ops::Range;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Matrix {
     pub data: Box<Vec<f64>>,
     pub dims: Vec<usize>,
@@ -28,6 +29,17 @@ impl Matrix {
         }
     }
 
+    /// Creates a zero matrix with the same dims and memory layout as `other`, so that both can be
+    /// traversed through `data` in lockstep.
+    pub fn zero_like(other: &Matrix) -> Matrix {
+        Matrix {
+            data: Box::new(vec![0.0; other.size]),
+            dims: other.dims.clone(),
+            step: other.step.clone(),
+            size: other.size,
+        }
+    }
+
     pub fn random(dims: (usize, usize), bounds: (f64, f64)) -> Matrix {
         let mut result = Matrix::zero(dims);
         let range = bounds.1 - bounds.0;
@@ -54,6 +66,167 @@ impl Matrix {
         }
     }
 
+    /// Stacks vectors of equal length as the rows of a matrix.
+    pub fn from_rows(rows: &[Vector]) -> Matrix {
+        Matrix::from(
+            rows.iter()
+                .map(|row| row.data.clone())
+                .collect::<Vec<Vec<f64>>>(),
+        )
+    }
+
+    /// Reshapes a vector into a matrix, filling it row by row.
+    pub fn from_vector(vec: Vector, dims: (usize, usize)) -> Matrix {
+        assert!(vec.len() == dims.0 * dims.1);
+        Matrix {
+            data: Box::new(vec.data),
+            dims: vec![dims.0, dims.1],
+            step: vec![dims.1, 1],
+            size: dims.0 * dims.1,
+        }
+    }
+
+    pub fn row(&self, i: usize) -> Vector {
+        (0..self.dims[1])
+            .map(|j| self.get(i, j))
+            .collect::<Vec<f64>>()
+            .into()
+    }
+
+    pub fn rows(&self) -> Vec<Vector> {
+        (0..self.dims[0]).map(|i| self.row(i)).collect()
+    }
+
+    /// Flattens the matrix row by row.
+    pub fn to_vector(&self) -> Vector {
+        let mut data = Vec::with_capacity(self.size);
+        for i in 0..self.dims[0] {
+            for j in 0..self.dims[1] {
+                data.push(self.get(i, j));
+            }
+        }
+        data.into()
+    }
+
+    pub fn add(&self, other: &Matrix) -> Matrix {
+        assert!(self.dims == other.dims);
+        let mut result = Matrix::zero((self.dims[0], self.dims[1]));
+        for i in 0..self.dims[0] {
+            for j in 0..self.dims[1] {
+                *result.get_mut(i, j) = self.get(i, j) + other.get(i, j);
+            }
+        }
+        result
+    }
+
+    /// Adds `vec` to every row of the matrix.
+    pub fn add_row(&self, vec: &Vector) -> Matrix {
+        assert!(self.dims[1] == vec.len());
+        let mut result = Matrix::zero((self.dims[0], self.dims[1]));
+        for i in 0..self.dims[0] {
+            for j in 0..self.dims[1] {
+                *result.get_mut(i, j) = self.get(i, j) + vec[j];
+            }
+        }
+        result
+    }
+
+    /// Sums the rows of the matrix into a single row.
+    pub fn sum_rows(&self) -> Vector {
+        let mut result = vec![0.0; self.dims[1]];
+        for i in 0..self.dims[0] {
+            for (j, r) in result.iter_mut().enumerate() {
+                *r += self.get(i, j);
+            }
+        }
+        result.into()
+    }
+
+    pub fn map<F>(&self, f: F) -> Matrix
+    where
+        F: Fn(f64) -> f64,
+    {
+        let mut result = Matrix::zero((self.dims[0], self.dims[1]));
+        for i in 0..self.dims[0] {
+            for j in 0..self.dims[1] {
+                *result.get_mut(i, j) = f(self.get(i, j));
+            }
+        }
+        result
+    }
+
+    /// Combines the matrix elementwise with another one of the same dims.
+    pub fn zip_map<F>(&self, other: &Matrix, f: F) -> Matrix
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        assert!(self.dims == other.dims);
+        let mut result = Matrix::zero((self.dims[0], self.dims[1]));
+        for i in 0..self.dims[0] {
+            for j in 0..self.dims[1] {
+                *result.get_mut(i, j) = f(self.get(i, j), other.get(i, j));
+            }
+        }
+        result
+    }
+
+    /// Multiplies the matrix elementwise with another one of the same dims.
+    pub fn hadamard(&self, other: &Matrix) -> Matrix {
+        self.zip_map(other, |a, b| a * b)
+    }
+
+    /// Copies the columns in `range` into a new matrix.
+    pub fn columns(&self, range: Range<usize>) -> Matrix {
+        assert!(range.end <= self.dims[1]);
+        let mut result = Matrix::zero((self.dims[0], range.len()));
+        for i in 0..self.dims[0] {
+            for (k, j) in range.clone().enumerate() {
+                *result.get_mut(i, k) = self.get(i, j);
+            }
+        }
+        result
+    }
+
+    /// Copies the block at the intersection of `rows` and `columns` into a new matrix.
+    pub fn slice(&self, rows: Range<usize>, columns: Range<usize>) -> Matrix {
+        assert!(rows.end <= self.dims[0] && columns.end <= self.dims[1]);
+        let mut result = Matrix::zero((rows.len(), columns.len()));
+        for (k, i) in rows.enumerate() {
+            for (l, j) in columns.clone().enumerate() {
+                *result.get_mut(k, l) = self.get(i, j);
+            }
+        }
+        result
+    }
+
+    /// Overwrites the block of the matrix starting at `offset` (row, column) with `block`.
+    pub fn set_slice(&mut self, offset: (usize, usize), block: &Matrix) {
+        assert!(offset.0 + block.dims[0] <= self.dims[0]);
+        assert!(offset.1 + block.dims[1] <= self.dims[1]);
+        for i in 0..block.dims[0] {
+            for j in 0..block.dims[1] {
+                *self.get_mut(offset.0 + i, offset.1 + j) = block.get(i, j);
+            }
+        }
+    }
+
+    /// Places matrices with the same number of rows side by side.
+    pub fn concat_columns(parts: &[Matrix]) -> Matrix {
+        let rows = parts[0].dims[0];
+        let mut result = Matrix::zero((rows, parts.iter().map(|p| p.dims[1]).sum()));
+        let mut offset = 0;
+        for part in parts {
+            assert!(part.dims[0] == rows);
+            for i in 0..rows {
+                for j in 0..part.dims[1] {
+                    *result.get_mut(i, offset + j) = part.get(i, j);
+                }
+            }
+            offset += part.dims[1];
+        }
+        result
+    }
+
     pub fn subtract(&self, other: &Matrix) -> Matrix {
         assert!(self.dims == other.dims);
         let mut result = Matrix::zero((self.dims[0], self.dims[1]));
@@ -84,15 +257,34 @@ impl Multiply<Vector, Vector> for Matrix {
     fn multiply(&self, vec: &Vector) -> Vector {
         assert!(self.dims[1] == vec.len());
         let mut res = vec![0.0; self.dims[0]];
-        for i in 0..self.dims[0] {
+        for (i, r) in res.iter_mut().enumerate() {
             for j in 0..self.dims[1] {
-                res[i] += self.data[i * self.step[0] + j * self.step[1]] * vec[j];
+                *r += self.data[i * self.step[0] + j * self.step[1]] * vec[j];
             }
         }
         Vector::from(res)
     }
 }
 
+impl Multiply<Matrix, Matrix> for Matrix {
+    fn multiply(&self, other: &Matrix) -> Matrix {
+        assert!(self.dims[1] == other.dims[0]);
+        let mut result = Matrix::zero((self.dims[0], other.dims[1]));
+        for i in 0..self.dims[0] {
+            for k in 0..self.dims[1] {
+                let a = self.get(i, k);
+                if a == 0.0 {
+                    continue;
+                }
+                for j in 0..other.dims[1] {
+                    result.data[i * result.step[0] + j] += a * other.get(k, j);
+                }
+            }
+        }
+        result
+    }
+}
+
 impl Scale<&Vector> for Matrix {
     fn scale(&self, vec: &Vector) -> Matrix {
         assert!(self.dims[0] == vec.len());
@@ -160,4 +352,48 @@ mod tests {
         let result = matrix.transpose().multiply(&vec);
         assert_eq!(result, vec![5.0, 8.0, 11.0]);
     }
+
+    #[test]
+    fn test_matrix_matrix_multiply() {
+        let matrix1 = Matrix::from(vec![vec![1.0, 2.0, 3.0], vec![3.0, 4.0, 5.0]]);
+        let matrix2 = Matrix::from(vec![vec![1.0, 0.0], vec![2.0, -1.0], vec![0.5, 1.0]]);
+        let result = matrix1.multiply(&matrix2);
+        assert_eq!(result.dims, vec![2, 2]);
+        assert_eq!(*result.data, vec![6.5, 1.0, 13.5, 1.0]);
+        let result = matrix1.transpose().multiply(&matrix2.transpose());
+        assert_eq!(result.dims, vec![3, 3]);
+        assert_eq!(result.row(0), vec![1.0, -1.0, 3.5]);
+    }
+
+    #[test]
+    fn test_matrix_rows() {
+        let matrix = Matrix::from(vec![vec![1.0, 2.0, 3.0], vec![3.0, 4.0, 5.0]]);
+        assert_eq!(
+            matrix.add_row(&Vector::from(vec![1.0, 0.0, -1.0])).row(1),
+            vec![4.0, 4.0, 4.0]
+        );
+        assert_eq!(matrix.sum_rows(), vec![4.0, 6.0, 8.0]);
+        assert_eq!(
+            matrix.transpose().to_vector(),
+            vec![1.0, 3.0, 2.0, 4.0, 3.0, 5.0]
+        );
+    }
+
+    #[test]
+    fn test_matrix_columns() {
+        let matrix = Matrix::from(vec![vec![1.0, 2.0, 3.0], vec![3.0, 4.0, 5.0]]);
+        let left = matrix.columns(0..1);
+        let right = matrix.transpose().transpose().columns(1..3);
+        assert_eq!(right.row(1), vec![4.0, 5.0]);
+        let mut joined = Matrix::concat_columns(&[left, right]);
+        assert_eq!(*joined.data, *matrix.data);
+        let block = matrix.transpose().slice(1..3, 1..2);
+        assert_eq!(*block.data, vec![4.0, 5.0]);
+        joined.set_slice((0, 1), &block.transpose());
+        assert_eq!(joined.row(0), vec![1.0, 4.0, 5.0]);
+        assert_eq!(
+            *matrix.hadamard(&matrix.map(|x| x - 1.0)).data,
+            vec![0.0, 2.0, 6.0, 6.0, 12.0, 20.0]
+        );
+    }
 }
@@ -0,0 +1,572 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::rc::Rc;
+
+use crate::matrix::Matrix;
+use crate::trainer::{Callback, Epoch};
+use crate::vector::{clamp_probability, Vector};
+use crate::NeuralNetwork;
+
+/// Metric computed from the predictions of a network and their targets, one sample per vector.
+///
+/// Closures can fix the other arguments of a metric, e.g. the `k` of `top_k_accuracy`.
+pub type Metric = Box<dyn Fn(&[Vector], &[Vector]) -> f64>;
+
+/// Class of a prediction or a one-hot target: the index of its largest value, or whether the
+/// value reaches 0.5 if there is a single one.
+pub fn class_of(vec: &Vector) -> usize {
+    if vec.len() == 1 {
+        return (vec.data[0] >= 0.5) as usize;
+    }
+    vec.iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        .map(|(i, _)| i)
+        .unwrap()
+}
+
+fn number_of_classes(targets: &[Vector]) -> usize {
+    match targets.first().map(Vector::len) {
+        Some(1) => 2,
+        Some(n) => n,
+        None => 0,
+    }
+}
+
+/// Fraction of the predictions of the right class.
+///
+/// Panics if there is no prediction.
+pub fn accuracy(predictions: &[Vector], targets: &[Vector]) -> f64 {
+    assert!(predictions.len() == targets.len());
+    assert!(!predictions.is_empty(), "accuracy: no predictions");
+    let correct = predictions
+        .iter()
+        .zip(targets)
+        .filter(|(p, t)| class_of(p) == class_of(t))
+        .count();
+    correct as f64 / predictions.len() as f64
+}
+
+/// Fraction of the predictions that rank the right class among their `k` largest values.
+///
+/// A single binary output stands for two classes, as in `class_of`. Panics if there is no
+/// prediction.
+pub fn top_k_accuracy(predictions: &[Vector], targets: &[Vector], k: usize) -> f64 {
+    assert!(predictions.len() == targets.len());
+    assert!(!predictions.is_empty(), "top_k_accuracy: no predictions");
+    let correct = predictions
+        .iter()
+        .zip(targets)
+        .filter(|(p, t)| {
+            if p.len() == 1 {
+                return k >= 2 || class_of(p) == class_of(t);
+            }
+            let target = p.data[class_of(t)];
+            p.iter().filter(|x| **x > target).count() < k
+        })
+        .count();
+    correct as f64 / predictions.len() as f64
+}
+
+/// Averaging of a per-class metric over the classes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Average {
+    /// Computes the metric from the counts of all the classes together.
+    Micro,
+    /// Takes the mean of the metric over the classes.
+    Macro,
+    /// Takes the mean of the metric over the classes, weighted by their number of samples.
+    Weighted,
+}
+
+/// Counts of the predicted classes for every true class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfusionMatrix {
+    /// `counts[i][j]` samples of class `i` were predicted as class `j`
+    pub counts: Vec<Vec<usize>>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(predictions: &[Vector], targets: &[Vector]) -> ConfusionMatrix {
+        assert!(predictions.len() == targets.len());
+        let classes = number_of_classes(targets);
+        let mut counts = vec![vec![0; classes]; classes];
+        for (p, t) in predictions.iter().zip(targets) {
+            counts[class_of(t)][class_of(p)] += 1;
+        }
+        ConfusionMatrix { counts }
+    }
+
+    pub fn classes(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().flatten().sum()
+    }
+
+    /// Fraction of the samples predicted as their class. Panics if there is no sample.
+    pub fn accuracy(&self) -> f64 {
+        assert!(self.total() > 0, "ConfusionMatrix::accuracy: no samples");
+        let correct = (0..self.classes())
+            .map(|i| self.counts[i][i])
+            .sum::<usize>();
+        correct as f64 / self.total() as f64
+    }
+
+    fn true_positives(&self, class: usize) -> usize {
+        self.counts[class][class]
+    }
+
+    /// Number of samples predicted as `class`.
+    fn predicted(&self, class: usize) -> usize {
+        self.counts.iter().map(|row| row[class]).sum()
+    }
+
+    /// Number of samples of `class`.
+    fn support(&self, class: usize) -> usize {
+        self.counts[class].iter().sum()
+    }
+
+    pub fn precision(&self, average: Average) -> f64 {
+        self.average(average, |c| {
+            ratio(self.true_positives(c), self.predicted(c))
+        })
+    }
+
+    pub fn recall(&self, average: Average) -> f64 {
+        self.average(average, |c| ratio(self.true_positives(c), self.support(c)))
+    }
+
+    pub fn f1(&self, average: Average) -> f64 {
+        if average == Average::Micro {
+            let (p, r) = (self.precision(average), self.recall(average));
+            return harmonic_mean(p, r);
+        }
+        self.average(average, |c| {
+            let p = ratio(self.true_positives(c), self.predicted(c));
+            let r = ratio(self.true_positives(c), self.support(c));
+            harmonic_mean(p, r)
+        })
+    }
+
+    fn average<F>(&self, average: Average, metric: F) -> f64
+    where
+        F: Fn(usize) -> f64,
+    {
+        let classes = 0..self.classes();
+        match average {
+            Average::Micro => {
+                let correct = classes.map(|c| self.true_positives(c)).sum();
+                ratio(correct, self.total())
+            }
+            Average::Macro => classes.map(metric).sum::<f64>() / self.classes() as f64,
+            Average::Weighted => {
+                classes
+                    .map(|c| metric(c) * self.support(c) as f64)
+                    .sum::<f64>()
+                    / self.total() as f64
+            }
+        }
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn harmonic_mean(p: f64, r: f64) -> f64 {
+    if p + r == 0.0 {
+        0.0
+    } else {
+        2.0 * p * r / (p + r)
+    }
+}
+
+/// Area under the ROC curve of binary `scores`, with `labels` of 1 for the positive samples and
+/// 0 for the others.
+///
+/// Returns `None` if the labels are all of the same class, for which the curve is undefined
+pub fn roc_auc(scores: &Vector, labels: &Vector) -> Option<f64> {
+    assert!(scores.len() == labels.len());
+    let order = descending(scores);
+    let positives = labels.iter().filter(|l| **l > 0.5).count();
+    let negatives = labels.len() - positives;
+    if positives == 0 || negatives == 0 {
+        return None;
+    }
+    // counts the pairs of a positive ranked above a negative, ties counting half
+    let (mut pairs, mut negatives_above) = (0.0, 0);
+    for group in ties(scores, &order) {
+        let p = group.iter().filter(|i| labels.data[**i] > 0.5).count();
+        let n = group.len() - p;
+        pairs += p as f64 * (negatives - negatives_above) as f64 - p as f64 * n as f64 / 2.0;
+        negatives_above += n;
+    }
+    Some(pairs / (positives * negatives) as f64)
+}
+
+/// Area under the precision-recall curve of binary `scores`, as the average precision over the
+/// recall steps.
+///
+/// Returns `None` if there is no positive label, for which recall is undefined
+pub fn pr_auc(scores: &Vector, labels: &Vector) -> Option<f64> {
+    assert!(scores.len() == labels.len());
+    let order = descending(scores);
+    let positives = labels.iter().filter(|l| **l > 0.5).count();
+    if positives == 0 {
+        return None;
+    }
+    let (mut area, mut true_positives, mut predicted) = (0.0, 0, 0);
+    for group in ties(scores, &order) {
+        let p = group.iter().filter(|i| labels.data[**i] > 0.5).count();
+        true_positives += p;
+        predicted += group.len();
+        area += p as f64 / positives as f64 * true_positives as f64 / predicted as f64;
+    }
+    Some(area)
+}
+
+/// Indices of the scores from the largest to the smallest.
+fn descending(scores: &Vector) -> Vec<usize> {
+    let mut order = (0..scores.len()).collect::<Vec<usize>>();
+    order.sort_by(|a, b| {
+        scores.data[*b]
+            .partial_cmp(&scores.data[*a])
+            .unwrap_or(Ordering::Equal)
+    });
+    order
+}
+
+/// Splits sorted indices into runs of equal scores.
+fn ties<'a>(scores: &'a Vector, order: &'a [usize]) -> impl Iterator<Item = &'a [usize]> {
+    order.chunk_by(move |a, b| scores.data[*a] == scores.data[*b])
+}
+
+/// Mean cross-entropy of predicted probabilities, one-hot targets or a single binary output.
+///
+/// Panics if there is no prediction.
+pub fn log_loss(predictions: &[Vector], targets: &[Vector]) -> f64 {
+    assert!(predictions.len() == targets.len());
+    assert!(!predictions.is_empty(), "log_loss: no predictions");
+    let total = predictions
+        .iter()
+        .zip(targets)
+        .map(|(p, t)| {
+            if p.len() == 1 {
+                let p = clamp_probability(p.data[0]);
+                -(t.data[0] * p.ln() + (1.0 - t.data[0]) * (1.0 - p).ln())
+            } else {
+                -p.iter()
+                    .zip(t.iter())
+                    .map(|(p, t)| t * clamp_probability(*p).ln())
+                    .sum::<f64>()
+            }
+        })
+        .sum::<f64>();
+    total / predictions.len() as f64
+}
+
+/// Pairs of a predicted and a target value over all the outputs.
+fn values<'a>(
+    predictions: &'a [Vector],
+    targets: &'a [Vector],
+) -> impl Iterator<Item = (f64, f64)> + 'a {
+    assert!(predictions.len() == targets.len());
+    predictions
+        .iter()
+        .zip(targets)
+        .flat_map(|(p, t)| p.iter().copied().zip(t.iter().copied()))
+}
+
+/// Number of target values, which the regression metrics need at least one of.
+fn count(targets: &[Vector]) -> f64 {
+    let count = targets.iter().map(Vector::len).sum::<usize>();
+    assert!(count > 0, "regression metric: no targets");
+    count as f64
+}
+
+pub fn mean_squared_error(predictions: &[Vector], targets: &[Vector]) -> f64 {
+    values(predictions, targets)
+        .map(|(p, t)| (p - t).powi(2))
+        .sum::<f64>()
+        / count(targets)
+}
+
+pub fn mean_absolute_error(predictions: &[Vector], targets: &[Vector]) -> f64 {
+    values(predictions, targets)
+        .map(|(p, t)| (p - t).abs())
+        .sum::<f64>()
+        / count(targets)
+}
+
+/// Coefficient of determination: 1 minus the squared error relative to that of predicting the
+/// mean target, over all the outputs together.
+///
+/// If all the targets are equal, the score is 1 for exact predictions and 0 otherwise, like that
+/// of predicting the mean.
+pub fn r2_score(predictions: &[Vector], targets: &[Vector]) -> f64 {
+    let mean = targets.iter().flat_map(Vector::iter).sum::<f64>() / count(targets);
+    let residual = values(predictions, targets)
+        .map(|(p, t)| (p - t).powi(2))
+        .sum::<f64>();
+    let variance = targets
+        .iter()
+        .flat_map(Vector::iter)
+        .map(|t| (t - mean).powi(2))
+        .sum::<f64>();
+    if variance == 0.0 {
+        return if residual == 0.0 { 1.0 } else { 0.0 };
+    }
+    1.0 - residual / variance
+}
+
+/// Callback evaluating metrics of the network on a dataset at the end of every epoch.
+///
+/// The values are shared through `results`, since the trainer owns its callbacks.
+pub struct MetricsCallback {
+    inputs: Matrix,
+    targets: Matrix,
+    metrics: Vec<(&'static str, Metric)>,
+    /// prints the metrics every `every` epochs if set
+    every: Option<usize>,
+    results: Rc<RefCell<Vec<Vec<f64>>>>,
+}
+
+impl MetricsCallback {
+    pub fn new(inputs: Matrix, targets: Matrix) -> MetricsCallback {
+        assert!(inputs.dims[0] == targets.dims[0]);
+        MetricsCallback {
+            inputs,
+            targets,
+            metrics: vec![],
+            every: None,
+            results: Rc::default(),
+        }
+    }
+
+    pub fn with_metric<F>(mut self, name: &'static str, metric: F) -> Self
+    where
+        F: Fn(&[Vector], &[Vector]) -> f64 + 'static,
+    {
+        self.metrics.push((name, Box::new(metric)));
+        self
+    }
+
+    /// Prints the metrics every `every` epochs.
+    pub fn print_every(mut self, every: usize) -> Self {
+        assert!(every > 0);
+        self.every = Some(every);
+        self
+    }
+
+    /// Values of the metrics, in the order they were added, for every epoch so far.
+    pub fn results(&self) -> Rc<RefCell<Vec<Vec<f64>>>> {
+        self.results.clone()
+    }
+}
+
+impl Debug for MetricsCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsCallback")
+            .field("inputs", &self.inputs)
+            .field("targets", &self.targets)
+            .field(
+                "metrics",
+                &self
+                    .metrics
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("every", &self.every)
+            .field("results", &self.results)
+            .finish()
+    }
+}
+
+impl Callback for MetricsCallback {
+    fn on_epoch_end(&mut self, epoch: &Epoch, nn: &mut NeuralNetwork) {
+        let training = nn.is_training();
+        nn.set_training(false);
+        let predictions = nn.forward_batch(&self.inputs).rows();
+        nn.set_training(training);
+        let targets = self.targets.rows();
+        let values = self
+            .metrics
+            .iter()
+            .map(|(_, metric)| metric(&predictions, &targets))
+            .collect::<Vec<f64>>();
+        if self
+            .every
+            .is_some_and(|every| epoch.index.is_multiple_of(every))
+        {
+            let line = self
+                .metrics
+                .iter()
+                .zip(&values)
+                .map(|((name, _), value)| format!("{} {}", name, value))
+                .collect::<Vec<String>>()
+                .join(", ");
+            println!("epoch {}: {}", epoch.index, line);
+        }
+        self.results.borrow_mut().push(values);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        accuracy, log_loss, mean_absolute_error, mean_squared_error, pr_auc, r2_score, roc_auc,
+        top_k_accuracy, Average, ConfusionMatrix, MetricsCallback,
+    };
+    use crate::activation::TANH;
+    use crate::matrix::Matrix;
+    use crate::optimizer::Sgd;
+    use crate::trainer::Trainer;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    fn vectors(rows: Vec<Vec<f64>>) -> Vec<Vector> {
+        rows.into_iter().map(Vector::from).collect()
+    }
+
+    fn one_hot(classes: &[usize], n: usize) -> Vec<Vector> {
+        classes
+            .iter()
+            .map(|c| Vector::from((0..n).map(|i| (i == *c) as u8 as f64).collect::<Vec<f64>>()))
+            .collect()
+    }
+
+    #[test]
+    fn test_classification() {
+        let predictions = vectors(vec![
+            vec![0.7, 0.2, 0.1],
+            vec![0.1, 0.6, 0.3],
+            vec![0.3, 0.3, 0.4],
+            vec![0.5, 0.4, 0.1],
+            vec![0.2, 0.5, 0.3],
+        ]);
+        let targets = one_hot(&[0, 1, 2, 1, 2], 3);
+        assert!((accuracy(&predictions, &targets) - 0.6).abs() < EPSILON);
+        assert!((top_k_accuracy(&predictions, &targets, 2) - 1.0).abs() < EPSILON);
+
+        let confusion = ConfusionMatrix::new(&predictions, &targets);
+        assert_eq!(
+            confusion.counts,
+            vec![vec![1, 0, 0], vec![1, 1, 0], vec![0, 1, 1]]
+        );
+        // per class precision (1/2, 1/2, 1), recall (1, 1/2, 1/2), f1 (2/3, 1/2, 2/3)
+        assert!((confusion.precision(Average::Micro) - 0.6).abs() < EPSILON);
+        assert!((confusion.precision(Average::Macro) - 2.0 / 3.0).abs() < EPSILON);
+        assert!((confusion.recall(Average::Macro) - 2.0 / 3.0).abs() < EPSILON);
+        assert!((confusion.recall(Average::Weighted) - 0.6).abs() < EPSILON);
+        assert!((confusion.f1(Average::Macro) - 11.0 / 18.0).abs() < EPSILON);
+        assert!((confusion.f1(Average::Weighted) - 3.0 / 5.0).abs() < EPSILON);
+        assert!((confusion.f1(Average::Micro) - 0.6).abs() < EPSILON);
+
+        let expected = -(0.7_f64.ln() + 0.6_f64.ln() + 0.4_f64.ln() + 0.4_f64.ln() + 0.3_f64.ln());
+        assert!((log_loss(&predictions, &targets) - expected / 5.0).abs() < EPSILON);
+
+        // a single binary output
+        let predictions = vectors(vec![vec![0.8], vec![0.3], vec![0.6]]);
+        let targets = vectors(vec![vec![1.0], vec![0.0], vec![0.0]]);
+        assert!((accuracy(&predictions, &targets) - 2.0 / 3.0).abs() < EPSILON);
+        assert!((top_k_accuracy(&predictions, &targets, 1) - 2.0 / 3.0).abs() < EPSILON);
+        assert!((top_k_accuracy(&predictions, &targets, 2) - 1.0).abs() < EPSILON);
+        let confusion = ConfusionMatrix::new(&predictions, &targets);
+        assert_eq!(confusion.counts, vec![vec![1, 1], vec![0, 1]]);
+    }
+
+    #[test]
+    fn test_ranking() {
+        let scores = Vector::from(vec![0.9, 0.8, 0.7, 0.6, 0.5, 0.4]);
+        let labels = Vector::from(vec![1.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+        // 7 of the 9 positive-negative pairs are ordered
+        assert!((roc_auc(&scores, &labels).unwrap() - 7.0 / 9.0).abs() < EPSILON);
+        let expected = (1.0 + 2.0 / 3.0 + 3.0 / 4.0) / 3.0;
+        assert!((pr_auc(&scores, &labels).unwrap() - expected).abs() < EPSILON);
+
+        let tied = Vector::from(vec![0.5; 6]);
+        assert!((roc_auc(&tied, &labels).unwrap() - 0.5).abs() < EPSILON);
+        assert!((pr_auc(&tied, &labels).unwrap() - 0.5).abs() < EPSILON);
+
+        let negative = Vector::from(vec![0.0; 6]);
+        assert_eq!(roc_auc(&scores, &negative), None);
+        assert_eq!(pr_auc(&scores, &negative), None);
+        assert_eq!(roc_auc(&scores, &Vector::from(vec![1.0; 6])), None);
+    }
+
+    #[test]
+    fn test_regression() {
+        let predictions = vectors(vec![vec![2.5], vec![0.0], vec![2.0], vec![8.0]]);
+        let targets = vectors(vec![vec![3.0], vec![-0.5], vec![2.0], vec![7.0]]);
+        assert!((mean_squared_error(&predictions, &targets) - 0.375).abs() < EPSILON);
+        assert!((mean_absolute_error(&predictions, &targets) - 0.5).abs() < EPSILON);
+        assert!((r2_score(&predictions, &targets) - 0.948608).abs() < EPSILON);
+
+        let constant = vectors(vec![vec![1.0], vec![1.0]]);
+        assert_eq!(r2_score(&constant, &constant), 1.0);
+        assert_eq!(r2_score(&predictions[..2], &constant), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "no predictions")]
+    fn test_empty_accuracy() {
+        accuracy(&[], &[]);
+    }
+
+    #[test]
+    fn test_metrics_callback() {
+        let inputs = Matrix::from(vec![
+            vec![0.0, 0.0],
+            vec![0.0, 1.0],
+            vec![1.0, 0.0],
+            vec![1.0, 1.0],
+        ]);
+        let targets = Matrix::from(vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![0.0, 1.0],
+            vec![1.0, 0.0],
+        ]);
+        let mut nn = NeuralNetwork::new(vec![
+            Layer::random((2, 8), (-1.0, 1.0)).with_activation(TANH),
+            Layer::random((8, 2), (-1.0, 1.0)),
+        ])
+        .with_optimizer(Sgd::new(0.5));
+        let callback = MetricsCallback::new(inputs.clone(), targets.clone())
+            .with_metric("accuracy", accuracy)
+            .with_metric("log loss", log_loss)
+            .with_metric("top 2", |p, t| top_k_accuracy(p, t, 2))
+            .with_metric("roc auc", |p, t| {
+                let scores = Vector::from(p.iter().map(|p| p[1]).collect::<Vec<f64>>());
+                let labels = Vector::from(t.iter().map(|t| t[1]).collect::<Vec<f64>>());
+                roc_auc(&scores, &labels).unwrap()
+            });
+        let results = callback.results();
+        let history = Trainer::new(20).batch_size(4).with_callback(callback).fit(
+            &mut nn,
+            &inputs,
+            &targets,
+            Some((&inputs, &targets)),
+        );
+        let results = results.borrow();
+        assert_eq!(results.len(), 20);
+        // the log loss on the validation set is the validation loss of the trainer
+        for (values, epoch) in results.iter().zip(&history.epochs) {
+            assert_eq!(values.len(), 4);
+            assert!((0.0..=1.0).contains(&values[0]));
+            assert_eq!(values[2], 1.0);
+            assert!((0.0..=1.0).contains(&values[3]));
+            assert!((values[1] - epoch.validation_loss.unwrap()).abs() < EPSILON);
+        }
+    }
+}
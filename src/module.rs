@@ -0,0 +1,225 @@
+use std::fmt::Debug;
+
+use crate::activation::Activation;
+use crate::dual::DualVector;
+use crate::matrix::Matrix;
+use crate::vector::Vector;
+
+/// A trainable tensor of a module together with its gradient, flattened to a slice.
+#[derive(Debug)]
+pub struct Parameter<'a> {
+    pub name: &'static str,
+    pub value: &'a mut [f64],
+    pub gradient: &'a mut [f64],
+    /// Whether the parameter is excluded from training updates.
+    pub frozen: bool,
+    /// Rows outside of which the gradient is zero, if it is known to be sparse.
+    pub sparse: Option<SparseRows<'a>>,
+}
+
+/// Rows of a parameter stored as a row-major matrix `width` values wide, e.g. the rows of an
+/// embedding table looked up by the last batch.
+#[derive(Debug, Clone, Copy)]
+pub struct SparseRows<'a> {
+    pub width: usize,
+    pub rows: &'a [usize],
+}
+
+/// A step of a `NeuralNetwork`, e.g. a dense `Layer`.
+///
+/// Modules cache whatever they need from `forward` so that the following `backward` can compute
+/// the gradients wrt their parameters.
+pub trait Module: Debug {
+    fn name(&self) -> &'static str;
+
+    /// Activation applied to the output of the module, if any.
+    fn activation(&self) -> Option<&dyn Activation> {
+        None
+    }
+
+    /// Computes the output of the module for a batch with one sample per row.
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix;
+
+    /// Computes the gradient of the loss wrt the parameters of the module, summed over the rows of
+    /// the batch, and stores it, given the gradient of the loss wrt the output of the last
+    /// `forward_batch` (dl_dz).
+    ///
+    /// Returns the gradient of the loss wrt the input of the module (dl_dx)
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix;
+
+    /// Computes the output of the module for a single sample.
+    fn forward(&mut self, input: &Vector) -> Vector {
+        self.forward_batch(&Matrix::from_rows(std::slice::from_ref(input)))
+            .row(0)
+    }
+
+    /// Single sample version of `backward_batch`.
+    fn backward(&mut self, dl_dz: &Vector) -> Vector {
+        self.backward_batch(&Matrix::from_rows(std::slice::from_ref(dl_dz)))
+            .row(0)
+    }
+
+    /// Computes the output of the module on a dual vector, without caching anything.
+    ///
+    /// Returns `None` if the module does not support forward-mode differentiation
+    fn forward_dual(&self, _input: &DualVector) -> Option<DualVector> {
+        None
+    }
+
+    /// Computes the regularization penalty of the parameters, which `backward` also adds to their
+    /// gradients.
+    fn penalty(&self) -> f64 {
+        0.0
+    }
+
+    /// Enforces constraints on the parameters after every training update, e.g. a max-norm.
+    fn apply_constraints(&mut self) {}
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        vec![]
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        vec![]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        vec![]
+    }
+
+    /// Named state that is not trained but belongs with the parameters in a checkpoint, e.g. the
+    /// running statistics of `BatchNorm`.
+    fn buffers(&self) -> Vec<(&'static str, &[f64])> {
+        vec![]
+    }
+
+    fn buffers_mut(&mut self) -> Vec<(&'static str, &mut [f64])> {
+        vec![]
+    }
+
+    /// Switches between training and inference behaviour, for modules that have both.
+    fn set_training(&mut self, _training: bool) {}
+
+    /// Forgets any state carried over between forward passes, e.g. by stateful recurrent layers.
+    fn reset_state(&mut self) {}
+
+    /// Sets the number of valid steps of every sequence of the next batches, for modules that
+    /// mask the padding after them, or `None` if no sequence is padded.
+    fn set_sequence_lengths(&mut self, _lengths: Option<&[usize]>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Module, Parameter};
+    use crate::gradcheck::{check_network, max_error};
+    use crate::matrix::Matrix;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    /// Elementwise learnable scaling, to check that other modules compose with `Layer`.
+    #[derive(Debug)]
+    struct Scaling {
+        scale: Vec<f64>,
+        gradient: Vec<f64>,
+        input: Matrix,
+    }
+
+    impl Module for Scaling {
+        fn name(&self) -> &'static str {
+            "scaling"
+        }
+
+        fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+            self.input = input.clone();
+            let mut output = input.clone();
+            for i in 0..input.dims[0] {
+                for j in 0..input.dims[1] {
+                    *output.get_mut(i, j) *= self.scale[j];
+                }
+            }
+            output
+        }
+
+        fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+            let mut dl_dx = dl_dz.clone();
+            self.gradient = vec![0.0; self.scale.len()];
+            for i in 0..dl_dz.dims[0] {
+                for j in 0..dl_dz.dims[1] {
+                    self.gradient[j] += dl_dz.get(i, j) * self.input.get(i, j);
+                    *dl_dx.get_mut(i, j) *= self.scale[j];
+                }
+            }
+            dl_dx
+        }
+
+        fn parameters(&self) -> Vec<&[f64]> {
+            vec![&self.scale]
+        }
+
+        fn gradients(&self) -> Vec<&[f64]> {
+            vec![&self.gradient]
+        }
+
+        fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+            vec![Parameter {
+                name: "scale",
+                value: &mut self.scale,
+                gradient: &mut self.gradient,
+                frozen: false,
+                sparse: None,
+            }]
+        }
+    }
+
+    #[test]
+    fn test_heterogeneous_network() {
+        let scaling = Scaling {
+            scale: vec![0.5, -1.5, 2.0],
+            gradient: vec![0.0; 3],
+            input: Matrix::zero((1, 3)),
+        };
+        let mut nn = NeuralNetwork::from_modules(vec![
+            Box::new(Layer::random((2, 3), (-1.0, 1.0))),
+            Box::new(scaling),
+            Box::new(Layer::random((3, 2), (-1.0, 1.0))),
+        ]);
+        let input = Vector::from(vec![0.3, -0.8]);
+        let target = Vector::from(vec![1.0, 0.0]);
+        for result in check_network(&mut nn, &input, &target) {
+            assert!(max_error(&result) < 1e-6, "{:?}", result);
+        }
+
+        let before = nn.layers[1].parameters()[0].to_vec();
+        nn.train(input, &target);
+        assert_ne!(nn.layers[1].parameters()[0], &before[..]);
+    }
+
+    #[test]
+    fn test_batch_gradients_are_averaged() {
+        let mut nn = NeuralNetwork::new(vec![
+            Layer::random((2, 3), (-1.0, 1.0)),
+            Layer::random((3, 2), (-1.0, 1.0)),
+        ]);
+        let inputs = vec![Vector::from(vec![0.3, -0.8]), Vector::from(vec![-0.5, 0.1])];
+        let targets = vec![Vector::from(vec![1.0, 0.0]), Vector::from(vec![0.0, 1.0])];
+
+        let mut expected = [0.0; 6];
+        let mut expected_loss = 0.0;
+        for (input, target) in inputs.iter().zip(&targets) {
+            nn.forward(input.clone());
+            expected_loss += nn.loss(target) / 2.0;
+            nn.backward(target);
+            for (e, g) in expected.iter_mut().zip(nn.layers[0].gradients()[0]) {
+                *e += g / 2.0;
+            }
+        }
+
+        let output = nn.forward_batch(&Matrix::from_rows(&inputs));
+        let targets = Matrix::from_rows(&targets);
+        assert!((nn.loss_batch(&output, &targets) - expected_loss).abs() < 1e-12);
+        nn.backward_batch(&output, &targets);
+        for (e, g) in expected.iter().zip(nn.layers[0].gradients()[0]) {
+            assert!((e - g).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use crate::matrix::Matrix;
+use crate::module::{Module, Parameter};
+
+/// Normalizes each group of values, given as index pairs into `input`, to zero mean and unit
+/// variance.
+///
+/// Returns the normalized values and the inverse standard deviation of every group
+fn normalize<G>(input: &Matrix, groups: usize, group: G, epsilon: f64) -> (Matrix, Vec<f64>)
+where
+    G: Fn(usize) -> Vec<(usize, usize)>,
+{
+    let mut x_hat = Matrix::zero((input.dims[0], input.dims[1]));
+    let mut inv_stds = Vec::with_capacity(groups);
+    for g in 0..groups {
+        let indices = group(g);
+        let n = indices.len() as f64;
+        let mean = indices.iter().map(|(i, j)| input.get(*i, *j)).sum::<f64>() / n;
+        let variance = indices
+            .iter()
+            .map(|(i, j)| (input.get(*i, *j) - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        let inv_std = 1.0 / (variance + epsilon).sqrt();
+        for (i, j) in indices {
+            *x_hat.get_mut(i, j) = (input.get(i, j) - mean) * inv_std;
+        }
+        inv_stds.push(inv_std);
+    }
+    (x_hat, inv_stds)
+}
+
+/// Backpropagates the gradient wrt normalized values (dl_dx_hat) through `normalize`.
+fn denormalize_gradient<G>(dl_dx_hat: &Matrix, x_hat: &Matrix, inv_stds: &[f64], group: G) -> Matrix
+where
+    G: Fn(usize) -> Vec<(usize, usize)>,
+{
+    let mut dl_dx = Matrix::zero((x_hat.dims[0], x_hat.dims[1]));
+    for (g, inv_std) in inv_stds.iter().enumerate() {
+        let indices = group(g);
+        let n = indices.len() as f64;
+        let sum = indices
+            .iter()
+            .map(|(i, j)| dl_dx_hat.get(*i, *j))
+            .sum::<f64>();
+        let dot = indices
+            .iter()
+            .map(|(i, j)| dl_dx_hat.get(*i, *j) * x_hat.get(*i, *j))
+            .sum::<f64>();
+        for (i, j) in indices {
+            *dl_dx.get_mut(i, j) =
+                inv_std / n * (n * dl_dx_hat.get(i, j) - sum - x_hat.get(i, j) * dot);
+        }
+    }
+    dl_dx
+}
+
+/// Learnable per-feature scale (gamma) and shift (beta) applied after normalizing.
+#[derive(Debug, Clone)]
+struct Affine {
+    gamma: Vec<f64>,
+    beta: Vec<f64>,
+    gamma_gradients: Vec<f64>,
+    beta_gradients: Vec<f64>,
+}
+
+impl Affine {
+    fn new(features: usize) -> Affine {
+        Affine {
+            gamma: vec![1.0; features],
+            beta: vec![0.0; features],
+            gamma_gradients: vec![0.0; features],
+            beta_gradients: vec![0.0; features],
+        }
+    }
+
+    fn forward(&self, x_hat: &Matrix) -> Matrix {
+        assert!(x_hat.dims[1] == self.gamma.len());
+        let mut output = Matrix::zero((x_hat.dims[0], x_hat.dims[1]));
+        for i in 0..x_hat.dims[0] {
+            for j in 0..x_hat.dims[1] {
+                *output.get_mut(i, j) = self.gamma[j] * x_hat.get(i, j) + self.beta[j];
+            }
+        }
+        output
+    }
+
+    /// Stores the gradients wrt gamma and beta and returns the gradient wrt x_hat.
+    fn backward(&mut self, dl_dz: &Matrix, x_hat: &Matrix) -> Matrix {
+        self.gamma_gradients = vec![0.0; self.gamma.len()];
+        self.beta_gradients = vec![0.0; self.beta.len()];
+        let mut dl_dx_hat = Matrix::zero((dl_dz.dims[0], dl_dz.dims[1]));
+        for i in 0..dl_dz.dims[0] {
+            for j in 0..dl_dz.dims[1] {
+                self.gamma_gradients[j] += dl_dz.get(i, j) * x_hat.get(i, j);
+                self.beta_gradients[j] += dl_dz.get(i, j);
+                *dl_dx_hat.get_mut(i, j) = dl_dz.get(i, j) * self.gamma[j];
+            }
+        }
+        dl_dx_hat
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        vec![&self.gamma, &self.beta]
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        vec![&self.gamma_gradients, &self.beta_gradients]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        vec![
+            Parameter {
+                name: "gamma",
+                value: &mut self.gamma,
+                gradient: &mut self.gamma_gradients,
+                frozen: false,
+                sparse: None,
+            },
+            Parameter {
+                name: "beta",
+                value: &mut self.beta,
+                gradient: &mut self.beta_gradients,
+                frozen: false,
+                sparse: None,
+            },
+        ]
+    }
+}
+
+/// Normalizes every feature over the batch during training, while tracking running averages of
+/// the mean and variance that replace the batch statistics at inference.
+///
+/// Training batches need at least two rows, since a single sample normalizes to zero and has no
+/// variance to estimate, so single-sample training with `NeuralNetwork::train` is not supported.
+#[derive(Debug, Clone)]
+pub struct BatchNorm {
+    pub momentum: f64,
+    pub epsilon: f64,
+    pub running_mean: Vec<f64>,
+    pub running_variance: Vec<f64>,
+    affine: Affine,
+    training: bool,
+    /// (x_hat, inverse standard deviations) of the last forward pass
+    cache: Option<(Matrix, Vec<f64>)>,
+}
+
+impl BatchNorm {
+    pub fn new(features: usize) -> BatchNorm {
+        BatchNorm {
+            momentum: 0.1,
+            epsilon: 1e-5,
+            running_mean: vec![0.0; features],
+            running_variance: vec![1.0; features],
+            affine: Affine::new(features),
+            training: true,
+            cache: None,
+        }
+    }
+
+    /// Sets the weight of the current batch in the running averages.
+    pub fn momentum(mut self, momentum: f64) -> Self {
+        assert!((0.0..=1.0).contains(&momentum));
+        self.momentum = momentum;
+        self
+    }
+}
+
+impl Module for BatchNorm {
+    fn name(&self) -> &'static str {
+        "batch_norm"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let (rows, features) = (input.dims[0], input.dims[1]);
+        assert!(features == self.running_mean.len());
+        let (x_hat, inv_stds) = if self.training {
+            assert!(
+                rows > 1,
+                "BatchNorm::forward_batch: training needs batches of at least two rows"
+            );
+            let column = |j: usize| (0..rows).map(|i| (i, j)).collect();
+            let (x_hat, inv_stds) = normalize(input, features, column, self.epsilon);
+            for j in 0..features {
+                let mean = (0..rows).map(|i| input.get(i, j)).sum::<f64>() / rows as f64;
+                // the running variance is unbiased, like the estimate it stands in for
+                let variance = (0..rows)
+                    .map(|i| (input.get(i, j) - mean).powi(2))
+                    .sum::<f64>()
+                    / (rows - 1) as f64;
+                self.running_mean[j] += self.momentum * (mean - self.running_mean[j]);
+                self.running_variance[j] += self.momentum * (variance - self.running_variance[j]);
+            }
+            (x_hat, inv_stds)
+        } else {
+            let inv_stds = self
+                .running_variance
+                .iter()
+                .map(|v| 1.0 / (v + self.epsilon).sqrt())
+                .collect::<Vec<f64>>();
+            let mut x_hat = Matrix::zero((rows, features));
+            for i in 0..rows {
+                for (j, inv_std) in inv_stds.iter().enumerate() {
+                    *x_hat.get_mut(i, j) = (input.get(i, j) - self.running_mean[j]) * inv_std;
+                }
+            }
+            (x_hat, inv_stds)
+        };
+        let output = self.affine.forward(&x_hat);
+        self.cache = Some((x_hat, inv_stds));
+        output
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let (x_hat, inv_stds) = self
+            .cache
+            .as_ref()
+            .expect("BatchNorm::backward: forward was not called");
+        let dl_dx_hat = self.affine.backward(dl_dz, x_hat);
+        if !self.training {
+            // the running statistics are constants
+            let mut dl_dx = dl_dx_hat;
+            for i in 0..dl_dx.dims[0] {
+                for (j, inv_std) in inv_stds.iter().enumerate() {
+                    *dl_dx.get_mut(i, j) *= inv_std;
+                }
+            }
+            return dl_dx;
+        }
+        let rows = x_hat.dims[0];
+        let column = |j: usize| (0..rows).map(|i| (i, j)).collect();
+        denormalize_gradient(&dl_dx_hat, x_hat, inv_stds, column)
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        self.affine.parameters()
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        self.affine.gradients()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        self.affine.parameters_mut()
+    }
+
+    fn buffers(&self) -> Vec<(&'static str, &[f64])> {
+        vec![
+            ("running_mean", &self.running_mean),
+            ("running_variance", &self.running_variance),
+        ]
+    }
+
+    fn buffers_mut(&mut self) -> Vec<(&'static str, &mut [f64])> {
+        vec![
+            ("running_mean", &mut self.running_mean),
+            ("running_variance", &mut self.running_variance),
+        ]
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+/// Normalizes the features of every sample independently, behaving the same in training and at
+/// inference.
+#[derive(Debug, Clone)]
+pub struct LayerNorm {
+    pub epsilon: f64,
+    affine: Affine,
+    /// (x_hat, inverse standard deviations) of the last forward pass
+    cache: Option<(Matrix, Vec<f64>)>,
+}
+
+impl LayerNorm {
+    pub fn new(features: usize) -> LayerNorm {
+        LayerNorm {
+            epsilon: 1e-5,
+            affine: Affine::new(features),
+            cache: None,
+        }
+    }
+}
+
+impl Module for LayerNorm {
+    fn name(&self) -> &'static str {
+        "layer_norm"
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let features = input.dims[1];
+        let row = |i: usize| (0..features).map(|j| (i, j)).collect();
+        let (x_hat, inv_stds) = normalize(input, input.dims[0], row, self.epsilon);
+        let output = self.affine.forward(&x_hat);
+        self.cache = Some((x_hat, inv_stds));
+        output
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        let (x_hat, inv_stds) = self
+            .cache
+            .as_ref()
+            .expect("LayerNorm::backward: forward was not called");
+        let dl_dx_hat = self.affine.backward(dl_dz, x_hat);
+        let features = x_hat.dims[1];
+        let row = |i: usize| (0..features).map(|j| (i, j)).collect();
+        denormalize_gradient(&dl_dx_hat, x_hat, inv_stds, row)
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        self.affine.parameters()
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        self.affine.gradients()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        self.affine.parameters_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchNorm, LayerNorm};
+    use crate::activation::RELU;
+    use crate::gradcheck::{check_module_batch, check_network, max_error};
+    use crate::matrix::Matrix;
+    use crate::module::Module;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    const TOLERANCE: f64 = 1e-6;
+
+    fn perturbed(module: &mut dyn Module) {
+        // move gamma and beta away from the identity so that their gradients matter
+        for (k, parameter) in module.parameters_mut().into_iter().enumerate() {
+            for (i, value) in parameter.value.iter_mut().enumerate() {
+                *value += 0.3 * (i as f64 + 1.0) * if k == 0 { 1.0 } else { -1.0 };
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_norm_statistics() {
+        let mut batch_norm = BatchNorm::new(2).momentum(1.0);
+        let input = Matrix::from(vec![vec![1.0, 10.0], vec![3.0, 20.0], vec![5.0, 60.0]]);
+        let output = batch_norm.forward_batch(&input);
+        for j in 0..2 {
+            let column = (0..3).map(|i| output.get(i, j)).collect::<Vec<f64>>();
+            let mean = column.iter().sum::<f64>() / 3.0;
+            let variance = column.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 3.0;
+            assert!(mean.abs() < EPSILON);
+            assert!((variance - 1.0).abs() < 1e-3);
+        }
+        assert!((batch_norm.running_mean[0] - 3.0).abs() < EPSILON);
+        assert!((batch_norm.running_variance[0] - 4.0).abs() < EPSILON);
+
+        batch_norm.set_training(false);
+        let output = batch_norm.forward_batch(&Matrix::from(vec![vec![5.0, 30.0]]));
+        assert!((output.get(0, 0) - 1.0).abs() < 1e-3);
+        assert!(output.get(0, 1).abs() < 1e-3);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least two rows")]
+    fn test_batch_norm_single_row() {
+        let mut batch_norm = BatchNorm::new(2);
+        batch_norm.forward_batch(&Matrix::from(vec![vec![1.0, 2.0]]));
+    }
+
+    #[test]
+    fn test_check_module_batch() {
+        let input = Matrix::random((4, 3), (-1.0, 1.0));
+        let target = Matrix::random((4, 3), (-1.0, 1.0));
+        let mut modules: Vec<Box<dyn Module>> =
+            vec![Box::new(BatchNorm::new(3)), Box::new(LayerNorm::new(3))];
+        let mut eval = BatchNorm::new(3);
+        eval.forward_batch(&Matrix::random((5, 3), (-2.0, 2.0)));
+        eval.set_training(false);
+        modules.push(Box::new(eval));
+        for mut module in modules {
+            perturbed(module.as_mut());
+            let result = check_module_batch(module.as_mut(), &input, &target);
+            assert!(max_error(&result) < TOLERANCE, "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn test_network() {
+        let mut nn = NeuralNetwork::from_modules(vec![
+            Box::new(Layer::random((3, 4), (-1.0, 1.0))),
+            Box::new(LayerNorm::new(4)),
+            Box::new(Layer::random((4, 4), (-1.0, 1.0)).with_activation(RELU)),
+            Box::new(BatchNorm::new(4)),
+            Box::new(Layer::random((4, 2), (-1.0, 1.0))),
+        ]);
+        let inputs = Matrix::random((8, 3), (-1.0, 1.0));
+        let targets = Matrix::from(vec![vec![1.0, 0.0]; 8]);
+        for _ in 0..5 {
+            nn.train_batch(&inputs, &targets);
+        }
+        nn.set_training(false);
+        let input = Vector::from(vec![0.5, -0.3, 0.8]);
+        for result in check_network(&mut nn, &input, &Vector::from(vec![1.0, 0.0])) {
+            assert!(max_error(&result) < TOLERANCE, "{:?}", result);
+        }
+    }
+}
@@ -0,0 +1,822 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::activation::{self, Activation, LeakyRelu};
+use crate::head::{self, Head};
+use crate::matrix::Matrix;
+use crate::module::Module;
+use crate::ops::Scale;
+use crate::vector::Vector;
+use crate::{Layer, NeuralNetwork};
+
+/// ONNX opset the exported models target.
+const OPSET: u64 = 13;
+
+/// ONNX IR version matching `OPSET`.
+const IR_VERSION: u64 = 7;
+
+/// `TensorProto.DataType` of 32 and 64 bit floats.
+const FLOAT: u64 = 1;
+const DOUBLE: u64 = 11;
+
+/// `AttributeProto.AttributeType` of floats and ints.
+const ATTRIBUTE_FLOAT: u64 = 1;
+const ATTRIBUTE_INT: u64 = 2;
+
+#[derive(Debug)]
+pub enum OnnxError {
+    Io(io::Error),
+    /// The file is not a valid ONNX model.
+    InvalidFormat(String),
+    /// The model or network uses something that cannot be converted, e.g. a convolution.
+    Unsupported(String),
+}
+
+impl fmt::Display for OnnxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OnnxError::Io(error) => write!(f, "onnx i/o error: {}", error),
+            OnnxError::InvalidFormat(message) => write!(f, "invalid onnx model: {}", message),
+            OnnxError::Unsupported(message) => {
+                write!(f, "unsupported by onnx conversion: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OnnxError {}
+
+impl From<io::Error> for OnnxError {
+    fn from(error: io::Error) -> Self {
+        OnnxError::Io(error)
+    }
+}
+
+fn invalid<T>(message: &str) -> Result<T, OnnxError> {
+    Err(OnnxError::InvalidFormat(message.to_owned()))
+}
+
+/// Protocol buffer message under construction.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.raw_varint(((field as u64) << 3) | wire_type as u64);
+    }
+
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn varint(&mut self, field: u32, value: u64) -> &mut Self {
+        self.key(field, 0);
+        self.raw_varint(value);
+        self
+    }
+
+    fn float(&mut self, field: u32, value: f32) -> &mut Self {
+        self.key(field, 5);
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Self {
+        self.key(field, 2);
+        self.raw_varint(value.len() as u64);
+        self.0.extend(value);
+        self
+    }
+
+    fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    fn message(&mut self, field: u32, value: &Message) -> &mut Self {
+        self.bytes(field, &value.0)
+    }
+}
+
+/// Value of a field of a protocol buffer message.
+#[derive(Debug, Clone, Copy)]
+enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Value<'a> {
+    fn bytes(self) -> Result<&'a [u8], OnnxError> {
+        match self {
+            Value::Bytes(bytes) => Ok(bytes),
+            _ => invalid("expected a length-delimited field"),
+        }
+    }
+
+    fn string(self) -> Result<String, OnnxError> {
+        String::from_utf8(self.bytes()?.to_vec()).or_else(|_| invalid("invalid UTF-8 in a string"))
+    }
+
+    fn int(self) -> Result<i64, OnnxError> {
+        match self {
+            Value::Varint(value) => Ok(value as i64),
+            _ => invalid("expected a varint field"),
+        }
+    }
+
+    /// Reads a repeated int64 field, packed or not.
+    fn ints(self) -> Result<Vec<i64>, OnnxError> {
+        match self {
+            Value::Bytes(mut bytes) => {
+                let mut ints = vec![];
+                while !bytes.is_empty() {
+                    ints.push(read_varint(&mut bytes)? as i64);
+                }
+                Ok(ints)
+            }
+            value => Ok(vec![value.int()?]),
+        }
+    }
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, OnnxError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| OnnxError::InvalidFormat("unexpected end of message".to_owned()))?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+    invalid("varint too long")
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], OnnxError> {
+    if bytes.len() < n {
+        return invalid("unexpected end of message");
+    }
+    let (taken, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(taken)
+}
+
+/// Splits a protocol buffer message into its fields.
+fn fields(mut bytes: &[u8]) -> Result<Vec<(u32, Value<'_>)>, OnnxError> {
+    let mut fields = vec![];
+    while !bytes.is_empty() {
+        let key = read_varint(&mut bytes)?;
+        let value = match key & 7 {
+            0 => Value::Varint(read_varint(&mut bytes)?),
+            1 => Value::Fixed64(u64::from_le_bytes(take(&mut bytes, 8)?.try_into().unwrap())),
+            2 => {
+                let length = read_varint(&mut bytes)? as usize;
+                Value::Bytes(take(&mut bytes, length)?)
+            }
+            5 => Value::Fixed32(u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap())),
+            _ => return invalid("unsupported wire type"),
+        };
+        fields.push(((key >> 3) as u32, value));
+    }
+    Ok(fields)
+}
+
+fn tensor(name: &str, dims: &[usize], values: &[f64]) -> Message {
+    let data = values
+        .iter()
+        .flat_map(|v| (*v as f32).to_le_bytes())
+        .collect::<Vec<u8>>();
+    let mut tensor = Message::default();
+    dims.iter().for_each(|d| {
+        tensor.varint(1, *d as u64);
+    });
+    tensor.varint(2, FLOAT).string(8, name).bytes(9, &data);
+    tensor
+}
+
+/// Describes a float matrix with a variable batch size.
+fn value_info(name: &str, width: usize) -> Message {
+    let mut batch = Message::default();
+    batch.string(2, "batch");
+    let mut columns = Message::default();
+    columns.varint(1, width as u64);
+    let mut shape = Message::default();
+    shape.message(1, &batch).message(1, &columns);
+    let mut tensor_type = Message::default();
+    tensor_type.varint(1, FLOAT).message(2, &shape);
+    let mut type_proto = Message::default();
+    type_proto.message(1, &tensor_type);
+    let mut info = Message::default();
+    info.string(1, name).message(2, &type_proto);
+    info
+}
+
+fn node(op_type: &str, inputs: &[&str], output: &str, attributes: &[Message]) -> Message {
+    let mut node = Message::default();
+    inputs.iter().for_each(|input| {
+        node.string(1, input);
+    });
+    node.string(2, output).string(3, output).string(4, op_type);
+    attributes.iter().for_each(|attribute| {
+        node.message(5, attribute);
+    });
+    node
+}
+
+fn float_attribute(name: &str, value: f64) -> Message {
+    let mut attribute = Message::default();
+    attribute
+        .string(1, name)
+        .float(2, value as f32)
+        .varint(20, ATTRIBUTE_FLOAT);
+    attribute
+}
+
+fn int_attribute(name: &str, value: i64) -> Message {
+    let mut attribute = Message::default();
+    attribute
+        .string(1, name)
+        .varint(3, value as u64)
+        .varint(20, ATTRIBUTE_INT);
+    attribute
+}
+
+/// Returns the ONNX operator computing an activation.
+fn activation_node(
+    activation: &dyn Activation,
+    input: &str,
+    output: &str,
+) -> Result<Message, OnnxError> {
+    match (activation.name(), activation.parameters()) {
+        ("relu", _) => Ok(node("Relu", &[input], output, &[])),
+        ("leaky_relu" | "prelu", [alpha]) => Ok(node(
+            "LeakyRelu",
+            &[input],
+            output,
+            &[float_attribute("alpha", *alpha)],
+        )),
+        ("sigmoid", _) => Ok(node("Sigmoid", &[input], output, &[])),
+        ("tanh", _) => Ok(node("Tanh", &[input], output, &[])),
+        (name, _) => Err(OnnxError::Unsupported(format!("activation {}", name))),
+    }
+}
+
+fn head_node(head: &dyn Head, input: &str, output: &str) -> Result<Option<Message>, OnnxError> {
+    let axis = [int_attribute("axis", 1)];
+    match head.name() {
+        "identity" => Ok(None),
+        "softmax" => Ok(Some(node("Softmax", &[input], output, &axis))),
+        "log_softmax" => Ok(Some(node("LogSoftmax", &[input], output, &axis))),
+        "sigmoid" => Ok(Some(node("Sigmoid", &[input], output, &[]))),
+        name => Err(OnnxError::Unsupported(format!("head {}", name))),
+    }
+}
+
+impl NeuralNetwork {
+    /// Encodes a network of dense layers as an ONNX model, with one `Gemm` node per layer
+    /// followed by its activation and the head.
+    ///
+    /// Weights are stored as 32 bit floats, the precision inference runtimes expect.
+    pub fn to_onnx(&self) -> Result<Vec<u8>, OnnxError> {
+        let mut graph = Message::default();
+        graph.string(2, "bamf");
+        let mut current = "input".to_owned();
+        let mut input_width = None;
+        for (l, layer) in self.layers.iter().enumerate() {
+            let parameters = layer.parameters();
+            let (weights, biases) = match (layer.name(), &parameters[..]) {
+                ("dense", [weights, biases, ..]) if !biases.is_empty() => (weights, biases),
+                ("dense", _) => {
+                    return Err(OnnxError::Unsupported(format!("layer {}: zero width", l)))
+                }
+                (name, _) => return Err(OnnxError::Unsupported(format!("layer {}: {}", l, name))),
+            };
+            let dims = [weights.len() / biases.len(), biases.len()];
+            input_width.get_or_insert(dims[0]);
+            let (w, b, y) = (
+                format!("weights{}", l),
+                format!("biases{}", l),
+                format!("dense{}", l),
+            );
+            graph.message(5, &tensor(&w, &dims, weights));
+            graph.message(5, &tensor(&b, &dims[1..], biases));
+            graph.message(1, &node("Gemm", &[&current, &w, &b], &y, &[]));
+            current = y;
+            if let Some(activation) = layer.activation() {
+                let z = format!("activation{}", l);
+                graph.message(1, &activation_node(activation, &current, &z)?);
+                current = z;
+            }
+        }
+        let output_width = match self.layers.last() {
+            Some(layer) => layer.parameters()[1].len(),
+            None => return Err(OnnxError::Unsupported("empty network".to_owned())),
+        };
+        if let Some(head) = head_node(self.head.as_ref(), &current, "output")? {
+            graph.message(1, &head);
+        } else {
+            graph.message(1, &node("Identity", &[&current], "output", &[]));
+        }
+        graph.message(11, &value_info("input", input_width.unwrap()));
+        graph.message(12, &value_info("output", output_width));
+
+        let mut opset = Message::default();
+        opset.string(1, "").varint(2, OPSET);
+        let mut model = Message::default();
+        model
+            .varint(1, IR_VERSION)
+            .string(2, "bamf")
+            .message(7, &graph)
+            .message(8, &opset);
+        Ok(model.0)
+    }
+
+    /// Writes the network to an ONNX file, see `to_onnx`.
+    pub fn export_onnx<P: AsRef<Path>>(&self, path: P) -> Result<(), OnnxError> {
+        Ok(fs::write(path, self.to_onnx()?)?)
+    }
+
+    /// Decodes an ONNX multilayer perceptron: a chain of `Gemm` (or `MatMul` and `Add`) nodes
+    /// with optional `Relu`, `LeakyRelu`, `Sigmoid` or `Tanh` activations, optionally ending with
+    /// `Softmax`, `LogSoftmax` or `Sigmoid`, which become the head.
+    pub fn from_onnx(bytes: &[u8]) -> Result<NeuralNetwork, OnnxError> {
+        let model = fields(bytes)?;
+        let graph = match model.iter().find(|(field, _)| *field == 7) {
+            Some((_, graph)) => fields(graph.bytes()?)?,
+            None => return invalid("missing graph"),
+        };
+        let mut initializers = HashMap::new();
+        for (_, value) in graph.iter().filter(|(field, _)| *field == 5) {
+            let (name, dims, values) = read_tensor(value.bytes()?)?;
+            initializers.insert(name, (dims, values));
+        }
+        let weights = |name: &str| {
+            initializers
+                .get(name)
+                .ok_or_else(|| OnnxError::Unsupported(format!("non-constant operand {}", name)))
+        };
+
+        let nodes = graph
+            .iter()
+            .filter(|(field, _)| *field == 1)
+            .map(|(_, value)| read_node(value.bytes()?))
+            .collect::<Result<Vec<Node>, OnnxError>>()?;
+
+        let mut layers: Vec<Layer> = vec![];
+        let mut head: Box<dyn Head> = Box::new(head::IDENTITY);
+        let mut current: Option<&str> = None;
+        for (i, node) in nodes.iter().enumerate() {
+            let unsupported = |message: &str| {
+                Err(OnnxError::Unsupported(format!(
+                    "{} node {}: {}",
+                    node.op_type, node.output, message
+                )))
+            };
+            let last = i + 1 == nodes.len();
+            // ONNX allows the output of the previous node on either side of an Add
+            let chained = match (current, node.op_type.as_str()) {
+                (Some(current), "Add")
+                    if node.inputs.get(1).map(String::as_str) == Some(current) =>
+                {
+                    1
+                }
+                _ => 0,
+            };
+            let input = node.inputs.get(chained).map(|input| input.as_str());
+            if current.is_some() && current != input {
+                return unsupported("does not continue a chain of layers");
+            }
+            let operand = |i: usize| match node.inputs.get(i) {
+                Some(name) => weights(name),
+                None => invalid(&format!(
+                    "{} node {} misses inputs",
+                    node.op_type, node.output
+                )),
+            };
+            match node.op_type.as_str() {
+                "Gemm" => {
+                    if node.int("transA", 0) != 0 {
+                        return unsupported("transA is set");
+                    }
+                    let mut matrix = to_matrix(operand(1)?, &node.output)?;
+                    if node.int("transB", 0) != 0 {
+                        matrix = matrix.transpose();
+                    }
+                    let dims = (matrix.dims[0], matrix.dims[1]);
+                    let weights = matrix.to_vector().scale(node.float("alpha", 1.0));
+                    let biases = match node.inputs.get(2) {
+                        Some(_) => broadcast(operand(2)?, dims.1, &node.output)?,
+                        None => Vector::zero(dims.1),
+                    };
+                    check_width(layers.last(), dims.0, node)?;
+                    layers.push(Layer::new(
+                        Matrix::from_vector(weights, dims),
+                        biases.scale(node.float("beta", 1.0)),
+                    ));
+                }
+                "MatMul" => {
+                    let matrix = to_matrix(operand(1)?, &node.output)?;
+                    let width = matrix.dims[1];
+                    check_width(layers.last(), matrix.dims[0], node)?;
+                    layers.push(Layer::new(matrix, Vector::zero(width)));
+                }
+                "Add" => match layers.pop() {
+                    Some(layer) if layer.activation().is_none() => {
+                        let biases =
+                            broadcast(operand(1 - chained)?, layer.biases.len(), &node.output)?;
+                        layers.push(Layer::new(layer.weights, layer.biases.add(&biases)));
+                    }
+                    _ => return unsupported("does not follow a MatMul"),
+                },
+                "Sigmoid" if last => head = Box::new(head::SIGMOID),
+                "Softmax" | "LogSoftmax" if last => {
+                    if ![1, -1].contains(&node.int("axis", -1)) {
+                        return unsupported("not over the features");
+                    }
+                    head = match node.op_type.as_str() {
+                        "Softmax" => Box::new(head::SOFTMAX),
+                        _ => Box::new(head::LOG_SOFTMAX),
+                    };
+                }
+                "Relu" | "LeakyRelu" | "Sigmoid" | "Tanh" => {
+                    let activation: Box<dyn Activation> = match node.op_type.as_str() {
+                        "Relu" => Box::new(activation::RELU),
+                        "LeakyRelu" => match node.float("alpha", 0.01) {
+                            alpha if alpha.is_finite() => Box::new(LeakyRelu::new(alpha)),
+                            _ => return unsupported("alpha is not finite"),
+                        },
+                        "Sigmoid" => Box::new(activation::SIGMOID),
+                        _ => Box::new(activation::TANH),
+                    };
+                    match layers.pop() {
+                        Some(layer) if layer.activation().is_none() => {
+                            layers.push(layer.with_boxed_activation(activation))
+                        }
+                        _ => return unsupported("does not follow a dense layer"),
+                    }
+                }
+                "Softmax" | "LogSoftmax" => return unsupported("only supported as the last node"),
+                "Identity" => {}
+                _ => return unsupported("unsupported operator"),
+            }
+            current = Some(&node.output);
+        }
+        if layers.is_empty() {
+            return Err(OnnxError::Unsupported(
+                "model without dense layers".to_owned(),
+            ));
+        }
+        let mut nn = NeuralNetwork::new(layers);
+        nn.head = head;
+        Ok(nn)
+    }
+
+    /// Reads a network from an ONNX file, see `from_onnx`.
+    pub fn import_onnx<P: AsRef<Path>>(path: P) -> Result<NeuralNetwork, OnnxError> {
+        NeuralNetwork::from_onnx(&fs::read(path)?)
+    }
+}
+
+/// Checks that a layer of `width` inputs fits the outputs of the previous layer.
+fn check_width(previous: Option<&Layer>, width: usize, node: &Node) -> Result<(), OnnxError> {
+    match previous {
+        Some(previous) if previous.biases.len() != width => Err(OnnxError::InvalidFormat(format!(
+            "{} node {} takes {} inputs, but the previous layer has {} outputs",
+            node.op_type,
+            node.output,
+            width,
+            previous.biases.len()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    inputs: Vec<String>,
+    output: String,
+    op_type: String,
+    /// (name, float value, int value) of every attribute
+    attributes: Vec<(String, Option<f64>, Option<i64>)>,
+}
+
+impl Node {
+    fn float(&self, name: &str, default: f64) -> f64 {
+        self.attributes
+            .iter()
+            .find(|(n, _, _)| n == name)
+            .and_then(|(_, f, _)| *f)
+            .unwrap_or(default)
+    }
+
+    fn int(&self, name: &str, default: i64) -> i64 {
+        self.attributes
+            .iter()
+            .find(|(n, _, _)| n == name)
+            .and_then(|(_, _, i)| *i)
+            .unwrap_or(default)
+    }
+}
+
+fn read_node(bytes: &[u8]) -> Result<Node, OnnxError> {
+    let mut node = Node {
+        inputs: vec![],
+        output: String::new(),
+        op_type: String::new(),
+        attributes: vec![],
+    };
+    for (field, value) in fields(bytes)? {
+        match field {
+            1 => node.inputs.push(value.string()?),
+            2 => node.output = value.string()?,
+            4 => node.op_type = value.string()?,
+            5 => {
+                let mut attribute = (String::new(), None, None);
+                for (field, value) in fields(value.bytes()?)? {
+                    match (field, value) {
+                        (1, value) => attribute.0 = value.string()?,
+                        (2, Value::Fixed32(bits)) => {
+                            attribute.1 = Some(f32::from_bits(bits) as f64)
+                        }
+                        (3, value) => attribute.2 = Some(value.int()?),
+                        _ => {}
+                    }
+                }
+                node.attributes.push(attribute);
+            }
+            _ => {}
+        }
+    }
+    Ok(node)
+}
+
+/// Returns the name, dimensions and values of a float or double tensor.
+fn read_tensor(bytes: &[u8]) -> Result<(String, Vec<usize>, Vec<f64>), OnnxError> {
+    let (mut name, mut dims, mut data_type) = (String::new(), vec![], FLOAT);
+    let (mut raw, mut values) = (None, vec![]);
+    for (field, value) in fields(bytes)? {
+        match (field, value) {
+            (1, value) => dims.extend(value.ints()?.into_iter().map(|d| d as usize)),
+            (2, value) => data_type = value.int()? as u64,
+            (4, Value::Fixed32(bits)) => values.push(f32::from_bits(bits) as f64),
+            (4, Value::Bytes(bytes)) => values.extend(
+                bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64),
+            ),
+            (8, value) => name = value.string()?,
+            (9, value) => raw = Some(value.bytes()?),
+            (10, Value::Fixed64(bits)) => values.push(f64::from_bits(bits)),
+            (10, Value::Bytes(bytes)) => values.extend(
+                bytes
+                    .chunks_exact(8)
+                    .map(|b| f64::from_le_bytes(b.try_into().unwrap())),
+            ),
+            _ => {}
+        }
+    }
+    if let Some(raw) = raw {
+        values = match data_type {
+            FLOAT => raw
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+                .collect(),
+            DOUBLE => raw
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+            _ => {
+                return Err(OnnxError::Unsupported(format!(
+                    "tensor {} of type {}",
+                    name, data_type
+                )))
+            }
+        };
+    } else if data_type != FLOAT && data_type != DOUBLE {
+        return Err(OnnxError::Unsupported(format!(
+            "tensor {} of type {}",
+            name, data_type
+        )));
+    }
+    let size = dims
+        .iter()
+        .try_fold(1_usize, |size, d| size.checked_mul(*d));
+    if size != Some(values.len()) {
+        return invalid(&format!("tensor {} does not match its dimensions", name));
+    }
+    Ok((name, dims, values))
+}
+
+fn to_matrix((dims, values): &(Vec<usize>, Vec<f64>), node: &str) -> Result<Matrix, OnnxError> {
+    match dims[..] {
+        [rows, columns] => Ok(Matrix::from_vector(values.clone().into(), (rows, columns))),
+        _ => Err(OnnxError::Unsupported(format!(
+            "{}: weights of shape {:?}",
+            node, dims
+        ))),
+    }
+}
+
+/// Reads a bias, broadcasting a scalar to `width` values.
+fn broadcast(
+    (dims, values): &(Vec<usize>, Vec<f64>),
+    width: usize,
+    node: &str,
+) -> Result<Vector, OnnxError> {
+    match values.len() {
+        1 => Ok(Vector::from(vec![values[0]; width])),
+        n if n == width => Ok(Vector::from(values.clone())),
+        _ => Err(OnnxError::Unsupported(format!(
+            "{}: bias of shape {:?}",
+            node, dims
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fields, float_attribute, int_attribute, node, read_node, tensor, Message, OnnxError,
+    };
+    use crate::activation::{LeakyRelu, GELU, RELU};
+    use crate::conv::Conv1d;
+    use crate::matrix::Matrix;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    /// Wraps nodes and initializers into a model.
+    fn model(nodes: &[Message], initializers: &[Message]) -> Vec<u8> {
+        let mut graph = Message::default();
+        nodes.iter().for_each(|n| {
+            graph.message(1, n);
+        });
+        initializers.iter().for_each(|i| {
+            graph.message(5, i);
+        });
+        let mut model = Message::default();
+        model.varint(1, 7).message(7, &graph);
+        model.0
+    }
+
+    fn assert_close(a: &Matrix, b: &Matrix) {
+        assert_eq!(a.dims, b.dims);
+        for (x, y) in a.to_vector().iter().zip(b.to_vector().iter()) {
+            assert!((x - y).abs() < EPSILON, "{} != {}", x, y);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut nn = NeuralNetwork::new(vec![
+            Layer::random((3, 5), (-1.0, 1.0)).with_activation(RELU),
+            Layer::random((5, 4), (-1.0, 1.0)).with_activation(LeakyRelu::new(0.2)),
+            Layer::random((4, 2), (-1.0, 1.0)),
+        ]);
+        let file = std::env::temp_dir().join(format!("bamf_{}.onnx", std::process::id()));
+        nn.export_onnx(&file).unwrap();
+
+        let bytes = std::fs::read(&file).unwrap();
+        let graph = fields(&bytes).unwrap()[2].1.bytes().unwrap();
+        let op_types = fields(graph)
+            .unwrap()
+            .into_iter()
+            .filter(|(field, _)| *field == 1)
+            .map(|(_, value)| read_node(value.bytes().unwrap()).unwrap().op_type)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            op_types,
+            ["Gemm", "Relu", "Gemm", "LeakyRelu", "Gemm", "Softmax"]
+        );
+
+        let mut imported = NeuralNetwork::import_onnx(&file).unwrap();
+        std::fs::remove_file(file).unwrap();
+        let input = Matrix::random((4, 3), (-2.0, 2.0));
+        assert_close(&nn.forward_batch(&input), &imported.forward_batch(&input));
+        assert_eq!(
+            imported.layers[1].activation().unwrap().name(),
+            "leaky_relu"
+        );
+    }
+
+    #[test]
+    fn test_import_matmul_add() {
+        // tanh(x W1 + b1) followed by sigmoid(2 x W2^T + b2)
+        let bytes = model(
+            &[
+                node("MatMul", &["x", "w1"], "y1", &[]),
+                node("Add", &["y1", "b1"], "z1", &[]),
+                node("Tanh", &["z1"], "a1", &[]),
+                node(
+                    "Gemm",
+                    &["a1", "w2", "b2"],
+                    "y2",
+                    &[int_attribute("transB", 1), float_attribute("alpha", 2.0)],
+                ),
+                node("Sigmoid", &["y2"], "out", &[]),
+            ],
+            &[
+                tensor("w1", &[2, 2], &[1.0, -1.0, 0.5, 2.0]),
+                tensor("b1", &[2], &[0.1, -0.2]),
+                tensor("w2", &[1, 2], &[0.5, -0.25]),
+                tensor("b2", &[1], &[0.3]),
+            ],
+        );
+        let mut nn = NeuralNetwork::from_onnx(&bytes).unwrap();
+        let x = [0.4, -0.6];
+        // the bias may come first in an Add, and a leaky relu may have any slope
+        let reordered = model(
+            &[
+                node("MatMul", &["x", "w1"], "y1", &[]),
+                node("Add", &["b1", "y1"], "z1", &[]),
+                node(
+                    "LeakyRelu",
+                    &["z1"],
+                    "a1",
+                    &[float_attribute("alpha", -0.5)],
+                ),
+            ],
+            &[
+                tensor("w1", &[2, 1], &[1.0, -1.0]),
+                tensor("b1", &[1], &[-2.0]),
+            ],
+        );
+        let mut other = NeuralNetwork::from_onnx(&reordered).unwrap();
+        let output = other.forward(Vector::from(x.to_vec()));
+        assert!((output[0] - 0.5).abs() < EPSILON);
+        let a1 = [
+            (x[0] + x[1] * 0.5 + 0.1_f64).tanh(),
+            (-x[0] + x[1] * 2.0 - 0.2_f64).tanh(),
+        ];
+        let y2: f64 = 2.0 * (a1[0] * 0.5 - a1[1] * 0.25) + 0.3;
+        let output = nn.forward(Vector::from(x.to_vec()));
+        assert!((output[0] - 1.0 / (1.0 + (-y2).exp())).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_errors() {
+        let nn = NeuralNetwork::from_modules(vec![Box::new(Conv1d::new((1, 1), 2, 4))]);
+        assert!(matches!(nn.to_onnx(), Err(OnnxError::Unsupported(_))));
+        let nn = NeuralNetwork::new(vec![
+            Layer::random((2, 2), (-1.0, 1.0)).with_activation(GELU)
+        ]);
+        assert!(matches!(nn.to_onnx(), Err(OnnxError::Unsupported(_))));
+
+        let conv = model(&[node("Conv", &["x", "w"], "y", &[])], &[]);
+        match NeuralNetwork::from_onnx(&conv) {
+            Err(error) => assert!(error.to_string().contains("Conv")),
+            Ok(_) => panic!("imported a convolution"),
+        }
+        let branching = model(
+            &[
+                node("MatMul", &["x", "w"], "y", &[]),
+                node("Relu", &["x"], "z", &[]),
+            ],
+            &[tensor("w", &[1, 1], &[1.0])],
+        );
+        assert!(matches!(
+            NeuralNetwork::from_onnx(&branching),
+            Err(OnnxError::Unsupported(_))
+        ));
+        let mismatched = model(
+            &[
+                node("MatMul", &["x", "w1"], "y1", &[]),
+                node("MatMul", &["y1", "w2"], "y2", &[]),
+            ],
+            &[
+                tensor("w1", &[2, 3], &[1.0; 6]),
+                tensor("w2", &[2, 1], &[1.0; 2]),
+            ],
+        );
+        match NeuralNetwork::from_onnx(&mismatched) {
+            Err(OnnxError::InvalidFormat(message)) => assert!(message.contains("3 outputs")),
+            _ => panic!("imported layers of mismatched widths"),
+        }
+        assert!(matches!(
+            NeuralNetwork::from_onnx(&[0x0a, 0x05, 0x01]),
+            Err(OnnxError::InvalidFormat(_))
+        ));
+        let overflowing = model(
+            &[node("MatMul", &["x", "w"], "y", &[])],
+            &[tensor("w", &[1 << 32, 1 << 32], &[1.0])],
+        );
+        assert!(matches!(
+            NeuralNetwork::from_onnx(&overflowing),
+            Err(OnnxError::InvalidFormat(_))
+        ));
+
+        let empty = NeuralNetwork::new(vec![Layer::new(Matrix::zero((2, 0)), Vector::zero(0))]);
+        assert!(matches!(empty.to_onnx(), Err(OnnxError::Unsupported(_))));
+    }
+}
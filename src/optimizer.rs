@@ -0,0 +1,554 @@
+use std::fmt::Debug;
+
+use crate::module::SparseRows;
+
+/// Update rule applied to the parameters of a `NeuralNetwork` after every backward pass.
+///
+/// Parameters are identified by their position among the parameters of the network, which lets
+/// optimizers keep per-parameter state such as momentum between steps.
+pub trait Optimizer: Debug {
+    fn name(&self) -> &'static str;
+
+    fn learning_rate(&self) -> f64;
+
+    fn set_learning_rate(&mut self, learning_rate: f64);
+
+    /// Called once per training step, before the parameters are updated.
+    fn begin_step(&mut self) {}
+
+    /// Updates the parameter at `index` in place given its gradient.
+    fn update(&mut self, index: usize, value: &mut [f64], gradient: &[f64]);
+
+    /// Like `update`, for a parameter whose gradient is zero outside of `rows`.
+    ///
+    /// Defaults to a dense `update`, since the state of most optimizers keeps changing the
+    /// parameters where the gradient is zero.
+    fn update_rows(&mut self, index: usize, value: &mut [f64], gradient: &[f64], rows: SparseRows) {
+        let _ = rows;
+        self.update(index, value, gradient);
+    }
+
+    /// Hyperparameters and per-parameter buffers of the optimizer by name, e.g. for checkpoints.
+    fn state(&self) -> Vec<(&'static str, Vec<Vec<f64>>)> {
+        vec![]
+    }
+
+    /// Restores an entry of `state`.
+    ///
+    /// Returns false if the name is unknown or the buffers are malformed
+    fn load_state(&mut self, _name: &str, _buffers: Vec<Vec<f64>>) -> bool {
+        false
+    }
+}
+
+/// Creates an optimizer from its name with default hyperparameters.
+pub fn from_name(name: &str, learning_rate: f64) -> Option<Box<dyn Optimizer>> {
+    let optimizer: Box<dyn Optimizer> = match name {
+        "sgd" => Box::new(Sgd::new(learning_rate)),
+        "adagrad" => Box::new(AdaGrad::new(learning_rate)),
+        "rmsprop" => Box::new(RmsProp::new(learning_rate)),
+        "adam" => Box::new(Adam::new(learning_rate)),
+        "adamw" => Box::new(AdamW::new(learning_rate, 0.0)),
+        _ => return None,
+    };
+    Some(optimizer)
+}
+
+fn scalar(value: f64) -> Vec<Vec<f64>> {
+    vec![vec![value]]
+}
+
+/// Reads a hyperparameter stored with `scalar`.
+fn load_scalar(value: &mut f64, buffers: &[Vec<f64>]) -> bool {
+    match buffers {
+        [buffer] if buffer.len() == 1 => {
+            *value = buffer[0];
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Returns the state buffer for the parameter at `index`, creating it zeroed on first use.
+fn state(buffers: &mut Vec<Vec<f64>>, index: usize, size: usize) -> &mut Vec<f64> {
+    if buffers.len() <= index {
+        buffers.resize(index + 1, vec![]);
+    }
+    if buffers[index].len() != size {
+        buffers[index] = vec![0.0; size];
+    }
+    &mut buffers[index]
+}
+
+/// Stochastic gradient descent, optionally with (Nesterov) momentum.
+#[derive(Debug, Clone)]
+pub struct Sgd {
+    pub learning_rate: f64,
+    pub momentum: f64,
+    pub nesterov: bool,
+    velocity: Vec<Vec<f64>>,
+}
+
+impl Sgd {
+    pub fn new(learning_rate: f64) -> Sgd {
+        Sgd {
+            learning_rate,
+            momentum: 0.0,
+            nesterov: false,
+            velocity: vec![],
+        }
+    }
+
+    pub fn momentum(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    pub fn nesterov(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self.nesterov = true;
+        self
+    }
+}
+
+impl Optimizer for Sgd {
+    fn name(&self) -> &'static str {
+        "sgd"
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update(&mut self, index: usize, value: &mut [f64], gradient: &[f64]) {
+        if self.momentum == 0.0 {
+            for (w, g) in value.iter_mut().zip(gradient) {
+                *w -= self.learning_rate * g;
+            }
+            return;
+        }
+        let velocity = state(&mut self.velocity, index, value.len());
+        for ((w, g), v) in value.iter_mut().zip(gradient).zip(velocity.iter_mut()) {
+            *v = self.momentum * *v + g;
+            let step = if self.nesterov {
+                g + self.momentum * *v
+            } else {
+                *v
+            };
+            *w -= self.learning_rate * step;
+        }
+    }
+
+    fn update_rows(&mut self, index: usize, value: &mut [f64], gradient: &[f64], rows: SparseRows) {
+        if self.momentum != 0.0 {
+            return self.update(index, value, gradient);
+        }
+        for &row in rows.rows {
+            let range = row * rows.width..(row + 1) * rows.width;
+            for (w, g) in value[range.clone()].iter_mut().zip(&gradient[range]) {
+                *w -= self.learning_rate * g;
+            }
+        }
+    }
+
+    fn state(&self) -> Vec<(&'static str, Vec<Vec<f64>>)> {
+        vec![
+            ("momentum", scalar(self.momentum)),
+            ("nesterov", scalar(self.nesterov as u8 as f64)),
+            ("velocity", self.velocity.clone()),
+        ]
+    }
+
+    fn load_state(&mut self, name: &str, buffers: Vec<Vec<f64>>) -> bool {
+        match name {
+            "momentum" => load_scalar(&mut self.momentum, &buffers),
+            "nesterov" => {
+                let mut nesterov = 0.0;
+                let loaded = load_scalar(&mut nesterov, &buffers);
+                self.nesterov = nesterov != 0.0;
+                loaded
+            }
+            "velocity" => {
+                self.velocity = buffers;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Scales the learning rate of every weight by the inverse root of its summed squared gradients.
+#[derive(Debug, Clone)]
+pub struct AdaGrad {
+    pub learning_rate: f64,
+    pub epsilon: f64,
+    squared_sum: Vec<Vec<f64>>,
+}
+
+impl AdaGrad {
+    pub fn new(learning_rate: f64) -> AdaGrad {
+        AdaGrad {
+            learning_rate,
+            epsilon: 1e-10,
+            squared_sum: vec![],
+        }
+    }
+}
+
+impl Optimizer for AdaGrad {
+    fn name(&self) -> &'static str {
+        "adagrad"
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update(&mut self, index: usize, value: &mut [f64], gradient: &[f64]) {
+        let squared_sum = state(&mut self.squared_sum, index, value.len());
+        for ((w, g), s) in value.iter_mut().zip(gradient).zip(squared_sum.iter_mut()) {
+            *s += g * g;
+            *w -= self.learning_rate * g / (s.sqrt() + self.epsilon);
+        }
+    }
+
+    fn update_rows(&mut self, index: usize, value: &mut [f64], gradient: &[f64], rows: SparseRows) {
+        let squared_sum = state(&mut self.squared_sum, index, value.len());
+        for &row in rows.rows {
+            let range = row * rows.width..(row + 1) * rows.width;
+            let values = value[range.clone()].iter_mut();
+            let sums = squared_sum[range.clone()].iter_mut();
+            for ((w, g), s) in values.zip(&gradient[range]).zip(sums) {
+                *s += g * g;
+                *w -= self.learning_rate * g / (s.sqrt() + self.epsilon);
+            }
+        }
+    }
+
+    fn state(&self) -> Vec<(&'static str, Vec<Vec<f64>>)> {
+        vec![
+            ("epsilon", scalar(self.epsilon)),
+            ("squared_sum", self.squared_sum.clone()),
+        ]
+    }
+
+    fn load_state(&mut self, name: &str, buffers: Vec<Vec<f64>>) -> bool {
+        match name {
+            "epsilon" => load_scalar(&mut self.epsilon, &buffers),
+            "squared_sum" => {
+                self.squared_sum = buffers;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Like `AdaGrad`, but with an exponential moving average of the squared gradients.
+#[derive(Debug, Clone)]
+pub struct RmsProp {
+    pub learning_rate: f64,
+    pub decay: f64,
+    pub epsilon: f64,
+    squared_mean: Vec<Vec<f64>>,
+}
+
+impl RmsProp {
+    pub fn new(learning_rate: f64) -> RmsProp {
+        RmsProp {
+            learning_rate,
+            decay: 0.99,
+            epsilon: 1e-8,
+            squared_mean: vec![],
+        }
+    }
+
+    pub fn decay(mut self, decay: f64) -> Self {
+        self.decay = decay;
+        self
+    }
+}
+
+impl Optimizer for RmsProp {
+    fn name(&self) -> &'static str {
+        "rmsprop"
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update(&mut self, index: usize, value: &mut [f64], gradient: &[f64]) {
+        let squared_mean = state(&mut self.squared_mean, index, value.len());
+        for ((w, g), s) in value.iter_mut().zip(gradient).zip(squared_mean.iter_mut()) {
+            *s = self.decay * *s + (1.0 - self.decay) * g * g;
+            *w -= self.learning_rate * g / (s.sqrt() + self.epsilon);
+        }
+    }
+
+    fn state(&self) -> Vec<(&'static str, Vec<Vec<f64>>)> {
+        vec![
+            ("decay", scalar(self.decay)),
+            ("epsilon", scalar(self.epsilon)),
+            ("squared_mean", self.squared_mean.clone()),
+        ]
+    }
+
+    fn load_state(&mut self, name: &str, buffers: Vec<Vec<f64>>) -> bool {
+        match name {
+            "decay" => load_scalar(&mut self.decay, &buffers),
+            "epsilon" => load_scalar(&mut self.epsilon, &buffers),
+            "squared_mean" => {
+                self.squared_mean = buffers;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Adam, with bias-corrected moving averages of the gradients and squared gradients.
+#[derive(Debug, Clone)]
+pub struct Adam {
+    pub learning_rate: f64,
+    pub beta1: f64,
+    pub beta2: f64,
+    pub epsilon: f64,
+    pub step: usize,
+    mean: Vec<Vec<f64>>,
+    squared_mean: Vec<Vec<f64>>,
+}
+
+impl Adam {
+    pub fn new(learning_rate: f64) -> Adam {
+        Adam {
+            learning_rate,
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+            step: 0,
+            mean: vec![],
+            squared_mean: vec![],
+        }
+    }
+
+    pub fn betas(mut self, beta1: f64, beta2: f64) -> Self {
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        self
+    }
+}
+
+impl Optimizer for Adam {
+    fn name(&self) -> &'static str {
+        "adam"
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn begin_step(&mut self) {
+        self.step += 1;
+    }
+
+    fn update(&mut self, index: usize, value: &mut [f64], gradient: &[f64]) {
+        let step = self.step.max(1) as i32;
+        let correction1 = 1.0 - self.beta1.powi(step);
+        let correction2 = 1.0 - self.beta2.powi(step);
+        state(&mut self.mean, index, value.len());
+        state(&mut self.squared_mean, index, value.len());
+        let (mean, squared_mean) = (&mut self.mean[index], &mut self.squared_mean[index]);
+        for (i, (w, g)) in value.iter_mut().zip(gradient).enumerate() {
+            mean[i] = self.beta1 * mean[i] + (1.0 - self.beta1) * g;
+            squared_mean[i] = self.beta2 * squared_mean[i] + (1.0 - self.beta2) * g * g;
+            let m = mean[i] / correction1;
+            let v = squared_mean[i] / correction2;
+            *w -= self.learning_rate * m / (v.sqrt() + self.epsilon);
+        }
+    }
+
+    fn state(&self) -> Vec<(&'static str, Vec<Vec<f64>>)> {
+        vec![
+            ("beta1", scalar(self.beta1)),
+            ("beta2", scalar(self.beta2)),
+            ("epsilon", scalar(self.epsilon)),
+            ("step", scalar(self.step as f64)),
+            ("mean", self.mean.clone()),
+            ("squared_mean", self.squared_mean.clone()),
+        ]
+    }
+
+    fn load_state(&mut self, name: &str, buffers: Vec<Vec<f64>>) -> bool {
+        match name {
+            "beta1" => load_scalar(&mut self.beta1, &buffers),
+            "beta2" => load_scalar(&mut self.beta2, &buffers),
+            "epsilon" => load_scalar(&mut self.epsilon, &buffers),
+            "step" => {
+                let mut step = 0.0;
+                let loaded = load_scalar(&mut step, &buffers);
+                self.step = step as usize;
+                loaded
+            }
+            "mean" => {
+                self.mean = buffers;
+                true
+            }
+            "squared_mean" => {
+                self.squared_mean = buffers;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Adam with weight decay decoupled from the gradient, applied directly to the weights.
+#[derive(Debug, Clone)]
+pub struct AdamW {
+    pub adam: Adam,
+    pub weight_decay: f64,
+}
+
+impl AdamW {
+    pub fn new(learning_rate: f64, weight_decay: f64) -> AdamW {
+        AdamW {
+            adam: Adam::new(learning_rate),
+            weight_decay,
+        }
+    }
+}
+
+impl Optimizer for AdamW {
+    fn name(&self) -> &'static str {
+        "adamw"
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.adam.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.adam.learning_rate = learning_rate;
+    }
+
+    fn begin_step(&mut self) {
+        self.adam.begin_step();
+    }
+
+    fn update(&mut self, index: usize, value: &mut [f64], gradient: &[f64]) {
+        let decay = 1.0 - self.adam.learning_rate * self.weight_decay;
+        for w in value.iter_mut() {
+            *w *= decay;
+        }
+        self.adam.update(index, value, gradient);
+    }
+
+    fn state(&self) -> Vec<(&'static str, Vec<Vec<f64>>)> {
+        let mut state = vec![("weight_decay", scalar(self.weight_decay))];
+        state.extend(self.adam.state());
+        state
+    }
+
+    fn load_state(&mut self, name: &str, buffers: Vec<Vec<f64>>) -> bool {
+        match name {
+            "weight_decay" => load_scalar(&mut self.weight_decay, &buffers),
+            _ => self.adam.load_state(name, buffers),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AdaGrad, Adam, AdamW, Optimizer, RmsProp, Sgd};
+
+    const EPSILON: f64 = 0.00001;
+
+    /// Minimizes `0.5 * |w - target|^2` and returns the final weights.
+    fn minimize(optimizer: &mut dyn Optimizer, steps: usize) -> Vec<f64> {
+        let target = [1.0, -2.0, 0.5];
+        let mut weights = vec![0.0; 3];
+        let mut bias = vec![3.0];
+        for _ in 0..steps {
+            optimizer.begin_step();
+            let gradient = weights
+                .iter()
+                .zip(target)
+                .map(|(w, t)| w - t)
+                .collect::<Vec<f64>>();
+            optimizer.update(0, &mut weights, &gradient);
+            let gradient = vec![bias[0]];
+            optimizer.update(1, &mut bias, &gradient);
+        }
+        weights.push(bias[0]);
+        weights
+    }
+
+    #[test]
+    fn test_convergence() {
+        let optimizers: Vec<Box<dyn Optimizer>> = vec![
+            Box::new(Sgd::new(0.1)),
+            Box::new(Sgd::new(0.05).momentum(0.9)),
+            Box::new(Sgd::new(0.05).nesterov(0.9)),
+            Box::new(AdaGrad::new(0.5)),
+            Box::new(RmsProp::new(0.01)),
+            Box::new(Adam::new(0.05)),
+        ];
+        for mut optimizer in optimizers {
+            let result = minimize(optimizer.as_mut(), 2000);
+            for (r, e) in result.iter().zip([1.0, -2.0, 0.5, 0.0]) {
+                assert!((r - e).abs() < 0.01, "{}: {:?}", optimizer.name(), result);
+            }
+        }
+    }
+
+    #[test]
+    fn test_momentum() {
+        let mut momentum = Sgd::new(0.1).momentum(0.5);
+        let mut nesterov = Sgd::new(0.1).nesterov(0.5);
+        let (mut w1, mut w2) = (vec![0.0], vec![0.0]);
+        for _ in 0..2 {
+            momentum.update(0, &mut w1, &[1.0]);
+            nesterov.update(0, &mut w2, &[1.0]);
+        }
+        // velocities 1.0 then 1.5
+        assert!((w1[0] + 0.1 + 0.15).abs() < EPSILON);
+        assert!((w2[0] + 0.15 + 0.175).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_adam_first_step() {
+        // the bias correction makes the first step exactly the learning rate
+        let mut adam = Adam::new(0.01);
+        let mut weights = vec![1.0, 1.0];
+        adam.begin_step();
+        adam.update(0, &mut weights, &[0.3, -20.0]);
+        assert!((weights[0] - 0.99).abs() < EPSILON);
+        assert!((weights[1] - 1.01).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_adamw_decay() {
+        let mut adamw = AdamW::new(0.01, 0.1);
+        let mut weights = vec![2.0];
+        adamw.begin_step();
+        adamw.update(0, &mut weights, &[0.0]);
+        assert!((weights[0] - 2.0 * (1.0 - 0.001)).abs() < EPSILON);
+    }
+}
@@ -0,0 +1,657 @@
+use std::fmt::Debug;
+
+use crate::matrix::{Matrix, Multiply};
+use crate::module::{Module, Parameter};
+use crate::vector::Vector;
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Input weights (W), hidden weights (U) and biases (b) of the gates of a recurrent cell, with
+/// the columns of every gate next to each other.
+#[derive(Debug, Clone)]
+pub struct Gates {
+    pub input_weights: Matrix,
+    pub hidden_weights: Matrix,
+    pub biases: Vector,
+    input_weight_gradients: Matrix,
+    hidden_weight_gradients: Matrix,
+    bias_gradients: Vector,
+}
+
+impl Gates {
+    /// Creates `gates` gates with weights drawn uniformly from +-1/sqrt(hidden_size).
+    fn new(input_size: usize, hidden_size: usize, gates: usize) -> Gates {
+        let bound = 1.0 / (hidden_size as f64).sqrt();
+        let input_weights = Matrix::random((input_size, gates * hidden_size), (-bound, bound));
+        let hidden_weights = Matrix::random((hidden_size, gates * hidden_size), (-bound, bound));
+        Gates {
+            input_weight_gradients: Matrix::zero_like(&input_weights),
+            hidden_weight_gradients: Matrix::zero_like(&hidden_weights),
+            input_weights,
+            hidden_weights,
+            biases: Vector::random(gates * hidden_size, (-bound, bound)),
+            bias_gradients: Vector::zero(gates * hidden_size),
+        }
+    }
+
+    /// Returns the tuple (xW + b, hU).
+    fn forward(&self, x: &Matrix, h: &Matrix) -> (Matrix, Matrix) {
+        (
+            x.multiply(&self.input_weights).add_row(&self.biases),
+            h.multiply(&self.hidden_weights),
+        )
+    }
+
+    /// Accumulates the gradients of the weights given the gradients wrt xW + b (dl_dxa) and hU
+    /// (dl_dha).
+    ///
+    /// Returns the tuple (dl_dx, dl_dh)
+    fn backward(
+        &mut self,
+        x: &Matrix,
+        h: &Matrix,
+        dl_dxa: &Matrix,
+        dl_dha: &Matrix,
+    ) -> (Matrix, Matrix) {
+        let dl_dw = x.transpose().multiply(dl_dxa);
+        let dl_du = h.transpose().multiply(dl_dha);
+        for (g, d) in self
+            .input_weight_gradients
+            .data
+            .iter_mut()
+            .zip(dl_dw.data.iter())
+        {
+            *g += d;
+        }
+        for (g, d) in self
+            .hidden_weight_gradients
+            .data
+            .iter_mut()
+            .zip(dl_du.data.iter())
+        {
+            *g += d;
+        }
+        self.bias_gradients = self.bias_gradients.add(&dl_dxa.sum_rows());
+        (
+            dl_dxa.multiply(&self.input_weights.transpose()),
+            dl_dha.multiply(&self.hidden_weights.transpose()),
+        )
+    }
+
+    fn zero_gradients(&mut self) {
+        self.input_weight_gradients = Matrix::zero_like(&self.input_weights);
+        self.hidden_weight_gradients = Matrix::zero_like(&self.hidden_weights);
+        self.bias_gradients = Vector::zero(self.biases.len());
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        vec![
+            &self.input_weights.data[..],
+            &self.hidden_weights.data[..],
+            &self.biases.data[..],
+        ]
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        vec![
+            &self.input_weight_gradients.data[..],
+            &self.hidden_weight_gradients.data[..],
+            &self.bias_gradients.data[..],
+        ]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        vec![
+            Parameter {
+                name: "input_weights",
+                value: &mut self.input_weights.data[..],
+                gradient: &mut self.input_weight_gradients.data[..],
+                frozen: false,
+                sparse: None,
+            },
+            Parameter {
+                name: "hidden_weights",
+                value: &mut self.hidden_weights.data[..],
+                gradient: &mut self.hidden_weight_gradients.data[..],
+                frozen: false,
+                sparse: None,
+            },
+            Parameter {
+                name: "biases",
+                value: &mut self.biases.data[..],
+                gradient: &mut self.bias_gradients.data[..],
+                frozen: false,
+                sparse: None,
+            },
+        ]
+    }
+}
+
+/// A single time step of a recurrent layer, unrolled over the sequence by `Recurrent`.
+///
+/// The state is a list of matrices with one row per sample, starting with the hidden state that
+/// is also the output of the step.
+pub trait Cell: Debug {
+    /// Values of a step needed by `step_backward`.
+    type Cache: Debug;
+
+    fn name(&self) -> &'static str;
+
+    fn input_size(&self) -> usize;
+
+    fn hidden_size(&self) -> usize;
+
+    /// Number of matrices in the state.
+    fn state_len(&self) -> usize {
+        1
+    }
+
+    /// Computes the next state from the input of the step and the previous state.
+    fn step(&self, x: &Matrix, state: &[Matrix]) -> (Vec<Matrix>, Self::Cache);
+
+    /// Accumulates the gradients of the parameters given the gradient of the loss wrt the state
+    /// after the step.
+    ///
+    /// Returns the gradient wrt the input of the step and wrt the previous state
+    fn step_backward(&mut self, cache: &Self::Cache, dl_dstate: &[Matrix])
+        -> (Matrix, Vec<Matrix>);
+
+    fn gates(&self) -> &Gates;
+
+    fn gates_mut(&mut self) -> &mut Gates;
+}
+
+/// Elman cell: `h' = tanh(xW + hU + b)`.
+#[derive(Debug, Clone)]
+pub struct RnnCell {
+    pub gates: Gates,
+}
+
+impl Cell for RnnCell {
+    /// (x, h, h')
+    type Cache = (Matrix, Matrix, Matrix);
+
+    fn name(&self) -> &'static str {
+        "rnn"
+    }
+
+    fn input_size(&self) -> usize {
+        self.gates.input_weights.dims[0]
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.gates.hidden_weights.dims[0]
+    }
+
+    fn step(&self, x: &Matrix, state: &[Matrix]) -> (Vec<Matrix>, Self::Cache) {
+        let (xa, ha) = self.gates.forward(x, &state[0]);
+        let h = xa.add(&ha).map(f64::tanh);
+        (vec![h.clone()], (x.clone(), state[0].clone(), h))
+    }
+
+    fn step_backward(
+        &mut self,
+        cache: &Self::Cache,
+        dl_dstate: &[Matrix],
+    ) -> (Matrix, Vec<Matrix>) {
+        let (x, h_prev, h) = cache;
+        let dl_da = dl_dstate[0].zip_map(h, |d, h| d * (1.0 - h * h));
+        let (dl_dx, dl_dh) = self.gates.backward(x, h_prev, &dl_da, &dl_da);
+        (dl_dx, vec![dl_dh])
+    }
+
+    fn gates(&self) -> &Gates {
+        &self.gates
+    }
+
+    fn gates_mut(&mut self) -> &mut Gates {
+        &mut self.gates
+    }
+}
+
+/// Gated recurrent unit with update (z), reset (r) and candidate (n) gates, where the reset gate
+/// applies after the hidden weights:
+///
+/// `n = tanh(xW_n + b_n + r * hU_n)`, `h' = (1 - z) * n + z * h`
+#[derive(Debug, Clone)]
+pub struct GruCell {
+    pub gates: Gates,
+}
+
+impl Cell for GruCell {
+    /// (x, h, hU, z, r, n)
+    type Cache = (Matrix, Matrix, Matrix, Matrix, Matrix, Matrix);
+
+    fn name(&self) -> &'static str {
+        "gru"
+    }
+
+    fn input_size(&self) -> usize {
+        self.gates.input_weights.dims[0]
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.gates.hidden_weights.dims[0]
+    }
+
+    fn step(&self, x: &Matrix, state: &[Matrix]) -> (Vec<Matrix>, Self::Cache) {
+        let size = self.hidden_size();
+        let h = &state[0];
+        let (xa, ha) = self.gates.forward(x, h);
+        let gate = |k: usize| {
+            let range = k * size..(k + 1) * size;
+            (xa.columns(range.clone()), ha.columns(range))
+        };
+        let ((xz, hz), (xr, hr), (xn, hn)) = (gate(0), gate(1), gate(2));
+        let z = xz.add(&hz).map(sigmoid);
+        let r = xr.add(&hr).map(sigmoid);
+        let n = xn.add(&r.hadamard(&hn)).map(f64::tanh);
+        let next = z.zip_map(&n, |z, n| (1.0 - z) * n).add(&z.hadamard(h));
+        (vec![next], (x.clone(), h.clone(), hn, z, r, n))
+    }
+
+    fn step_backward(
+        &mut self,
+        cache: &Self::Cache,
+        dl_dstate: &[Matrix],
+    ) -> (Matrix, Vec<Matrix>) {
+        let (x, h, hn, z, r, n) = cache;
+        let dl_dnext = &dl_dstate[0];
+        let dl_dz = dl_dnext.hadamard(&h.subtract(n));
+        let dl_dn = dl_dnext.zip_map(z, |d, z| d * (1.0 - z));
+        let dl_dan = dl_dn.zip_map(n, |d, n| d * (1.0 - n * n));
+        let dl_dr = dl_dan.hadamard(hn);
+        let dl_daz = dl_dz.zip_map(z, |d, z| d * z * (1.0 - z));
+        let dl_dar = dl_dr.zip_map(r, |d, r| d * r * (1.0 - r));
+        let dl_dxa = Matrix::concat_columns(&[dl_daz.clone(), dl_dar.clone(), dl_dan.clone()]);
+        let dl_dha = Matrix::concat_columns(&[dl_daz, dl_dar, dl_dan.hadamard(r)]);
+        let (dl_dx, dl_dh) = self.gates.backward(x, h, &dl_dxa, &dl_dha);
+        (dl_dx, vec![dl_dh.add(&dl_dnext.hadamard(z))])
+    }
+
+    fn gates(&self) -> &Gates {
+        &self.gates
+    }
+
+    fn gates_mut(&mut self) -> &mut Gates {
+        &mut self.gates
+    }
+}
+
+/// Long short-term memory cell with input (i), forget (f), cell (g) and output (o) gates, whose
+/// state is the hidden state followed by the cell state.
+#[derive(Debug, Clone)]
+pub struct LstmCell {
+    pub gates: Gates,
+}
+
+impl Cell for LstmCell {
+    /// (x, h, c, i, f, g, o, tanh(c'))
+    type Cache = (
+        Matrix,
+        Matrix,
+        Matrix,
+        Matrix,
+        Matrix,
+        Matrix,
+        Matrix,
+        Matrix,
+    );
+
+    fn name(&self) -> &'static str {
+        "lstm"
+    }
+
+    fn input_size(&self) -> usize {
+        self.gates.input_weights.dims[0]
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.gates.hidden_weights.dims[0]
+    }
+
+    fn state_len(&self) -> usize {
+        2
+    }
+
+    fn step(&self, x: &Matrix, state: &[Matrix]) -> (Vec<Matrix>, Self::Cache) {
+        let size = self.hidden_size();
+        let (h, c) = (&state[0], &state[1]);
+        let (xa, ha) = self.gates.forward(x, h);
+        let a = xa.add(&ha);
+        let gate = |k: usize| a.columns(k * size..(k + 1) * size);
+        let (i, f, g, o) = (
+            gate(0).map(sigmoid),
+            gate(1).map(sigmoid),
+            gate(2).map(f64::tanh),
+            gate(3).map(sigmoid),
+        );
+        let next_c = f.hadamard(c).add(&i.hadamard(&g));
+        let tanh_c = next_c.map(f64::tanh);
+        let next_h = o.hadamard(&tanh_c);
+        (
+            vec![next_h, next_c],
+            (x.clone(), h.clone(), c.clone(), i, f, g, o, tanh_c),
+        )
+    }
+
+    fn step_backward(
+        &mut self,
+        cache: &Self::Cache,
+        dl_dstate: &[Matrix],
+    ) -> (Matrix, Vec<Matrix>) {
+        let (x, h, c, i, f, g, o, tanh_c) = cache;
+        let (dl_dh, dl_dc) = (&dl_dstate[0], &dl_dstate[1]);
+        let dl_do = dl_dh.hadamard(tanh_c);
+        let dl_dc = dl_dc.add(&dl_dh.hadamard(o).zip_map(tanh_c, |d, t| d * (1.0 - t * t)));
+        let dl_dai = dl_dc.hadamard(g).zip_map(i, |d, i| d * i * (1.0 - i));
+        let dl_daf = dl_dc.hadamard(c).zip_map(f, |d, f| d * f * (1.0 - f));
+        let dl_dag = dl_dc.hadamard(i).zip_map(g, |d, g| d * (1.0 - g * g));
+        let dl_dao = dl_do.zip_map(o, |d, o| d * o * (1.0 - o));
+        let dl_da = Matrix::concat_columns(&[dl_dai, dl_daf, dl_dag, dl_dao]);
+        let (dl_dx, dl_dh) = self.gates.backward(x, h, &dl_da, &dl_da);
+        (dl_dx, vec![dl_dh, dl_dc.hadamard(f)])
+    }
+
+    fn gates(&self) -> &Gates {
+        &self.gates
+    }
+
+    fn gates_mut(&mut self) -> &mut Gates {
+        &mut self.gates
+    }
+}
+
+/// Recurrent layer that unrolls a `Cell` over sequences stored as rows, one step of
+/// `input_size` values after the other.
+///
+/// Outputs the hidden states of every step, one after the other, or only the last one.
+#[derive(Debug)]
+pub struct Recurrent<C: Cell> {
+    pub cell: C,
+    /// whether to output the hidden state of every step or only of the last one
+    pub return_sequences: bool,
+    /// number of steps after which backpropagation through time is cut
+    pub truncation: Option<usize>,
+    /// whether the final state of a forward pass is the initial state of the next one
+    pub stateful: bool,
+    state: Option<Vec<Matrix>>,
+    cache: Vec<C::Cache>,
+}
+
+/// Vanilla recurrent layer.
+pub type Rnn = Recurrent<RnnCell>;
+
+pub type Gru = Recurrent<GruCell>;
+
+pub type Lstm = Recurrent<LstmCell>;
+
+impl Rnn {
+    pub fn new(input_size: usize, hidden_size: usize) -> Rnn {
+        Recurrent::from_cell(RnnCell {
+            gates: Gates::new(input_size, hidden_size, 1),
+        })
+    }
+}
+
+impl Gru {
+    pub fn new(input_size: usize, hidden_size: usize) -> Gru {
+        Recurrent::from_cell(GruCell {
+            gates: Gates::new(input_size, hidden_size, 3),
+        })
+    }
+}
+
+impl Lstm {
+    /// Creates an LSTM whose forget gate biases start at 1, so that it remembers by default.
+    pub fn new(input_size: usize, hidden_size: usize) -> Lstm {
+        let mut gates = Gates::new(input_size, hidden_size, 4);
+        for b in &mut gates.biases.data[hidden_size..2 * hidden_size] {
+            *b = 1.0;
+        }
+        Recurrent::from_cell(LstmCell { gates })
+    }
+}
+
+impl<C: Cell> Recurrent<C> {
+    pub fn from_cell(cell: C) -> Recurrent<C> {
+        Recurrent {
+            cell,
+            return_sequences: true,
+            truncation: None,
+            stateful: false,
+            state: None,
+            cache: vec![],
+        }
+    }
+
+    /// Only outputs the hidden state of the last step.
+    pub fn last_only(mut self) -> Self {
+        self.return_sequences = false;
+        self
+    }
+
+    /// Cuts backpropagation through time into chunks of `steps` steps (truncated BPTT).
+    pub fn truncate(mut self, steps: usize) -> Self {
+        assert!(steps > 0);
+        self.truncation = Some(steps);
+        self
+    }
+
+    /// Carries the final state of every forward pass over to the next one, e.g. to feed a long
+    /// sequence in chunks, until `reset_state` is called.
+    pub fn stateful(mut self) -> Self {
+        self.stateful = true;
+        self
+    }
+
+    /// Returns the final state of the last forward pass, starting with the hidden state.
+    pub fn state(&self) -> Option<&[Matrix]> {
+        self.state.as_deref()
+    }
+
+    /// Runs a single sequence through the layer.
+    ///
+    /// Returns the hidden state of every step and the final state
+    pub fn forward_sequence(&mut self, inputs: &[Vector]) -> (Vec<Vector>, Vec<Vector>) {
+        let row = inputs
+            .iter()
+            .flat_map(|input| input.data.clone())
+            .collect::<Vec<f64>>();
+        let input = Matrix::from(vec![row]);
+        let return_sequences = self.return_sequences;
+        self.return_sequences = true;
+        let output = self.forward_batch(&input).row(0);
+        self.return_sequences = return_sequences;
+        let size = self.cell.hidden_size();
+        let outputs = output
+            .data
+            .chunks(size)
+            .map(|chunk| Vector::from(chunk.to_vec()))
+            .collect();
+        let state = self
+            .state
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|s| s.row(0))
+            .collect();
+        (outputs, state)
+    }
+}
+
+impl<C: Cell> Module for Recurrent<C> {
+    fn name(&self) -> &'static str {
+        self.cell.name()
+    }
+
+    fn forward_batch(&mut self, input: &Matrix) -> Matrix {
+        let (input_size, hidden_size) = (self.cell.input_size(), self.cell.hidden_size());
+        assert!(input.dims[1].is_multiple_of(input_size) && input.dims[1] > 0);
+        let steps = input.dims[1] / input_size;
+        let mut state = match &self.state {
+            Some(state) if self.stateful && state[0].dims[0] == input.dims[0] => state.clone(),
+            _ => vec![Matrix::zero((input.dims[0], hidden_size)); self.cell.state_len()],
+        };
+        self.cache.clear();
+        let mut outputs = Vec::with_capacity(steps);
+        for t in 0..steps {
+            let x = input.columns(t * input_size..(t + 1) * input_size);
+            let (next, cache) = self.cell.step(&x, &state);
+            self.cache.push(cache);
+            outputs.push(next[0].clone());
+            state = next;
+        }
+        self.state = Some(state);
+        if self.return_sequences {
+            Matrix::concat_columns(&outputs)
+        } else {
+            outputs.pop().unwrap()
+        }
+    }
+
+    fn backward_batch(&mut self, dl_dz: &Matrix) -> Matrix {
+        assert!(
+            !self.cache.is_empty(),
+            "Recurrent::backward: forward was not called"
+        );
+        let hidden_size = self.cell.hidden_size();
+        let steps = self.cache.len();
+        let rows = dl_dz.dims[0];
+        self.cell.gates_mut().zero_gradients();
+        let mut dl_dstate = vec![Matrix::zero((rows, hidden_size)); self.cell.state_len()];
+        let mut dl_dx = Vec::with_capacity(steps);
+        for t in (0..steps).rev() {
+            let dl_dh = if self.return_sequences {
+                Some(dl_dz.columns(t * hidden_size..(t + 1) * hidden_size))
+            } else if t == steps - 1 {
+                Some(dl_dz.clone())
+            } else {
+                None
+            };
+            if let Some(dl_dh) = dl_dh {
+                dl_dstate[0] = dl_dstate[0].add(&dl_dh);
+            }
+            let (dl_dx_t, dl_dprevious) = self.cell.step_backward(&self.cache[t], &dl_dstate);
+            dl_dx.push(dl_dx_t);
+            dl_dstate = dl_dprevious;
+            if self.truncation.is_some_and(|k| t % k == 0) {
+                dl_dstate = vec![Matrix::zero((rows, hidden_size)); self.cell.state_len()];
+            }
+        }
+        dl_dx.reverse();
+        Matrix::concat_columns(&dl_dx)
+    }
+
+    fn parameters(&self) -> Vec<&[f64]> {
+        self.cell.gates().parameters()
+    }
+
+    fn gradients(&self) -> Vec<&[f64]> {
+        self.cell.gates().gradients()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<Parameter<'_>> {
+        self.cell.gates_mut().parameters_mut()
+    }
+
+    fn reset_state(&mut self) {
+        self.state = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Gru, Lstm, Rnn};
+    use crate::gradcheck::{check_module_batch, max_error};
+    use crate::head::IDENTITY;
+    use crate::matrix::Matrix;
+    use crate::module::Module;
+    use crate::optimizer::Adam;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    #[test]
+    fn test_gradients() {
+        let modules: Vec<Box<dyn Module>> = vec![
+            Box::new(Rnn::new(3, 4)),
+            Box::new(Rnn::new(3, 4).last_only()),
+            Box::new(Gru::new(3, 4)),
+            Box::new(Gru::new(3, 4).last_only()),
+            Box::new(Lstm::new(3, 4)),
+            Box::new(Lstm::new(3, 4).last_only()),
+        ];
+        for mut module in modules {
+            let input = Matrix::random((2, 15), (-1.0, 1.0));
+            let output_width = module.forward_batch(&input).dims[1];
+            let target = Matrix::random((2, output_width), (-1.0, 1.0));
+            let result = check_module_batch(module.as_mut(), &input, &target);
+            assert!(max_error(&result) < 1e-6, "{}: {:?}", module.name(), result);
+        }
+    }
+
+    #[test]
+    fn test_truncation() {
+        let mut lstm = Lstm::new(2, 3).last_only().truncate(2);
+        let input = Matrix::random((1, 8), (-1.0, 1.0));
+        lstm.forward_batch(&input);
+        let dl_dx = lstm.backward_batch(&Matrix::from(vec![vec![1.0, 1.0, 1.0]]));
+        // only the last chunk of two steps receives a gradient
+        for j in 0..4 {
+            assert_eq!(dl_dx.get(0, j), 0.0);
+        }
+        assert!((4..8).all(|j| dl_dx.get(0, j) != 0.0));
+    }
+
+    #[test]
+    fn test_state() {
+        let mut gru = Gru::new(2, 3).stateful();
+        let inputs = vec![
+            Vector::from(vec![0.5, -0.5]),
+            Vector::from(vec![1.0, 0.0]),
+            Vector::from(vec![-1.0, 0.3]),
+        ];
+        let (outputs, state) = gru.forward_sequence(&inputs);
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[2], state[0]);
+
+        // feeding the sequence in two chunks gives the same result
+        gru.reset_state();
+        gru.forward_sequence(&inputs[..2]);
+        let (chunked, _) = gru.forward_sequence(&inputs[2..]);
+        for (a, b) in chunked[0].iter().zip(outputs[2].iter()) {
+            assert!((a - b).abs() < EPSILON);
+        }
+
+        let mut lstm = Lstm::new(2, 3);
+        let (_, state) = lstm.forward_sequence(&inputs);
+        assert_eq!(state.len(), 2);
+    }
+
+    #[test]
+    fn test_memory() {
+        // the target is the first value of the sequence, which the network has to remember
+        let inputs = Matrix::from(vec![
+            vec![1.0, 0.3, -0.2, 0.5, 0.1],
+            vec![-1.0, 0.3, -0.2, 0.5, 0.1],
+            vec![0.5, -0.4, 0.6, 0.0, -0.3],
+            vec![-0.5, -0.4, 0.6, 0.0, -0.3],
+        ]);
+        let targets = Matrix::from(vec![vec![1.0], vec![-1.0], vec![0.5], vec![-0.5]]);
+        let mut nn = NeuralNetwork::from_modules(vec![
+            Box::new(Lstm::new(1, 8).last_only()),
+            Box::new(Layer::random((8, 1), (-0.5, 0.5))),
+        ])
+        .with_head(IDENTITY)
+        .with_optimizer(Adam::new(0.02));
+        for _ in 0..500 {
+            nn.train_batch(&inputs, &targets);
+        }
+        let output = nn.forward_batch(&inputs);
+        assert!(nn.loss_batch(&output, &targets) < 0.01);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::matrix::Matrix;
+
+/// Elastic-net penalty `l1 * sum(|w|) + 0.5 * l2 * sum(w^2)` on the parameters of a `Layer`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Regularization {
+    pub l1: f64,
+    pub l2: f64,
+    /// Whether the biases are penalized along with the weights.
+    pub biases: bool,
+}
+
+impl Regularization {
+    pub fn new(l1: f64, l2: f64) -> Regularization {
+        assert!(l1 >= 0.0 && l2 >= 0.0);
+        Regularization {
+            l1,
+            l2,
+            biases: true,
+        }
+    }
+
+    pub fn l1(strength: f64) -> Regularization {
+        Regularization::new(strength, 0.0)
+    }
+
+    pub fn l2(strength: f64) -> Regularization {
+        Regularization::new(0.0, strength)
+    }
+
+    /// Splits `strength` between L1 and L2 according to `l1_ratio`, as in scikit-learn.
+    pub fn elastic_net(strength: f64, l1_ratio: f64) -> Regularization {
+        assert!((0.0..=1.0).contains(&l1_ratio));
+        Regularization::new(strength * l1_ratio, strength * (1.0 - l1_ratio))
+    }
+
+    /// Only penalizes the weights.
+    pub fn exclude_biases(mut self) -> Self {
+        self.biases = false;
+        self
+    }
+
+    pub fn penalty(&self, values: &[f64]) -> f64 {
+        values
+            .iter()
+            .map(|w| self.l1 * w.abs() + 0.5 * self.l2 * w * w)
+            .sum()
+    }
+
+    /// Adds the gradient of the penalty to `gradient`.
+    pub fn add_gradient(&self, values: &[f64], gradient: &mut [f64]) {
+        for (w, g) in values.iter().zip(gradient.iter_mut()) {
+            let sign = if *w > 0.0 {
+                1.0
+            } else if *w < 0.0 {
+                -1.0
+            } else {
+                0.0
+            };
+            *g += self.l1 * sign + self.l2 * w;
+        }
+    }
+}
+
+/// Rescales every column of `weights`, i.e. the incoming weights of an output, whose euclidean
+/// norm exceeds `max_norm` down to `max_norm`.
+pub fn clip_columns(weights: &mut Matrix, max_norm: f64) {
+    for j in 0..weights.dims[1] {
+        let norm = (0..weights.dims[0])
+            .map(|i| weights.get(i, j).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        if norm > max_norm {
+            for i in 0..weights.dims[0] {
+                *weights.get_mut(i, j) *= max_norm / norm;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clip_columns, Regularization};
+    use crate::gradcheck::{check_network, max_error};
+    use crate::matrix::Matrix;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+
+    const EPSILON: f64 = 0.00001;
+
+    #[test]
+    fn test_penalty() {
+        let values = [1.0, -2.0, 0.0];
+        let elastic = Regularization::elastic_net(0.5, 0.2);
+        assert!((elastic.penalty(&values) - (0.1 * 3.0 + 0.5 * 0.4 * 5.0)).abs() < EPSILON);
+        let mut gradient = [0.0; 3];
+        elastic.add_gradient(&values, &mut gradient);
+        assert!((gradient[0] - 0.5).abs() < EPSILON);
+        assert!((gradient[1] + 0.9).abs() < EPSILON);
+        assert!(gradient[2].abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_network_penalty() {
+        let mut nn = NeuralNetwork::new(vec![
+            Layer::random((3, 4), (-1.0, 1.0)).with_regularization(Regularization::l2(0.1)),
+            Layer::random((4, 2), (-1.0, 1.0))
+                .with_regularization(Regularization::elastic_net(0.2, 0.5).exclude_biases()),
+        ]);
+        let input = Vector::from(vec![0.5, -0.3, 0.8]);
+        let target = Vector::from(vec![0.3, 0.7]);
+        for result in check_network(&mut nn, &input, &target) {
+            assert!(max_error(&result) < 1e-6, "{:?}", result);
+        }
+
+        let weights = &nn.layers[0].parameters();
+        let expected = 0.05
+            * weights[0]
+                .iter()
+                .chain(weights[1])
+                .map(|w| w * w)
+                .sum::<f64>()
+            + Regularization::elastic_net(0.2, 0.5).penalty(nn.layers[1].parameters()[0]);
+        assert!((nn.penalty() - expected).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_max_norm() {
+        let mut weights = Matrix::from(vec![vec![3.0, 0.1], vec![4.0, 0.2]]);
+        clip_columns(&mut weights, 1.0);
+        for (w, e) in weights.data.iter().zip([0.6, 0.1, 0.8, 0.2]) {
+            assert!((w - e).abs() < EPSILON);
+        }
+
+        let mut nn =
+            NeuralNetwork::new(vec![Layer::random((3, 2), (-5.0, 5.0)).with_max_norm(0.5)]);
+        nn.train(
+            Vector::from(vec![1.0, 2.0, 3.0]),
+            &Vector::from(vec![1.0, 0.0]),
+        );
+        let weights = nn.layers[0].parameters()[0];
+        for j in 0..2 {
+            let norm = (0..3).map(|i| weights[i * 2 + j].powi(2)).sum::<f64>();
+            assert!(norm.sqrt() <= 0.5 + EPSILON);
+        }
+    }
+}
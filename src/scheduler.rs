@@ -0,0 +1,397 @@
+use std::f64::consts::PI;
+use std::fmt::Debug;
+
+/// Adjusts the learning rate of the optimizer of a `NeuralNetwork` over the course of training.
+pub trait LrScheduler: Debug {
+    fn name(&self) -> &'static str;
+
+    /// Computes the learning rate for the next training step.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - learning rate of the optimizer when training started
+    /// * `step` - number of training steps taken so far
+    /// * `epoch` - number of completed epochs
+    fn learning_rate(&mut self, base: f64, step: usize, epoch: usize) -> f64;
+
+    /// Reports the validation loss at the end of an epoch.
+    fn observe(&mut self, _validation_loss: f64) {}
+}
+
+/// Multiplies the learning rate by `gamma` every `step_size` epochs.
+#[derive(Debug, Clone)]
+pub struct StepDecay {
+    pub step_size: usize,
+    pub gamma: f64,
+}
+
+impl StepDecay {
+    pub fn new(step_size: usize, gamma: f64) -> StepDecay {
+        assert!(step_size > 0);
+        StepDecay { step_size, gamma }
+    }
+}
+
+impl LrScheduler for StepDecay {
+    fn name(&self) -> &'static str {
+        "step_decay"
+    }
+
+    fn learning_rate(&mut self, base: f64, _step: usize, epoch: usize) -> f64 {
+        base * self.gamma.powi((epoch / self.step_size) as i32)
+    }
+}
+
+/// Multiplies the learning rate by `gamma` every epoch.
+#[derive(Debug, Clone)]
+pub struct ExponentialDecay {
+    pub gamma: f64,
+}
+
+impl ExponentialDecay {
+    pub fn new(gamma: f64) -> ExponentialDecay {
+        ExponentialDecay { gamma }
+    }
+}
+
+impl LrScheduler for ExponentialDecay {
+    fn name(&self) -> &'static str {
+        "exponential_decay"
+    }
+
+    fn learning_rate(&mut self, base: f64, _step: usize, epoch: usize) -> f64 {
+        base * self.gamma.powi(epoch as i32)
+    }
+}
+
+/// Decays the learning rate linearly to `min_factor * base` over `total_steps`, as in word2vec.
+#[derive(Debug, Clone)]
+pub struct LinearDecay {
+    pub total_steps: usize,
+    pub min_factor: f64,
+}
+
+impl LinearDecay {
+    pub fn new(total_steps: usize) -> LinearDecay {
+        LinearDecay {
+            total_steps,
+            min_factor: 1e-4,
+        }
+    }
+}
+
+impl LrScheduler for LinearDecay {
+    fn name(&self) -> &'static str {
+        "linear_decay"
+    }
+
+    fn learning_rate(&mut self, base: f64, step: usize, _epoch: usize) -> f64 {
+        let progress = step as f64 / self.total_steps.max(1) as f64;
+        base * (1.0 - progress).max(self.min_factor)
+    }
+}
+
+/// Cosine annealing from the base learning rate down to `min` over `period` steps, restarting
+/// afterwards with a period `multiplier` times longer (SGDR).
+#[derive(Debug, Clone)]
+pub struct CosineAnnealing {
+    period: usize,
+    multiplier: usize,
+    pub min: f64,
+}
+
+impl CosineAnnealing {
+    pub fn new(period: usize, min: f64) -> CosineAnnealing {
+        assert!(period > 0);
+        CosineAnnealing {
+            period,
+            multiplier: 1,
+            min,
+        }
+    }
+
+    pub fn multiplier(mut self, multiplier: usize) -> Self {
+        assert!(multiplier > 0);
+        self.multiplier = multiplier;
+        self
+    }
+}
+
+impl LrScheduler for CosineAnnealing {
+    fn name(&self) -> &'static str {
+        "cosine_annealing"
+    }
+
+    fn learning_rate(&mut self, base: f64, step: usize, _epoch: usize) -> f64 {
+        let progress = if self.multiplier == 1 {
+            (step % self.period) as f64 / self.period as f64
+        } else {
+            let (period, multiplier, step) =
+                (self.period as f64, self.multiplier as f64, step as f64);
+            // restart i starts at the geometric sum period * (multiplier^i - 1) / (multiplier - 1)
+            let start = |i: f64| period * (multiplier.powf(i) - 1.0) / (multiplier - 1.0);
+            let mut restarts = (step * (multiplier - 1.0) / period + 1.0)
+                .log(multiplier)
+                .floor();
+            // corrects the rounding of the logarithm at the restarts
+            if start(restarts + 1.0) <= step {
+                restarts += 1.0;
+            } else if start(restarts) > step {
+                restarts -= 1.0;
+            }
+            (step - start(restarts)) / (period * multiplier.powf(restarts))
+        };
+        self.min + 0.5 * (base - self.min) * (1.0 + (PI * progress).cos())
+    }
+}
+
+/// Ramps the learning rate up linearly over `warmup_steps`, then hands over to `after`, if any,
+/// with its steps counted from the end of the warmup.
+#[derive(Debug)]
+pub struct LinearWarmup {
+    pub warmup_steps: usize,
+    after: Option<Box<dyn LrScheduler>>,
+}
+
+impl LinearWarmup {
+    pub fn new(warmup_steps: usize) -> LinearWarmup {
+        LinearWarmup {
+            warmup_steps,
+            after: None,
+        }
+    }
+
+    pub fn then<S>(mut self, after: S) -> Self
+    where
+        S: LrScheduler + 'static,
+    {
+        self.after = Some(Box::new(after));
+        self
+    }
+}
+
+impl LrScheduler for LinearWarmup {
+    fn name(&self) -> &'static str {
+        "linear_warmup"
+    }
+
+    fn learning_rate(&mut self, base: f64, step: usize, epoch: usize) -> f64 {
+        if step < self.warmup_steps {
+            return base * (step + 1) as f64 / self.warmup_steps as f64;
+        }
+        match &mut self.after {
+            Some(after) => after.learning_rate(base, step - self.warmup_steps, epoch),
+            None => base,
+        }
+    }
+
+    fn observe(&mut self, validation_loss: f64) {
+        if let Some(after) = &mut self.after {
+            after.observe(validation_loss);
+        }
+    }
+}
+
+/// One-cycle policy: cosine ramp from `max / div_factor` up to `max` over the first `pct_start`
+/// of `total_steps`, then cosine decay down to `max / final_div_factor`.
+#[derive(Debug, Clone)]
+pub struct OneCycle {
+    pub max: f64,
+    pub total_steps: usize,
+    pub pct_start: f64,
+    pub div_factor: f64,
+    pub final_div_factor: f64,
+}
+
+impl OneCycle {
+    pub fn new(max: f64, total_steps: usize) -> OneCycle {
+        OneCycle {
+            max,
+            total_steps,
+            pct_start: 0.3,
+            div_factor: 25.0,
+            final_div_factor: 1e4,
+        }
+    }
+}
+
+impl LrScheduler for OneCycle {
+    fn name(&self) -> &'static str {
+        "one_cycle"
+    }
+
+    fn learning_rate(&mut self, _base: f64, step: usize, _epoch: usize) -> f64 {
+        let anneal = |from: f64, to: f64, progress: f64| {
+            to + 0.5 * (from - to) * (1.0 + (PI * progress.min(1.0)).cos())
+        };
+        let initial = self.max / self.div_factor;
+        let warmup_steps = (self.pct_start * self.total_steps as f64).max(1.0);
+        let step = step as f64;
+        if step < warmup_steps {
+            anneal(initial, self.max, step / warmup_steps)
+        } else {
+            let decay_steps = (self.total_steps as f64 - warmup_steps).max(1.0);
+            anneal(
+                self.max,
+                initial / self.final_div_factor,
+                (step - warmup_steps) / decay_steps,
+            )
+        }
+    }
+}
+
+/// Multiplies the learning rate by `factor` whenever the validation loss has not improved by more
+/// than `threshold` for `patience` epochs, down to `min`.
+#[derive(Debug, Clone)]
+pub struct ReduceOnPlateau {
+    pub factor: f64,
+    pub patience: usize,
+    pub threshold: f64,
+    pub min: f64,
+    best: f64,
+    bad_epochs: usize,
+    scale: f64,
+}
+
+impl ReduceOnPlateau {
+    pub fn new(factor: f64, patience: usize) -> ReduceOnPlateau {
+        assert!(factor > 0.0 && factor < 1.0);
+        ReduceOnPlateau {
+            factor,
+            patience,
+            threshold: 1e-4,
+            min: 0.0,
+            best: f64::INFINITY,
+            bad_epochs: 0,
+            scale: 1.0,
+        }
+    }
+
+    pub fn min(mut self, min: f64) -> Self {
+        self.min = min;
+        self
+    }
+}
+
+impl LrScheduler for ReduceOnPlateau {
+    fn name(&self) -> &'static str {
+        "reduce_on_plateau"
+    }
+
+    fn learning_rate(&mut self, base: f64, _step: usize, _epoch: usize) -> f64 {
+        (base * self.scale).max(self.min)
+    }
+
+    fn observe(&mut self, validation_loss: f64) {
+        if validation_loss < self.best - self.threshold {
+            self.best = validation_loss;
+            self.bad_epochs = 0;
+        } else {
+            self.bad_epochs += 1;
+            if self.bad_epochs > self.patience {
+                self.scale *= self.factor;
+                self.bad_epochs = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CosineAnnealing, ExponentialDecay, LinearDecay, LinearWarmup, LrScheduler, OneCycle,
+        ReduceOnPlateau, StepDecay,
+    };
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+    use std::f64::consts::PI;
+
+    const EPSILON: f64 = 0.00001;
+
+    fn assert_close(result: f64, expected: f64) {
+        assert!(
+            (result - expected).abs() < EPSILON,
+            "{} != {}",
+            result,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_decays() {
+        let mut step = StepDecay::new(2, 0.5);
+        assert_close(step.learning_rate(1.0, 0, 1), 1.0);
+        assert_close(step.learning_rate(1.0, 0, 5), 0.25);
+        let mut exponential = ExponentialDecay::new(0.9);
+        assert_close(exponential.learning_rate(1.0, 0, 2), 0.81);
+        let mut linear = LinearDecay::new(100);
+        assert_close(linear.learning_rate(0.5, 25, 0), 0.375);
+        assert_close(linear.learning_rate(0.5, 200, 0), 0.5e-4);
+    }
+
+    #[test]
+    fn test_cosine_annealing() {
+        let mut cosine = CosineAnnealing::new(10, 0.0).multiplier(2);
+        assert_close(cosine.learning_rate(1.0, 0, 0), 1.0);
+        assert_close(cosine.learning_rate(1.0, 5, 0), 0.5);
+        // restart with a period of 20
+        assert_close(cosine.learning_rate(1.0, 10, 0), 1.0);
+        assert_close(cosine.learning_rate(1.0, 20, 0), 0.5);
+        assert_close(cosine.learning_rate(1.0, 30, 0), 1.0);
+        assert_close(
+            cosine.learning_rate(1.0, 29, 0),
+            0.5 * (1.0 + (PI * 19.0 / 20.0).cos()),
+        );
+
+        let mut cosine = CosineAnnealing::new(10, 0.0).multiplier(3);
+        // restarts at 10, 40, 130, ...
+        for restart in [10, 40, 130, 10 * (3_usize.pow(20) - 1) / 2] {
+            assert_close(cosine.learning_rate(1.0, restart, 0), 1.0);
+        }
+        assert_close(cosine.learning_rate(1.0, 25, 0), 0.5);
+
+        let mut cosine = CosineAnnealing::new(10, 0.0);
+        assert_close(cosine.learning_rate(1.0, 1_000_000_005, 0), 0.5);
+    }
+
+    #[test]
+    fn test_warmup() {
+        let mut warmup = LinearWarmup::new(4).then(StepDecay::new(1, 0.1));
+        assert_close(warmup.learning_rate(1.0, 0, 0), 0.25);
+        assert_close(warmup.learning_rate(1.0, 3, 0), 1.0);
+        assert_close(warmup.learning_rate(1.0, 10, 2), 0.01);
+    }
+
+    #[test]
+    fn test_one_cycle() {
+        let mut one_cycle = OneCycle::new(1.0, 100);
+        assert_close(one_cycle.learning_rate(0.0, 0, 0), 0.04);
+        assert_close(one_cycle.learning_rate(0.0, 30, 0), 1.0);
+        assert_close(one_cycle.learning_rate(0.0, 100, 0), 0.04e-4);
+    }
+
+    #[test]
+    fn test_reduce_on_plateau() {
+        let mut plateau = ReduceOnPlateau::new(0.5, 1);
+        for loss in [1.0, 0.9, 0.95, 0.9] {
+            plateau.observe(loss);
+        }
+        assert_close(plateau.learning_rate(1.0, 0, 0), 0.5);
+        plateau.observe(0.5);
+        assert_close(plateau.learning_rate(1.0, 0, 0), 0.5);
+    }
+
+    #[test]
+    fn test_network_schedule() {
+        let mut nn = NeuralNetwork::new(vec![Layer::random((2, 2), (-1.0, 1.0))])
+            .with_scheduler(StepDecay::new(1, 0.5));
+        let input = Vector::from(vec![0.5, -0.5]);
+        let target = Vector::from(vec![1.0, 0.0]);
+        nn.train(input.clone(), &target);
+        assert_close(nn.optimizer().learning_rate(), 0.1);
+        nn.end_epoch(None);
+        nn.train(input, &target);
+        assert_close(nn.optimizer().learning_rate(), 0.05);
+        assert_eq!(nn.steps(), 2);
+    }
+}
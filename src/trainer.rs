@@ -0,0 +1,508 @@
+use std::fmt::Debug;
+
+use rand::{thread_rng, Rng};
+
+use crate::data::{DataLoader, Dataset, InMemoryDataset, LastBatch};
+use crate::matrix::Matrix;
+use crate::NeuralNetwork;
+
+/// Losses at the end of an epoch of training.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Epoch {
+    pub index: usize,
+    /// mean loss over the batches of the epoch
+    pub train_loss: f64,
+    pub validation_loss: Option<f64>,
+}
+
+impl Epoch {
+    /// Loss that early stopping and best-model restoration follow: the validation loss if there
+    /// is a validation set, the training loss otherwise.
+    pub fn monitored_loss(&self) -> f64 {
+        self.validation_loss.unwrap_or(self.train_loss)
+    }
+}
+
+/// Hooks into the training loop of a `Trainer`, e.g. to log progress or compute metrics.
+pub trait Callback: Debug {
+    /// Called after every training step with the mean loss of the batch.
+    fn on_batch_end(&mut self, _batch: usize, _loss: f64, _nn: &NeuralNetwork) {}
+
+    /// Called after every epoch, once the validation loss is computed.
+    fn on_epoch_end(&mut self, _epoch: &Epoch, _nn: &mut NeuralNetwork) {}
+}
+
+/// Prints the losses every `every` epochs.
+#[derive(Debug, Clone)]
+pub struct Logger {
+    pub every: usize,
+}
+
+impl Logger {
+    pub fn new(every: usize) -> Logger {
+        assert!(every > 0);
+        Logger { every }
+    }
+}
+
+impl Callback for Logger {
+    fn on_epoch_end(&mut self, epoch: &Epoch, _nn: &mut NeuralNetwork) {
+        if epoch.index.is_multiple_of(self.every) {
+            match epoch.validation_loss {
+                Some(validation) => println!(
+                    "epoch {}: loss {}, validation loss {}",
+                    epoch.index, epoch.train_loss, validation
+                ),
+                None => println!("epoch {}: loss {}", epoch.index, epoch.train_loss),
+            }
+        }
+    }
+}
+
+/// Record of a call to `Trainer::fit`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    pub epochs: Vec<Epoch>,
+    /// index of the epoch with the lowest monitored loss
+    pub best_epoch: Option<usize>,
+    pub stopped_early: bool,
+}
+
+impl History {
+    pub fn train_loss(&self) -> Vec<f64> {
+        self.epochs.iter().map(|e| e.train_loss).collect()
+    }
+
+    /// Validation losses of every epoch, empty without a validation set.
+    pub fn validation_loss(&self) -> Vec<f64> {
+        self.epochs
+            .iter()
+            .filter_map(|e| e.validation_loss)
+            .collect()
+    }
+
+    pub fn best(&self) -> Option<&Epoch> {
+        self.best_epoch.map(|i| &self.epochs[i])
+    }
+}
+
+/// Runs epochs of mini-batch training over a dataset, which a `DataLoader` shuffles and batches.
+#[derive(Debug)]
+pub struct Trainer {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub shuffle: bool,
+    /// seed of the shuffling order, random if `None`
+    pub seed: Option<u64>,
+    pub last_batch: LastBatch,
+    /// number of epochs without improvement after which training stops
+    patience: Option<usize>,
+    /// decrease of the monitored loss that counts as an improvement
+    min_delta: f64,
+    restore_best: bool,
+    callbacks: Vec<Box<dyn Callback>>,
+}
+
+impl Trainer {
+    pub fn new(epochs: usize) -> Trainer {
+        Trainer {
+            epochs,
+            batch_size: 32,
+            shuffle: true,
+            seed: None,
+            last_batch: LastBatch::Keep,
+            patience: None,
+            min_delta: 0.0,
+            restore_best: false,
+            callbacks: vec![],
+        }
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0);
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Keeps the samples in order instead of shuffling them every epoch.
+    pub fn no_shuffle(mut self) -> Self {
+        self.shuffle = false;
+        self
+    }
+
+    /// Shuffles the samples in an order determined by `seed`, for reproducible training.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Skips the last batch of every epoch if it is smaller than the others.
+    pub fn drop_last(mut self) -> Self {
+        self.last_batch = LastBatch::Drop;
+        self
+    }
+
+    /// Stops training after `patience` epochs without improvement of the monitored loss.
+    pub fn early_stopping(mut self, patience: usize) -> Self {
+        self.patience = Some(patience);
+        self
+    }
+
+    pub fn min_delta(mut self, min_delta: f64) -> Self {
+        self.min_delta = min_delta;
+        self
+    }
+
+    /// Restores the parameters and buffers, e.g. the running statistics of `BatchNorm`, of the
+    /// epoch with the lowest monitored loss once training ends.
+    pub fn restore_best(mut self) -> Self {
+        self.restore_best = true;
+        self
+    }
+
+    pub fn with_callback<C>(mut self, callback: C) -> Self
+    where
+        C: Callback + 'static,
+    {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Trains the network on `inputs` and `targets` with one sample per row, evaluating it on the
+    /// `validation` set, if any, after every epoch.
+    pub fn fit(
+        &mut self,
+        nn: &mut NeuralNetwork,
+        inputs: &Matrix,
+        targets: &Matrix,
+        validation: Option<(&Matrix, &Matrix)>,
+    ) -> History {
+        assert!(inputs.dims[0] == targets.dims[0]);
+        let dataset = InMemoryDataset::from_matrices(inputs, targets);
+        self.fit_dataset(nn, &dataset, validation)
+    }
+
+    /// Like `fit`, on the samples of any dataset, which a `DataLoader` batches.
+    ///
+    /// A last batch of a single sample is merged into the previous batch while the network is in
+    /// training mode, since e.g. `BatchNorm` cannot train on one sample.
+    pub fn fit_dataset<D>(
+        &mut self,
+        nn: &mut NeuralNetwork,
+        dataset: &D,
+        validation: Option<(&Matrix, &Matrix)>,
+    ) -> History
+    where
+        D: Dataset + ?Sized,
+    {
+        let mut loader = DataLoader::new(dataset, self.batch_size);
+        loader.last_batch = self.last_batch;
+        if self.shuffle {
+            loader = loader.shuffle(self.seed.unwrap_or_else(|| thread_rng().gen()));
+        }
+        assert!(
+            loader.num_batches() > 0,
+            "Trainer::fit: no batch to train on"
+        );
+        let mut history = History::default();
+        let (mut best_loss, mut best_state, mut waited) = (f64::INFINITY, None, 0);
+        let mut batch = 0;
+        for index in 0..self.epochs {
+            let (mut loss_sum, mut samples) = (0.0, 0);
+            let mut batches = loader.epoch().peekable();
+            while let Some((mut inputs, mut targets)) = batches.next() {
+                let single = batches.peek().is_some_and(|(next, _)| next.dims[0] == 1);
+                if single && nn.is_training() {
+                    let (last_inputs, last_targets) = batches.next().unwrap();
+                    inputs = Matrix::from_rows(&[inputs.rows(), last_inputs.rows()].concat());
+                    targets = Matrix::from_rows(&[targets.rows(), last_targets.rows()].concat());
+                }
+                let loss = nn.train_batch(&inputs, &targets);
+                loss_sum += loss * inputs.dims[0] as f64;
+                samples += inputs.dims[0];
+                for callback in &mut self.callbacks {
+                    callback.on_batch_end(batch, loss, nn);
+                }
+                batch += 1;
+            }
+            let epoch = Epoch {
+                index,
+                train_loss: loss_sum / samples as f64,
+                validation_loss: validation.map(|(inputs, targets)| evaluate(nn, inputs, targets)),
+            };
+            nn.end_epoch(epoch.validation_loss);
+            for callback in &mut self.callbacks {
+                callback.on_epoch_end(&epoch, nn);
+            }
+            history.epochs.push(epoch);
+
+            if epoch.monitored_loss() < best_loss - self.min_delta {
+                best_loss = epoch.monitored_loss();
+                history.best_epoch = Some(index);
+                waited = 0;
+                if self.restore_best {
+                    best_state = Some(snapshot(nn));
+                }
+            } else {
+                waited += 1;
+                if self.patience.is_some_and(|patience| waited >= patience) {
+                    history.stopped_early = true;
+                    break;
+                }
+            }
+        }
+        if let Some(state) = best_state {
+            restore(nn, &state);
+        }
+        history
+    }
+}
+
+/// Computes the loss of the network on a dataset in inference mode.
+fn evaluate(nn: &mut NeuralNetwork, inputs: &Matrix, targets: &Matrix) -> f64 {
+    let training = nn.is_training();
+    nn.set_training(false);
+    let output = nn.forward_batch(inputs);
+    nn.set_training(training);
+    nn.loss_batch(&output, targets)
+}
+
+/// Copies the parameters and then the buffers of every layer.
+fn snapshot(nn: &NeuralNetwork) -> Vec<Vec<f64>> {
+    nn.layers
+        .iter()
+        .flat_map(|layer| {
+            let buffers = layer.buffers().into_iter().map(|(_, values)| values);
+            layer.parameters().into_iter().chain(buffers)
+        })
+        .map(|values| values.to_vec())
+        .collect()
+}
+
+fn restore(nn: &mut NeuralNetwork, state: &[Vec<f64>]) {
+    let mut saved = state.iter();
+    for layer in &mut nn.layers {
+        for parameter in layer.parameters_mut() {
+            parameter.value.copy_from_slice(saved.next().unwrap());
+        }
+        for (_, buffer) in layer.buffers_mut() {
+            buffer.copy_from_slice(saved.next().unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Callback, Epoch, Trainer};
+    use crate::activation::TANH;
+    use crate::data::GeneratedDataset;
+    use crate::matrix::Matrix;
+    use crate::normalization::BatchNorm;
+    use crate::optimizer::Sgd;
+    use crate::vector::Vector;
+    use crate::{Layer, NeuralNetwork};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const EPSILON: f64 = 0.00001;
+
+    /// Counts the calls of every hook.
+    #[derive(Debug, Default)]
+    struct Counter {
+        calls: Rc<RefCell<(usize, usize)>>,
+    }
+
+    impl Callback for Counter {
+        fn on_batch_end(&mut self, _batch: usize, _loss: f64, _nn: &NeuralNetwork) {
+            self.calls.borrow_mut().0 += 1;
+        }
+
+        fn on_epoch_end(&mut self, _epoch: &Epoch, _nn: &mut NeuralNetwork) {
+            self.calls.borrow_mut().1 += 1;
+        }
+    }
+
+    /// Records the buffers of every layer at the end of every epoch.
+    #[derive(Debug, Default)]
+    struct Buffers {
+        epochs: Rc<RefCell<Vec<Vec<Vec<f64>>>>>,
+    }
+
+    impl Callback for Buffers {
+        fn on_epoch_end(&mut self, _epoch: &Epoch, nn: &mut NeuralNetwork) {
+            let buffers = nn
+                .layers
+                .iter()
+                .flat_map(|layer| layer.buffers())
+                .map(|(_, values)| values.to_vec())
+                .collect();
+            self.epochs.borrow_mut().push(buffers);
+        }
+    }
+
+    /// Samples of the xor function.
+    fn xor() -> (Matrix, Matrix) {
+        let inputs = Matrix::from(vec![
+            vec![0.0, 0.0],
+            vec![0.0, 1.0],
+            vec![1.0, 0.0],
+            vec![1.0, 1.0],
+        ]);
+        let targets = Matrix::from(vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![0.0, 1.0],
+            vec![1.0, 0.0],
+        ]);
+        (inputs, targets)
+    }
+
+    /// Layer with weights drawn from `rng`, to keep the tests deterministic.
+    fn layer(dims: (usize, usize), rng: &mut StdRng) -> Layer {
+        let weights = (0..dims.0 * dims.1)
+            .map(|_| rng.gen_range(-1.0..1.0))
+            .collect::<Vec<f64>>();
+        Layer::new(
+            Matrix::from_vector(weights.into(), dims),
+            Vector::zero(dims.1),
+        )
+    }
+
+    fn batch_norm_network(learning_rate: f64) -> NeuralNetwork {
+        let mut rng = StdRng::seed_from_u64(3);
+        NeuralNetwork::from_modules(vec![
+            Box::new(layer((2, 8), &mut rng)),
+            Box::new(BatchNorm::new(8)),
+            Box::new(layer((8, 2), &mut rng).with_activation(TANH)),
+        ])
+        .with_optimizer(Sgd::new(learning_rate))
+    }
+
+    fn network(learning_rate: f64) -> NeuralNetwork {
+        let mut rng = StdRng::seed_from_u64(3);
+        NeuralNetwork::new(vec![
+            layer((2, 8), &mut rng).with_activation(TANH),
+            layer((8, 2), &mut rng),
+        ])
+        .with_optimizer(Sgd::new(learning_rate))
+    }
+
+    #[test]
+    fn test_fit() {
+        let (inputs, targets) = xor();
+        let counter = Counter::default();
+        let calls = counter.calls.clone();
+        let mut nn = network(0.5);
+        let history = Trainer::new(500)
+            .batch_size(2)
+            .seed(7)
+            .with_callback(counter)
+            .fit(&mut nn, &inputs, &targets, Some((&inputs, &targets)));
+        assert_eq!(*calls.borrow(), (1000, 500));
+        assert_eq!(history.train_loss().len(), 500);
+        assert_eq!(history.validation_loss().len(), 500);
+        assert!(!history.stopped_early);
+        assert!(history.validation_loss()[499] < 0.1);
+        assert_eq!(nn.epochs(), 500);
+
+        // the same seed trains the same network the same way
+        let mut other = network(0.5);
+        let again = Trainer::new(500).batch_size(2).seed(7).fit(
+            &mut other,
+            &inputs,
+            &targets,
+            Some((&inputs, &targets)),
+        );
+        assert_eq!(again, history);
+    }
+
+    #[test]
+    fn test_early_stopping() {
+        let (inputs, targets) = xor();
+        let history = Trainer::new(100).early_stopping(3).no_shuffle().fit(
+            &mut network(0.0),
+            &inputs,
+            &targets,
+            None,
+        );
+        assert!(history.stopped_early);
+        assert_eq!(history.epochs.len(), 4);
+        assert_eq!(history.best_epoch, Some(0));
+    }
+
+    #[test]
+    fn test_restore_best() {
+        // a learning rate this large makes the loss jump around
+        let (inputs, targets) = xor();
+        let mut nn = network(20.0);
+        let history = Trainer::new(30).restore_best().no_shuffle().fit(
+            &mut nn,
+            &inputs,
+            &targets,
+            Some((&inputs, &targets)),
+        );
+        let best = history.best().unwrap();
+        assert!(history
+            .validation_loss()
+            .iter()
+            .all(|loss| *loss >= best.validation_loss.unwrap()));
+        let output = nn.forward_batch(&inputs);
+        let loss = nn.loss_batch(&output, &targets);
+        assert!((loss - best.validation_loss.unwrap()).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_fit_dataset() {
+        let (inputs, targets) = xor();
+        let dataset = GeneratedDataset::new(5, |i| (inputs.row(i % 4), targets.row(i % 4)));
+        let counter = Counter::default();
+        let calls = counter.calls.clone();
+        let history = Trainer::new(3)
+            .batch_size(2)
+            .drop_last()
+            .with_callback(counter)
+            .fit_dataset(&mut network(0.1), &dataset, None);
+        assert_eq!(*calls.borrow(), (6, 3));
+        assert!(history.train_loss().iter().all(|loss| loss.is_finite()));
+    }
+
+    #[test]
+    fn test_restore_best_buffers() {
+        let (inputs, targets) = xor();
+        let recorder = Buffers::default();
+        let epochs = recorder.epochs.clone();
+        let mut nn = batch_norm_network(20.0);
+        let history = Trainer::new(30)
+            .restore_best()
+            .seed(1)
+            .batch_size(2)
+            .with_callback(recorder)
+            .fit(&mut nn, &inputs, &targets, Some((&inputs, &targets)));
+        let best = history.best_epoch.unwrap();
+        assert!(best < 29);
+        let buffers = nn
+            .layers
+            .iter()
+            .flat_map(|layer| layer.buffers())
+            .map(|(_, values)| values.to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(buffers, epochs.borrow()[best]);
+    }
+
+    #[test]
+    fn test_single_sample_last_batch() {
+        // 5 samples in batches of 2 leave a single one, which BatchNorm cannot train on
+        let (inputs, targets) = xor();
+        let dataset = GeneratedDataset::new(5, |i| (inputs.row(i % 4), targets.row(i % 4)));
+        let counter = Counter::default();
+        let calls = counter.calls.clone();
+        let history = Trainer::new(3)
+            .batch_size(2)
+            .with_callback(counter)
+            .fit_dataset(&mut batch_norm_network(0.1), &dataset, None);
+        assert_eq!(*calls.borrow(), (6, 3));
+        assert!(history.train_loss().iter().all(|loss| loss.is_finite()));
+    }
+}
@@ -3,6 +3,14 @@ use rand::Rng;
 use crate::ops::Scale;
 use std::{ops::Index, slice::Iter};
 
+/// Smallest probability used by the losses and metrics, to keep logarithms and inverses finite.
+const MIN_PROBABILITY: f64 = 1e-12;
+
+/// Keeps probabilities away from 0 and 1 so that their logarithms and inverses stay finite.
+pub(crate) fn clamp_probability(p: f64) -> f64 {
+    p.clamp(MIN_PROBABILITY, 1.0 - MIN_PROBABILITY)
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Vector {
     pub size: usize,
@@ -30,6 +38,10 @@ impl Vector {
         self.size
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
     pub fn softmax(&self) -> Vector {
         let exp_sum = self.data.iter().map(|x| x.exp()).sum::<f64>();
         Vector {
@@ -48,6 +60,28 @@ impl Vector {
             .sum::<f64>()
     }
 
+    pub fn log_softmax(&self) -> Vector {
+        let max = self.data.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let log_sum = self.data.iter().map(|x| (x - max).exp()).sum::<f64>().ln() + max;
+        Vector {
+            size: self.size,
+            data: self.data.iter().map(|x| x - log_sum).collect(),
+        }
+    }
+
+    pub fn binary_cross_entropy_loss(&self, expected: &Vector) -> f64 {
+        let binary_cross_entropy_fn = |expected: f64, actual: f64| {
+            let actual = clamp_probability(actual);
+            -expected * actual.ln() - (1.0 - expected) * (1.0 - actual).ln()
+        };
+        expected
+            .data
+            .iter()
+            .zip(self.data.iter())
+            .map(|(e, a)| binary_cross_entropy_fn(*e, *a))
+            .sum::<f64>()
+    }
+
     pub fn subtract(&self, vec2: &Vector) -> Vector {
         assert!(self.size == vec2.size);
         let res = self
@@ -79,8 +113,8 @@ impl Vector {
             .sum::<f64>()
     }
 
-    pub fn iter(&self) -> Iter<f64> {
-        return self.data.iter();
+    pub fn iter(&self) -> Iter<'_, f64> {
+        self.data.iter()
     }
 }
 
@@ -209,4 +243,22 @@ mod tests {
         let expected = 0.510825623765990;
         assert!((result - expected).abs() < EPSILON);
     }
+
+    #[test]
+    fn test_log_softmax() {
+        let vec = Vector::from(vec![-1.0, 0.0, 0.5]);
+        let result = vec.log_softmax();
+        for (r, e) in result.iter().zip(vec.softmax().iter()) {
+            assert!((r - e.ln()).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_binary_cross_entropy_loss() {
+        let vec = Vector::from(vec![0.1, 0.8]);
+        let target = Vector::from(vec![0.0, 1.0]);
+        let result = vec.binary_cross_entropy_loss(&target);
+        let expected = 0.328504066972036;
+        assert!((result - expected).abs() < EPSILON);
+    }
 }